@@ -0,0 +1,44 @@
+//! Runs every script in `benches/lox` a few times and reports how long it took.
+//!
+//! ```text
+//! cargo bench
+//! cargo bench --features nan_boxing
+//! cargo bench -- fib
+//! ```
+
+use rslox::vm::{InterpretResult, VM};
+
+use std::time::{Duration, Instant};
+
+
+const RUNS: usize = 5;
+
+const SCRIPTS: &[(&str, &str)] = &[
+    ("fib",     include_str!("lox/fib.lox")),
+    ("loops",   include_str!("lox/loops.lox")),
+    ("strings", include_str!("lox/strings.lox")),
+];
+
+fn main() {
+    // `cargo bench` passes `--bench`, anything else filters the scripts by name.
+    let filters: Vec<String> = std::env::args().skip(1).filter(|arg| !arg.starts_with("--")).collect();
+    println!("{:<10} {:>10} {:>10} {:>10}", "script", "min ms", "median ms", "max ms");
+    for (name, source) in SCRIPTS {
+        if !filters.is_empty() && !filters.iter().any(|filter| name.contains(filter.as_str())) {
+            continue;
+        }
+        let mut times: Vec<Duration> = (0..RUNS).map(|_| run(name, source)).collect();
+        times.sort();
+        let ms = |time: Duration| time.as_secs_f64() * 1000.0;
+        println!("{name:<10} {:>10.1} {:>10.1} {:>10.1}", ms(times[0]), ms(times[RUNS / 2]), ms(times[RUNS - 1]));
+    }
+}
+
+fn run(name: &str, source: &str) -> Duration {
+    let mut vm = VM::new();
+    let started = Instant::now();
+    let result = vm.interpret_named(name, source);
+    let elapsed = started.elapsed();
+    assert_eq!(result, InterpretResult::Ok, "{name} failed");
+    elapsed
+}
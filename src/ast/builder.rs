@@ -0,0 +1,554 @@
+use crate::ast::{BinaryOp, Expr, ExprKind, Function, Identifier, Literal, LogicalOp, Script, Stmt, StmtKind, UnaryOp, Variable};
+use crate::diagnostic::{Diagnostic, Severity, Span};
+use crate::scanner::{Scanner, Token, TokenType};
+
+use std::cell::{Cell, RefCell};
+use std::mem;
+use std::rc::Rc;
+
+
+#[repr(u8)]
+#[derive(PartialEq, PartialOrd)]
+enum Precedence {
+    None,
+    Assignment,  // =
+    Or,          // or
+    And,         // and
+    Equality,    // == !=
+    Comparision, // < > <= >=
+    Term,        // + 1
+    Factor,      // * /
+    Unary,       // ! -
+    Call,        // . ()
+    #[allow(dead_code)] // Only reached by adding to `Call`.
+    Primary,
+}
+
+impl std::ops::Add<u8> for Precedence {
+    type Output = Self;
+
+    fn add(self, rhs: u8) -> Self::Output {
+        unsafe {
+            mem::transmute((self as u8 + rhs) % 11)
+        }
+    }
+}
+
+/// Parses a script into statements with a Pratt parser, reporting syntax
+/// errors and recovering from them at the next statement. Which variable a
+/// name refers to is left to `compiler::Resolver`.
+#[derive(Default)]
+pub struct Builder<'a> {
+    scanner:    Scanner<'a>,
+    current:    Token<'a>,
+    previous:   Token<'a>,
+    had_error:  bool,
+    panic_mode: bool,
+    diagnostics:    Vec<Diagnostic>,
+    /// How many blocks, `for` loops and functions enclose the current token.
+    scope_depth:    usize,
+    /// Accept an expression without its `;` at the end of the source.
+    repl:           bool,
+}
+
+fn infix_precedence(token_type: TokenType) -> Precedence {
+    use TokenType::*;
+    match token_type {
+        LeftParen                                       => Precedence::Call,
+        Minus | Plus                                    => Precedence::Term,
+        Slash | Star                                    => Precedence::Factor,
+        BangEqual | EqualEqual                          => Precedence::Equality,
+        Greater | GreaterEqual | Less | LessEqual       => Precedence::Comparision,
+        And                                             => Precedence::And,
+        Or                                              => Precedence::Or,
+        _                                               => Precedence::None,
+    }
+}
+
+impl<'a> Builder<'a> {
+    pub fn new(source: &'a str) -> Self {
+        Self {
+            scanner: Scanner::new(source),
+            ..Default::default()
+        }
+    }
+
+    /// Lets the source end with an expression statement missing its `;`, as
+    /// typed at the REPL prompt.
+    pub fn set_repl(&mut self, repl: bool) {
+        self.repl = repl;
+    }
+
+    /// The syntax errors found, in the order of the source.
+    pub fn diagnostics(&self) -> &[Diagnostic] {
+        &self.diagnostics
+    }
+
+    pub fn had_error(&self) -> bool {
+        self.had_error
+    }
+
+    /// Keeps the comments of the source, for `comments`.
+    pub fn keep_comments(&mut self) {
+        self.scanner.keep_comments();
+    }
+
+    /// The comments of the source scanned so far, see `Scanner::comments`.
+    pub fn comments(&self) -> &[Span] {
+        self.scanner.comments()
+    }
+
+    /// Builds the whole script. After an error the statements it was found in
+    /// are left out or incomplete.
+    pub fn build(&mut self) -> Script {
+        let mut statements = Vec::new();
+        self.advance();
+        while !self.r#match(TokenType::EOF) {
+            statements.push(self.declaration());
+        }
+        Script { statements, end: self.previous.span() }
+    }
+
+    fn declaration(&mut self) -> Stmt {
+        let start = self.current.span();
+        let statement = if self.r#match(TokenType::Fun) {
+            let kind = self.fun_declaration();
+            self.stmt(kind, start)
+        } else if self.r#match(TokenType::Var) {
+            let kind = self.var_declaration();
+            self.stmt(kind, start)
+        } else {
+            self.statement()
+        };
+        if self.panic_mode {
+            self.synchronize();
+        }
+        statement
+    }
+
+    /// A statement from `start` to the previous token.
+    fn stmt(&self, kind: StmtKind, start: Span) -> Stmt {
+        Stmt { kind, start, end: self.previous.span() }
+    }
+
+    fn fun_declaration(&mut self) -> StmtKind {
+        let name = self.parse_variable("Expect function name.");
+        StmtKind::Function(Rc::new(self.function(name)))
+    }
+
+    fn var_declaration(&mut self) -> StmtKind {
+        let name = self.parse_variable("Expect variable name.");
+        let initializer = if self.r#match(TokenType::Equal) {
+            Some(self.expression())
+        } else {
+            None
+        };
+        self.consume(TokenType::Semicolon, "Expect ';' after variable declaration.");
+        StmtKind::Var { name, initializer, captured: Cell::new(false) }
+    }
+
+    fn parse_variable(&mut self, error_message: &str) -> Identifier {
+        self.consume(TokenType::Idenitifier, error_message);
+        self.identifier(&self.previous.clone())
+    }
+
+    fn identifier(&self, token: &Token) -> Identifier {
+        Identifier {
+            name: token.value.to_string(),
+            span: token.span(),
+        }
+    }
+
+    fn function(&mut self, name: Identifier) -> Function {
+        self.scope_depth += 1;
+        let mut params = Vec::new();
+        self.consume(TokenType::LeftParen, "Expect '(' after function name.");
+        if !self.check(TokenType::RightParen) {
+            loop {
+                if params.len() == 255 {
+                    self.error_at_current("Can't have more than 255 parameters.");
+                }
+                let param = self.parse_variable("Expect parameter name.");
+                if self.previous.token_type == TokenType::Idenitifier {
+                    params.push(param);
+                }
+                if self.panic_mode {
+                    self.recover_to(&[TokenType::Comma, TokenType::RightParen]);
+                } else if !self.check(TokenType::Comma) && !self.check(TokenType::RightParen) {
+                    self.error_at_current("Expect ')' after parameters.");
+                    self.recover_to(&[TokenType::Comma, TokenType::RightParen]);
+                }
+                if !self.r#match(TokenType::Comma) {
+                    break;
+                }
+            }
+        }
+        self.consume(TokenType::RightParen, "Expect ')' after parameters.");
+        // Without a brace there is no body to parse, and parsing the following
+        // statements as the body would end the function at an unrelated brace.
+        let mut left_brace = Span::default();
+        let body = if self.r#match(TokenType::LeftBrace) {
+            left_brace = self.previous.span();
+            self.block()
+        } else {
+            self.error_at_current("Expect '{' before function body.");
+            Vec::new()
+        };
+        self.scope_depth -= 1;
+        Function {
+            name,
+            params,
+            left_brace,
+            body,
+            upvalues: RefCell::new(Vec::new()),
+            captured: Cell::new(false),
+        }
+    }
+
+    fn synchronize(&mut self) {
+        use TokenType::*;
+        self.panic_mode = false;
+        while self.current.token_type != EOF {
+            if self.previous.token_type == Semicolon {
+                return;
+            }
+            match self.current.token_type {
+                Class | Fun | Var | For | If | While | Print | Return => return,
+                // Let the enclosing block end where it should, so the statements
+                // after it aren't parsed as part of the wrong scope.
+                RightBrace if self.scope_depth > 0 => return,
+                _ => ()
+            }
+            self.advance();
+        }
+    }
+
+    /// Skips to the next of `stops` outside of nested parentheses and braces,
+    /// so parsing can resume at the next item of a list rather than at the
+    /// next statement. Leaves panic mode only if one of `stops` was found.
+    fn recover_to(&mut self, stops: &[TokenType]) {
+        use TokenType::*;
+        let mut depth = 0;
+        loop {
+            match self.current.token_type {
+                EOF => return,
+                token_type if depth == 0 && stops.contains(&token_type) => break,
+                Semicolon if depth == 0 => return,
+                LeftParen | LeftBrace => depth += 1,
+                RightParen | RightBrace => {
+                    if depth == 0 {
+                        return;
+                    }
+                    depth -= 1;
+                }
+                _ => (),
+            }
+            self.advance();
+        }
+        self.panic_mode = false;
+    }
+
+    fn statement(&mut self) -> Stmt {
+        let start = self.current.span();
+        let kind = if self.r#match(TokenType::Print) {
+            let value = self.expression();
+            self.consume(TokenType::Semicolon, "Expect ';' after value.");
+            StmtKind::Print(value)
+        } else if self.r#match(TokenType::LeftBrace) {
+            self.scope_depth += 1;
+            let statements = self.block();
+            self.scope_depth -= 1;
+            StmtKind::Block(statements)
+        } else if self.r#match(TokenType::If) {
+            self.if_statement()
+        } else if self.r#match(TokenType::Return) {
+            self.return_statement()
+        } else if self.r#match(TokenType::While) {
+            self.while_statement()
+        } else if self.r#match(TokenType::For) {
+            self.for_statement()
+        } else {
+            self.expression_statement()
+        };
+        self.stmt(kind, start)
+    }
+
+    fn for_statement(&mut self) -> StmtKind {
+        self.scope_depth += 1;
+        self.consume(TokenType::LeftParen, "Expect '(' after 'for'.");
+        let start = self.current.span();
+        let initializer = if self.r#match(TokenType::Semicolon) {
+            None
+        } else if self.r#match(TokenType::Var) {
+            let kind = self.var_declaration();
+            Some(Box::new(self.stmt(kind, start)))
+        } else {
+            let kind = self.expression_statement();
+            Some(Box::new(self.stmt(kind, start)))
+        };
+        let mut condition = None;
+        if !self.r#match(TokenType::Semicolon) {
+            condition = Some(self.expression());
+            self.consume(TokenType::Semicolon, "Expect ';' after loop condition.");
+        }
+        let semicolon = self.previous.span();
+        let mut increment = None;
+        if !self.r#match(TokenType::RightParen) {
+            increment = Some(self.expression());
+            self.consume(TokenType::RightParen, "Expect ')' after for clauses.");
+        }
+        let right_paren = self.previous.span();
+        let body = Box::new(self.statement());
+        self.scope_depth -= 1;
+        StmtKind::For { initializer, condition, semicolon, increment, right_paren, body }
+    }
+
+    fn while_statement(&mut self) -> StmtKind {
+        self.consume(TokenType::LeftParen, "Expect '(' after 'while'.");
+        let condition = self.expression();
+        self.consume(TokenType::RightParen, "Expect ')' after condition.");
+        let right_paren = self.previous.span();
+        let body = Box::new(self.statement());
+        StmtKind::While { condition, right_paren, body }
+    }
+
+    fn if_statement(&mut self) -> StmtKind {
+        self.consume(TokenType::LeftParen, "Expect '(' after 'if'.");
+        let condition = self.expression();
+        self.consume(TokenType::RightParen, "Expect ')' after condition.");
+        let right_paren = self.previous.span();
+        let then_branch = Box::new(self.statement());
+        let else_branch = if self.r#match(TokenType::Else) {
+            Some(Box::new(self.statement()))
+        } else {
+            None
+        };
+        StmtKind::If { condition, right_paren, then_branch, else_branch }
+    }
+
+    fn return_statement(&mut self) -> StmtKind {
+        if self.r#match(TokenType::Semicolon) {
+            return StmtKind::Return(None);
+        }
+        let value = self.expression();
+        self.consume(TokenType::Semicolon, "Expect ';' after return value.");
+        StmtKind::Return(Some(value))
+    }
+
+    fn expression_statement(&mut self) -> StmtKind {
+        let expression = self.expression();
+        if self.repl && self.scope_depth == 0 && self.check(TokenType::EOF) {
+            return StmtKind::Expression(expression);
+        }
+        self.consume(TokenType::Semicolon, "Expect ';' after expression.");
+        StmtKind::Expression(expression)
+    }
+
+    fn block(&mut self) -> Vec<Stmt> {
+        let mut statements = Vec::new();
+        while !self.check(TokenType::RightBrace) && !self.check(TokenType::EOF) {
+            statements.push(self.declaration());
+        }
+        if self.check(TokenType::EOF) {
+            // The block runs to the end of the source, as does the panic.
+            self.error_at_current("Expect '}' after block.");
+            self.advance();
+        } else {
+            self.consume(TokenType::RightBrace, "Expect '}' after block.");
+        }
+        statements
+    }
+
+    fn expression(&mut self) -> Expr {
+        self.parse_precedence(Precedence::Assignment)
+    }
+
+    /// An expression spanning from `start` to the previous token.
+    fn expr(&self, kind: ExprKind, start: Span) -> Expr {
+        Expr {
+            kind,
+            span: start.to(self.previous.span()),
+            end: self.previous.span(),
+        }
+    }
+
+    fn parse_precedence(&mut self, precedence: Precedence) -> Expr {
+        self.advance();
+        let start = self.previous.span();
+        let can_assign = precedence <= Precedence::Assignment;
+        let mut expr = match self.prefix(can_assign) {
+            Some(expr) => expr,
+            None => {
+                self.error("Expect expression.");
+                return self.expr(ExprKind::Literal(Literal::Nil), start);
+            }
+        };
+        while precedence <= infix_precedence(self.current.token_type) {
+            self.advance();
+            expr = self.infix(expr, start);
+        }
+        if can_assign && self.r#match(TokenType::Equal) {
+            self.error("Invalid assignment target.");
+        }
+        expr
+    }
+
+    fn prefix(&mut self, can_assign: bool) -> Option<Expr> {
+        use TokenType::*;
+        let token = self.previous.clone();
+        let start = token.span();
+        let kind = match token.token_type {
+            LeftParen => {
+                let inner = self.expression();
+                self.consume(RightParen, "Expect ')' after expression.");
+                ExprKind::Grouping(Box::new(inner))
+            }
+            Minus | Bang => {
+                let operand = self.parse_precedence(Precedence::Unary);
+                ExprKind::Unary {
+                    operator: if token.token_type == Minus { UnaryOp::Negate } else { UnaryOp::Not },
+                    operator_span: start,
+                    operand: Box::new(operand),
+                }
+            }
+            Number      => ExprKind::Literal(Literal::Number(token.value.parse().unwrap())),
+            String      => ExprKind::Literal(Literal::String(token.value[1..token.value.len() - 1].to_string())),
+            Nil         => ExprKind::Literal(Literal::Nil),
+            True        => ExprKind::Literal(Literal::Bool(true)),
+            False       => ExprKind::Literal(Literal::Bool(false)),
+            Idenitifier => return Some(self.variable(token, can_assign)),
+            _           => return None,
+        };
+        Some(self.expr(kind, start))
+    }
+
+    fn infix(&mut self, left: Expr, start: Span) -> Expr {
+        use TokenType::*;
+        let operator = self.previous.token_type;
+        let operator_span = self.previous.span();
+        let kind = match operator {
+            LeftParen => {
+                let arguments = self.argument_list();
+                ExprKind::Call(Box::new(left), arguments)
+            }
+            And | Or => {
+                let right = self.parse_precedence(infix_precedence(operator));
+                ExprKind::Logical {
+                    operator: if operator == And { LogicalOp::And } else { LogicalOp::Or },
+                    operator_span,
+                    left: Box::new(left),
+                    right: Box::new(right),
+                }
+            }
+            _ => {
+                let right = self.parse_precedence(infix_precedence(operator) + 1);
+                let operator = match operator {
+                    BangEqual       => BinaryOp::NotEqual,
+                    EqualEqual      => BinaryOp::Equal,
+                    Greater         => BinaryOp::Greater,
+                    GreaterEqual    => BinaryOp::GreaterEqual,
+                    Less            => BinaryOp::Less,
+                    LessEqual       => BinaryOp::LessEqual,
+                    Plus            => BinaryOp::Add,
+                    Minus           => BinaryOp::Subtract,
+                    Star            => BinaryOp::Multiply,
+                    _               => BinaryOp::Divide,
+                };
+                ExprKind::Binary { operator, operator_span, left: Box::new(left), right: Box::new(right) }
+            }
+        };
+        self.expr(kind, start)
+    }
+
+    fn argument_list(&mut self) -> Vec<Expr> {
+        let mut arguments = Vec::new();
+        if !self.check(TokenType::RightParen) {
+            loop {
+                arguments.push(self.expression());
+                if self.panic_mode {
+                    self.recover_to(&[TokenType::Comma, TokenType::RightParen]);
+                }
+                if arguments.len() == 256 {
+                    self.error("Can't have more than 255 arguments.");
+                }
+                if !self.r#match(TokenType::Comma) {
+                    break;
+                }
+            }
+        }
+        self.consume(TokenType::RightParen, "Expect ')' after arguments.");
+        arguments
+    }
+
+    fn variable(&mut self, token: Token<'a>, can_assign: bool) -> Expr {
+        let variable = Variable {
+            name: self.identifier(&token),
+            binding: Cell::default(),
+        };
+        if can_assign && self.r#match(TokenType::Equal) {
+            let value = self.expression();
+            self.expr(ExprKind::Assign(variable, Box::new(value)), token.span())
+        } else {
+            self.expr(ExprKind::Variable(variable), token.span())
+        }
+    }
+
+    fn r#match(&mut self, token_type: TokenType) -> bool {
+        if !self.check(token_type) {
+            return false;
+        }
+        self.advance();
+        true
+    }
+
+    fn check(&self, token_type: TokenType) -> bool {
+        self.current.token_type == token_type
+    }
+
+    fn consume(&mut self, token_type: TokenType, message: &str) {
+        if self.current.token_type == token_type {
+            self.advance();
+        } else {
+            self.error_at_current(message);
+        }
+    }
+
+    fn advance(&mut self) {
+        self.previous = mem::take(&mut self.current);
+        loop {
+            self.current = self.scanner.scan_token();
+            if !matches!(self.current.token_type, TokenType::Error(_)) {
+                break;
+            }
+            self.error_at_current(self.current.value);
+        }
+    }
+
+    fn error_at_current(&mut self, message: &str) {
+        self.error_at(self.current.clone(), message);
+    }
+
+    fn error(&mut self, message: &str) {
+        self.error_at(self.previous.clone(), message);
+    }
+
+    fn error_at(&mut self, token: Token, message: &str) {
+        if self.panic_mode {
+            return;
+        }
+        self.panic_mode = true;
+        let location = match token.token_type {
+            TokenType::EOF      => " at end".to_string(),
+            TokenType::Error(_) => String::new(),
+            _                   => format!(" at '{}'", token.value),
+        };
+        self.diagnostics.push(Diagnostic {
+            severity: Severity::Error,
+            message: message.to_string(),
+            line: token.line,
+            span: token.span(),
+            location,
+        });
+        self.had_error = true;
+    }
+}
@@ -0,0 +1,247 @@
+use crate::ast::{Binding, Expr, ExprKind, Function, Literal, Script, Stmt, StmtKind, Variable};
+use crate::diagnostic::Span;
+use crate::json::Json;
+use crate::value::Value;
+
+use std::fmt::Write;
+
+
+/// Writes `script` as an S-expression, one statement per line, e.g.
+///
+/// ```text
+/// (fun add (a b)
+///   (return (+ (local a 1) (local b 2))))
+/// (print (call (global add) 1 2))
+/// ```
+pub fn sexpr(script: &Script) -> String {
+    let mut out = String::new();
+    for statement in &script.statements {
+        write_stmt(&mut out, statement, 0);
+        out.push('\n');
+    }
+    out
+}
+
+/// Writes a single statement the way `sexpr` does.
+pub fn stmt_sexpr(statement: &Stmt) -> String {
+    let mut out = String::new();
+    write_stmt(&mut out, statement, 0);
+    out
+}
+
+fn write_stmt(out: &mut String, statement: &Stmt, indent: usize) {
+    let _ = write!(out, "{:indent$}", "");
+    match &statement.kind {
+        StmtKind::Expression(expr)  => { let _ = write!(out, "(expr {})", expr_sexpr(expr)); }
+        StmtKind::Print(expr)       => { let _ = write!(out, "(print {})", expr_sexpr(expr)); }
+        StmtKind::Var { name, initializer, .. } => {
+            let _ = write!(out, "(var {}", name.name);
+            if let Some(initializer) = initializer {
+                let _ = write!(out, " {}", expr_sexpr(initializer));
+            }
+            out.push(')');
+        }
+        StmtKind::Function(function) => {
+            let params: Vec<&str> = function.params.iter().map(|param| param.name.as_str()).collect();
+            let _ = write!(out, "(fun {} ({})", function.name.name, params.join(" "));
+            write_body(out, &function.body, indent);
+        }
+        StmtKind::Block(statements) => {
+            out.push_str("(block");
+            write_body(out, statements, indent);
+        }
+        StmtKind::If { condition, then_branch, else_branch, .. } => {
+            let _ = write!(out, "(if {}", expr_sexpr(condition));
+            out.push('\n');
+            write_stmt(out, then_branch, indent + 2);
+            if let Some(else_branch) = else_branch {
+                out.push('\n');
+                write_stmt(out, else_branch, indent + 2);
+            }
+            out.push(')');
+        }
+        StmtKind::While { condition, body, .. } => {
+            let _ = writeln!(out, "(while {}", expr_sexpr(condition));
+            write_stmt(out, body, indent + 2);
+            out.push(')');
+        }
+        StmtKind::For { initializer, condition, increment, body, .. } => {
+            out.push_str("(for ");
+            match initializer {
+                Some(initializer)   => write_stmt(out, initializer, 0),
+                None                => out.push_str("()"),
+            }
+            for clause in [condition, increment] {
+                let _ = write!(out, " {}", clause.as_ref().map_or("()".to_string(), expr_sexpr));
+            }
+            out.push('\n');
+            write_stmt(out, body, indent + 2);
+            out.push(')');
+        }
+        StmtKind::Return(None)          => out.push_str("(return)"),
+        StmtKind::Return(Some(value))   => { let _ = write!(out, "(return {})", expr_sexpr(value)); }
+    }
+}
+
+fn write_body(out: &mut String, statements: &[Stmt], indent: usize) {
+    for statement in statements {
+        out.push('\n');
+        write_stmt(out, statement, indent + 2);
+    }
+    out.push(')');
+}
+
+fn expr_sexpr(expr: &Expr) -> String {
+    match &expr.kind {
+        ExprKind::Literal(literal)      => literal_sexpr(literal),
+        ExprKind::Variable(variable)    => variable_sexpr(variable),
+        ExprKind::Assign(variable, value) => format!("(= {} {})", variable_sexpr(variable), expr_sexpr(value)),
+        ExprKind::Unary { operator, operand, .. } => format!("({} {})", operator.symbol(), expr_sexpr(operand)),
+        ExprKind::Binary { operator, left, right, .. } => {
+            format!("({} {} {})", operator.symbol(), expr_sexpr(left), expr_sexpr(right))
+        }
+        ExprKind::Logical { operator, left, right, .. } => {
+            format!("({} {} {})", operator.symbol(), expr_sexpr(left), expr_sexpr(right))
+        }
+        ExprKind::Call(callee, arguments) => {
+            let mut out = format!("(call {}", expr_sexpr(callee));
+            for argument in arguments {
+                let _ = write!(out, " {}", expr_sexpr(argument));
+            }
+            out.push(')');
+            out
+        }
+        ExprKind::Grouping(inner) => format!("(group {})", expr_sexpr(inner)),
+    }
+}
+
+fn literal_sexpr(literal: &Literal) -> String {
+    match literal {
+        Literal::Nil        => "nil".to_string(),
+        Literal::Bool(b)    => b.to_string(),
+        Literal::Number(n)  => Value::Number(*n).to_string(),
+        Literal::String(s)  => format!("{s:?}"),
+    }
+}
+
+fn variable_sexpr(variable: &Variable) -> String {
+    match variable.binding.get() {
+        Binding::Global                     => format!("(global {})", variable.name.name),
+        Binding::Local { slot, .. }         => format!("(local {} {slot})", variable.name.name),
+        Binding::Upvalue { upvalue, .. }    => format!("(upvalue {} {upvalue})", variable.name.name),
+    }
+}
+
+/// `script` as JSON: every node is an object with its `type`, the `line` and
+/// `column` it starts at and its children.
+pub fn json(script: &Script) -> Json {
+    Json::object([
+        ("type", Json::from("Script")),
+        ("statements", Json::Array(script.statements.iter().map(stmt_json).collect())),
+    ])
+}
+
+fn node(kind: &str, span: Span, fields: Vec<(&str, Json)>) -> Json {
+    let mut entries = vec![
+        ("type", Json::from(kind)),
+        ("line", Json::from(span.line)),
+        ("column", Json::from(span.column)),
+    ];
+    entries.extend(fields);
+    Json::object(entries)
+}
+
+fn stmts_json(statements: &[Stmt]) -> Json {
+    Json::Array(statements.iter().map(stmt_json).collect())
+}
+
+fn stmt_json(statement: &Stmt) -> Json {
+    let (kind, fields) = match &statement.kind {
+        StmtKind::Expression(expr)  => ("Expression", vec![("expression", expr_json(expr))]),
+        StmtKind::Print(expr)       => ("Print", vec![("expression", expr_json(expr))]),
+        StmtKind::Var { name, initializer, captured } => ("Var", vec![
+            ("name", Json::from(name.name.as_str())),
+            ("initializer", initializer.as_ref().map_or(Json::Null, expr_json)),
+            ("captured", Json::from(captured.get())),
+        ]),
+        StmtKind::Function(function) => ("Function", function_json(function)),
+        StmtKind::Block(statements) => ("Block", vec![("statements", stmts_json(statements))]),
+        StmtKind::If { condition, then_branch, else_branch, .. } => ("If", vec![
+            ("condition", expr_json(condition)),
+            ("then", stmt_json(then_branch)),
+            ("else", else_branch.as_deref().map_or(Json::Null, stmt_json)),
+        ]),
+        StmtKind::While { condition, body, .. } => ("While", vec![
+            ("condition", expr_json(condition)),
+            ("body", stmt_json(body)),
+        ]),
+        StmtKind::For { initializer, condition, increment, body, .. } => ("For", vec![
+            ("initializer", initializer.as_deref().map_or(Json::Null, stmt_json)),
+            ("condition", condition.as_ref().map_or(Json::Null, expr_json)),
+            ("increment", increment.as_ref().map_or(Json::Null, expr_json)),
+            ("body", stmt_json(body)),
+        ]),
+        StmtKind::Return(value) => ("Return", vec![("value", value.as_ref().map_or(Json::Null, expr_json))]),
+    };
+    node(kind, statement.start, fields)
+}
+
+fn function_json(function: &Function) -> Vec<(&'static str, Json)> {
+    let params: Vec<Json> = function.params.iter().map(|param| Json::from(param.name.as_str())).collect();
+    let upvalues: Vec<Json> = function.upvalues.borrow().iter()
+        .map(|upvalue| Json::object([("index", Json::from(upvalue.index as usize)), ("local", Json::from(upvalue.is_local))]))
+        .collect();
+    vec![
+        ("name", Json::from(function.name.name.as_str())),
+        ("params", Json::Array(params)),
+        ("upvalues", Json::Array(upvalues)),
+        ("captured", Json::from(function.captured.get())),
+        ("body", stmts_json(&function.body)),
+    ]
+}
+
+fn expr_json(expr: &Expr) -> Json {
+    let (kind, fields) = match &expr.kind {
+        ExprKind::Literal(literal) => ("Literal", vec![("value", match literal {
+            Literal::Nil        => Json::Null,
+            Literal::Bool(b)    => Json::from(*b),
+            Literal::Number(n)  => Json::from(*n),
+            Literal::String(s)  => Json::from(s.as_str()),
+        })]),
+        ExprKind::Variable(variable) => ("Variable", variable_json(variable)),
+        ExprKind::Assign(variable, value) => {
+            let mut fields = variable_json(variable);
+            fields.push(("value", expr_json(value)));
+            ("Assign", fields)
+        }
+        ExprKind::Unary { operator, operand, .. } => ("Unary", vec![
+            ("operator", Json::from(operator.symbol())),
+            ("operand", expr_json(operand)),
+        ]),
+        ExprKind::Binary { operator, left, right, .. } => ("Binary", vec![
+            ("operator", Json::from(operator.symbol())),
+            ("left", expr_json(left)),
+            ("right", expr_json(right)),
+        ]),
+        ExprKind::Logical { operator, left, right, .. } => ("Logical", vec![
+            ("operator", Json::from(operator.symbol())),
+            ("left", expr_json(left)),
+            ("right", expr_json(right)),
+        ]),
+        ExprKind::Call(callee, arguments) => ("Call", vec![
+            ("callee", expr_json(callee)),
+            ("arguments", Json::Array(arguments.iter().map(expr_json).collect())),
+        ]),
+        ExprKind::Grouping(inner) => ("Grouping", vec![("expression", expr_json(inner))]),
+    };
+    node(kind, expr.span, fields)
+}
+
+fn variable_json(variable: &Variable) -> Vec<(&'static str, Json)> {
+    let binding = match variable.binding.get() {
+        Binding::Global => Json::object([("kind", Json::from("global"))]),
+        Binding::Local { slot, .. } => Json::object([("kind", Json::from("local")), ("slot", Json::from(slot as usize))]),
+        Binding::Upvalue { upvalue, .. } => Json::object([("kind", Json::from("upvalue")), ("index", Json::from(upvalue as usize))]),
+    };
+    vec![("name", Json::from(variable.name.name.as_str())), ("binding", binding)]
+}
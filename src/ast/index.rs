@@ -0,0 +1,252 @@
+use crate::ast::{Expr, ExprKind, Function, Identifier, Script, Stmt, StmtKind, Variable};
+use crate::diagnostic::Span;
+use crate::native::NATIVES;
+
+use std::collections::HashMap;
+use std::ops::Range;
+
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SymbolKind {
+    Variable,
+    Parameter,
+    Function(Vec<String>),
+    Native(u8),
+}
+
+/// A name declared by the script, or a native function.
+#[derive(Debug, Clone)]
+pub struct Symbol {
+    pub name:   String,
+    pub kind:   SymbolKind,
+    /// The name in its declaration, `None` for natives.
+    pub span:   Option<Span>,
+    pub global: bool,
+    /// The offsets a local can be referred to from, from the end of its
+    /// declaration to the end of its scope.
+    pub scope:  Range<usize>,
+}
+
+/// A use of a name, and the symbol it refers to if any.
+#[derive(Debug, Clone)]
+pub struct Reference {
+    pub span:       Span,
+    pub symbol:     Option<usize>,
+    /// Whether the name is assigned rather than read.
+    pub assignment: bool,
+}
+
+/// Every declaration and every use of a name in a script. Locals are found
+/// the way the resolver finds them, globals by the first declaration of the
+/// script wherever it is, since functions may use globals declared after them.
+#[derive(Debug, Default)]
+pub struct Index {
+    pub symbols:    Vec<Symbol>,
+    /// In the order of the source.
+    pub references: Vec<Reference>,
+}
+
+/// A scope of locals, with the offset it ends at.
+struct Scope {
+    end:    usize,
+    locals: Vec<usize>,
+}
+
+struct Walker {
+    index:      Index,
+    scopes:     Vec<Scope>,
+    globals:    HashMap<String, usize>,
+    /// References to globals, bound once every global is declared.
+    unbound:    Vec<(usize, String)>,
+}
+
+fn end_of(span: Span) -> usize {
+    span.offset + span.len
+}
+
+fn contains(span: Span, offset: usize) -> bool {
+    (span.offset..=end_of(span)).contains(&offset)
+}
+
+impl Index {
+    pub fn new(script: &Script) -> Self {
+        let mut walker = Walker {
+            index: Index::default(),
+            scopes: Vec::new(),
+            globals: HashMap::new(),
+            unbound: Vec::new(),
+        };
+        for (name, arity, _) in NATIVES {
+            let symbol = walker.add_symbol(name, SymbolKind::Native(arity), None, 0..usize::MAX);
+            walker.globals.insert(name.to_string(), symbol);
+        }
+        for statement in &script.statements {
+            walker.statement(statement);
+        }
+        for (reference, name) in std::mem::take(&mut walker.unbound) {
+            walker.index.references[reference].symbol = walker.globals.get(&name).copied();
+        }
+        walker.index.references.sort_by_key(|reference| reference.span.offset);
+        walker.index
+    }
+
+    /// The use of a name at `span`.
+    pub fn reference(&self, span: Span) -> Option<&Reference> {
+        let i = self.references.binary_search_by_key(&span.offset, |reference| reference.span.offset).ok()?;
+        Some(&self.references[i])
+    }
+
+    /// The symbol declared or referred to at `offset`.
+    pub fn symbol_at(&self, offset: usize) -> Option<&Symbol> {
+        let referred = self.references.iter()
+            .find(|reference| contains(reference.span, offset))
+            .map(|reference| reference.symbol);
+        match referred {
+            Some(symbol)    => symbol.map(|symbol| &self.symbols[symbol]),
+            None            => self.symbols.iter().find(|symbol| symbol.span.is_some_and(|span| contains(span, offset))),
+        }
+    }
+
+    /// The symbols that can be referred to at `offset`, the innermost one of
+    /// every name.
+    pub fn visible_at(&self, offset: usize) -> Vec<&Symbol> {
+        let mut visible: Vec<&Symbol> = Vec::new();
+        let globals = self.symbols.iter().filter(|symbol| symbol.global);
+        let locals = self.symbols.iter().filter(|symbol| !symbol.global && symbol.scope.contains(&offset));
+        for symbol in globals.chain(locals) {
+            match visible.iter_mut().find(|seen| seen.name == symbol.name) {
+                Some(seen) if !symbol.global    => *seen = symbol,
+                Some(_)                         => {}
+                None                            => visible.push(symbol),
+            }
+        }
+        visible
+    }
+}
+
+impl Walker {
+    fn add_symbol(&mut self, name: &str, kind: SymbolKind, span: Option<Span>, scope: Range<usize>) -> usize {
+        self.index.symbols.push(Symbol {
+            name: name.to_string(),
+            kind,
+            span,
+            global: self.scopes.is_empty(),
+            scope,
+        });
+        self.index.symbols.len() - 1
+    }
+
+    /// Declares `name` from `start` on, until the end of the current scope.
+    fn declare(&mut self, name: &Identifier, kind: SymbolKind, start: usize) {
+        let Some(scope) = self.scopes.last() else {
+            let symbol = self.add_symbol(&name.name, kind, Some(name.span), 0..usize::MAX);
+            let is_native = |symbol: &usize| self.index.symbols[*symbol].span.is_none();
+            if self.globals.get(&name.name).is_none_or(is_native) {
+                self.globals.insert(name.name.clone(), symbol);
+            }
+            return;
+        };
+        let end = scope.end;
+        let symbol = self.add_symbol(&name.name, kind, Some(name.span), start..end);
+        self.scopes.last_mut().unwrap().locals.push(symbol);
+    }
+
+    fn begin_scope(&mut self, end: Span) {
+        self.scopes.push(Scope { end: end_of(end), locals: Vec::new() });
+    }
+
+    fn refer(&mut self, variable: &Variable, assignment: bool) {
+        let name = &variable.name.name;
+        let local = self.scopes.iter().rev()
+            .flat_map(|scope| scope.locals.iter().rev())
+            .find(|symbol| self.index.symbols[**symbol].name == *name)
+            .copied();
+        self.index.references.push(Reference { span: variable.name.span, symbol: local, assignment });
+        if local.is_none() {
+            self.unbound.push((self.index.references.len() - 1, name.clone()));
+        }
+    }
+
+    fn statement(&mut self, statement: &Stmt) {
+        match &statement.kind {
+            StmtKind::Expression(expr) | StmtKind::Print(expr) => self.expression(expr),
+            StmtKind::Var { name, initializer, .. } => {
+                if let Some(initializer) = initializer {
+                    self.expression(initializer);
+                }
+                self.declare(name, SymbolKind::Variable, end_of(statement.end));
+            }
+            StmtKind::Function(function) => self.function(function, statement.end),
+            StmtKind::Block(statements) => {
+                self.begin_scope(statement.end);
+                for statement in statements {
+                    self.statement(statement);
+                }
+                self.scopes.pop();
+            }
+            StmtKind::If { condition, then_branch, else_branch, .. } => {
+                self.expression(condition);
+                self.statement(then_branch);
+                if let Some(else_branch) = else_branch {
+                    self.statement(else_branch);
+                }
+            }
+            StmtKind::While { condition, body, .. } => {
+                self.expression(condition);
+                self.statement(body);
+            }
+            StmtKind::For { initializer, condition, increment, body, .. } => {
+                self.begin_scope(statement.end);
+                if let Some(initializer) = initializer {
+                    self.statement(initializer);
+                }
+                for clause in [condition, increment].into_iter().flatten() {
+                    self.expression(clause);
+                }
+                self.statement(body);
+                self.scopes.pop();
+            }
+            StmtKind::Return(value) => {
+                if let Some(value) = value {
+                    self.expression(value);
+                }
+            }
+        }
+    }
+
+    fn function(&mut self, function: &Function, end: Span) {
+        let params = function.params.iter().map(|param| param.name.clone()).collect();
+        self.declare(&function.name, SymbolKind::Function(params), end_of(function.name.span));
+        self.begin_scope(end);
+        for param in &function.params {
+            self.declare(param, SymbolKind::Parameter, end_of(param.span));
+        }
+        for statement in &function.body {
+            self.statement(statement);
+        }
+        self.scopes.pop();
+    }
+
+    fn expression(&mut self, expr: &Expr) {
+        match &expr.kind {
+            ExprKind::Literal(_)            => {}
+            ExprKind::Variable(variable)    => self.refer(variable, false),
+            ExprKind::Assign(variable, value) => {
+                self.expression(value);
+                self.refer(variable, true);
+            }
+            ExprKind::Unary { operand, .. } => self.expression(operand),
+            ExprKind::Binary { left, right, .. } | ExprKind::Logical { left, right, .. } => {
+                self.expression(left);
+                self.expression(right);
+            }
+            ExprKind::Call(callee, arguments) => {
+                self.expression(callee);
+                for argument in arguments {
+                    self.expression(argument);
+                }
+            }
+            ExprKind::Grouping(inner)       => self.expression(inner),
+        }
+    }
+}
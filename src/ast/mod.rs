@@ -0,0 +1,225 @@
+use crate::diagnostic::Span;
+
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+
+mod builder;
+pub mod dump;
+pub mod index;
+
+pub use builder::Builder;
+
+
+/// A name as written in the source.
+#[derive(Debug, Clone)]
+pub struct Identifier {
+    pub name:   String,
+    pub span:   Span,
+}
+
+/// Where a variable lives, as found by `compiler::Resolver`. Locals and
+/// upvalues are located twice: for the VM by their stack slot or upvalue
+/// index, and for the tree-walking interpreter as the `index`th variable
+/// declared in the scope `depth` scopes out from where they are used.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Binding {
+    /// Also what every variable is bound to before the resolver runs.
+    #[default]
+    Global,
+    Local { depth: usize, index: usize, slot: u8 },
+    Upvalue { depth: usize, index: usize, upvalue: u8 },
+}
+
+#[derive(Debug, Clone)]
+pub struct Variable {
+    pub name:       Identifier,
+    pub binding:    Cell<Binding>,
+}
+
+/// A variable of an enclosing function that a function closes over: a slot
+/// of the function right around it when `is_local`, or one of its upvalues.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Capture {
+    pub index:      u8,
+    pub is_local:   bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Literal {
+    Nil,
+    Bool(bool),
+    Number(f64),
+    String(String),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UnaryOp {
+    Negate,
+    Not,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BinaryOp {
+    Equal,
+    NotEqual,
+    Greater,
+    GreaterEqual,
+    Less,
+    LessEqual,
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LogicalOp {
+    And,
+    Or,
+}
+
+#[derive(Debug, Clone)]
+pub struct Expr {
+    pub kind:   ExprKind,
+    pub span:   Span,
+    /// Last token of the expression. The VM reports runtime errors of the
+    /// expression on its line.
+    pub end:    Span,
+}
+
+#[derive(Debug, Clone)]
+pub enum ExprKind {
+    Literal(Literal),
+    Variable(Variable),
+    Assign(Variable, Box<Expr>),
+    Unary {
+        operator:       UnaryOp,
+        operator_span:  Span,
+        operand:        Box<Expr>,
+    },
+    Binary {
+        operator:       BinaryOp,
+        operator_span:  Span,
+        left:           Box<Expr>,
+        right:          Box<Expr>,
+    },
+    Logical {
+        operator:       LogicalOp,
+        operator_span:  Span,
+        left:           Box<Expr>,
+        right:          Box<Expr>,
+    },
+    Call(Box<Expr>, Vec<Expr>),
+    Grouping(Box<Expr>),
+}
+
+#[derive(Debug)]
+pub struct Function {
+    pub name:       Identifier,
+    pub params:     Vec<Identifier>,
+    /// The `{` the body starts with.
+    pub left_brace: Span,
+    pub body:       Vec<Stmt>,
+    /// The variables the function closes over, in the order of its upvalues.
+    pub upvalues:   RefCell<Vec<Capture>>,
+    /// Whether a closure captures the variable the function is declared in.
+    pub captured:   Cell<bool>,
+}
+
+#[derive(Debug)]
+pub struct Stmt {
+    pub kind:   StmtKind,
+    /// First token of the statement.
+    pub start:  Span,
+    /// Last token of the statement: its `;` or `}`, or that of its body.
+    pub end:    Span,
+}
+
+#[derive(Debug)]
+pub enum StmtKind {
+    Expression(Expr),
+    Print(Expr),
+    Var {
+        name:           Identifier,
+        initializer:    Option<Expr>,
+        /// Whether a closure captures the variable.
+        captured:       Cell<bool>,
+    },
+    Function(Rc<Function>),
+    Block(Vec<Stmt>),
+    If {
+        condition:      Expr,
+        right_paren:    Span,
+        then_branch:    Box<Stmt>,
+        else_branch:    Option<Box<Stmt>>,
+    },
+    While {
+        condition:      Expr,
+        right_paren:    Span,
+        body:           Box<Stmt>,
+    },
+    /// Kept apart from `While` since the loop variable is scoped to the loop.
+    For {
+        initializer:    Option<Box<Stmt>>,
+        condition:      Option<Expr>,
+        /// The `;` ending the condition, which is there without one as well.
+        semicolon:      Span,
+        increment:      Option<Expr>,
+        right_paren:    Span,
+        body:           Box<Stmt>,
+    },
+    Return(Option<Expr>),
+}
+
+/// A whole source file.
+#[derive(Debug)]
+pub struct Script {
+    pub statements: Vec<Stmt>,
+    /// The end of the source, where the script returns.
+    pub end:        Span,
+}
+
+impl Expr {
+    pub fn line(&self) -> usize {
+        self.end.line
+    }
+}
+
+impl UnaryOp {
+    pub fn symbol(self) -> &'static str {
+        match self {
+            Self::Negate    => "-",
+            Self::Not       => "!",
+        }
+    }
+}
+
+impl BinaryOp {
+    pub fn symbol(self) -> &'static str {
+        match self {
+            Self::Equal         => "==",
+            Self::NotEqual      => "!=",
+            Self::Greater       => ">",
+            Self::GreaterEqual  => ">=",
+            Self::Less          => "<",
+            Self::LessEqual     => "<=",
+            Self::Add           => "+",
+            Self::Subtract      => "-",
+            Self::Multiply      => "*",
+            Self::Divide        => "/",
+        }
+    }
+
+    pub fn is_comparison(self) -> bool {
+        !matches!(self, Self::Add | Self::Subtract | Self::Multiply | Self::Divide)
+    }
+}
+
+impl LogicalOp {
+    pub fn symbol(self) -> &'static str {
+        match self {
+            Self::And   => "and",
+            Self::Or    => "or",
+        }
+    }
+}
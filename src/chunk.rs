@@ -1,13 +1,17 @@
+use crate::diagnostic::{Source, Span};
 use crate::value::Value;
 
 use std::default::Default;
 use std::fmt::Debug;
 use std::mem;
+use std::rc::Rc;
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 #[repr(u8)]
 pub enum OpCode {
     Add,
+    /// `GetLocal; Constant; Add` in one instruction.
+    AddLocalConstant,
     Call,
     CloseUpvalue,
     Closure,
@@ -22,6 +26,8 @@ pub enum OpCode {
     Greater,
     Jump,
     JumpIfFalse,
+    /// `Not; JumpIfFalse` for when the condition is popped either way.
+    JumpIfTrue,
     Less,
     Loop,
     Multiply,
@@ -35,6 +41,8 @@ pub enum OpCode {
     SetLocal,
     SetUpvalue,
     Subtract,
+    /// `Call` reusing the frame of the caller, which returns right after.
+    TailCall,
     True,
 }
 
@@ -59,6 +67,8 @@ pub struct Chunk {
     pub code:       Vec<u8>,
     pub constants:  Vec<Value>,
     pub lines:      Vec<usize>,
+    pub spans:      Vec<Span>,
+    pub source:     Rc<Source>,
 }
 
 impl Chunk {
@@ -66,9 +76,10 @@ impl Chunk {
         Self::default()
     }
 
-    pub fn write<T: Into<u8>>(&mut self, value: T, line: usize) {
+    pub fn write<T: Into<u8>>(&mut self, value: T, line: usize, span: Span) {
         self.code.push(value.into());
         self.lines.push(line);
+        self.spans.push(span);
     }
 
     pub fn add_constant(&mut self, value: Value) -> usize {
@@ -76,4 +87,19 @@ impl Chunk {
         self.constants.len() - 1
     }
 
+    /// Size in bytes of the instruction starting at `offset`, operands included.
+    pub fn instruction_len(&self, offset: usize) -> usize {
+        use OpCode::*;
+        match self.code[offset].into() {
+            Constant | GetLocal | SetLocal | GetGlobal | DefineGlobal | SetGlobal |
+            Call | TailCall | GetUpvalue | SetUpvalue => 2,
+            Jump | JumpIfFalse | JumpIfTrue | Loop | AddLocalConstant => 3,
+            Closure => match &self.constants[self.code[offset + 1] as usize] {
+                Value::Function(function) => 2 + 2 * function.upvalue_count,
+                _ => 2,
+            },
+            _ => 1,
+        }
+    }
+
 }
@@ -0,0 +1,509 @@
+use crate::ast::{BinaryOp, Binding, Expr, ExprKind, Function as Declaration, Identifier, Literal, LogicalOp, Script, Stmt, StmtKind, UnaryOp};
+use crate::chunk::OpCode;
+use crate::compiler::peephole;
+use crate::compiler::report::{Report, Reporter};
+use crate::compiler::{Options, Warning};
+use crate::diagnostic::{Source, Span};
+use crate::value::{Function, Value};
+
+use std::mem;
+use std::rc::Rc;
+
+
+struct Local {
+    depth:      usize,
+    captured:   bool,
+}
+
+/// The script or a function being compiled.
+#[derive(Default)]
+pub(super) struct FunctionState {
+    pub(super) function:    Function,
+    is_script:      bool,
+    /// The locals of the scopes a block ends, slot zero left out.
+    locals:         Vec<Local>,
+    scope_depth:    usize,
+}
+
+#[derive(Debug, PartialEq)]
+pub(super) enum Operand {
+    Literal(Value),
+    /// The opcode reading the variable and its slot or name.
+    Variable(OpCode, String),
+}
+
+/// Emits the bytecode of a resolved tree. Every instruction is attributed to
+/// the token the parser would have just read when emitting it, so runtime
+/// errors are reported on the same lines as by a single-pass compiler.
+pub(super) struct Codegen<'a> {
+    pub(super) options: &'a Options,
+    source:     &'a Rc<Source>,
+    reporter:   Reporter<'a>,
+    pub(super) current: FunctionState,
+    /// The functions around the one being compiled, innermost last.
+    enclosing:  Vec<FunctionState>,
+    /// The token the emitted code is attributed to.
+    previous:   Span,
+    /// How many `if`, `while` and `for` bodies enclose the current statement.
+    nested:     usize,
+    /// Offset of the last `Call` emitted, to find calls in tail position.
+    last_call:  Option<usize>,
+}
+
+impl<'a> Codegen<'a> {
+    pub fn new(options: &'a Options, text: &'a str, source: &'a Rc<Source>) -> Self {
+        Self {
+            options,
+            source,
+            reporter: Reporter::new(text, options.warnings),
+            current: FunctionState { is_script: true, ..Default::default() },
+            enclosing: Vec::new(),
+            previous: Span::default(),
+            nested: 0,
+            last_call: None,
+        }
+    }
+
+    pub fn into_reports(self) -> Vec<Report> {
+        self.reporter.reports
+    }
+
+    pub fn compile(&mut self, script: &Script) -> Rc<Function> {
+        for statement in &script.statements {
+            self.declaration(statement);
+        }
+        self.previous = script.end;
+        self.end_function()
+    }
+
+    fn declaration(&mut self, statement: &Stmt) {
+        match &statement.kind {
+            StmtKind::Function(function) => {
+                self.previous = function.name.span;
+                let global = self.parse_variable(&function.name, function.captured.get());
+                self.function(function, statement.start, statement.end);
+                self.define_variable(global);
+            }
+            StmtKind::Var { .. } => self.var_declaration(statement),
+            _ => self.statement(statement),
+        }
+        self.reporter.panic_mode = false;
+    }
+
+    fn var_declaration(&mut self, statement: &Stmt) {
+        let StmtKind::Var { name, initializer, captured } = &statement.kind else {
+            unreachable!("not a variable declaration");
+        };
+        self.previous = name.span;
+        let global = self.parse_variable(name, captured.get());
+        match initializer {
+            Some(initializer)   => self.expression(initializer),
+            None                => self.emit_byte(OpCode::Nil),
+        }
+        self.previous = statement.end;
+        self.define_variable(global);
+    }
+
+    /// Adds a local for `name` in a scope, or the constant naming the global.
+    fn parse_variable(&mut self, name: &Identifier, captured: bool) -> u8 {
+        if self.current.scope_depth > 0 {
+            self.current.locals.push(Local { depth: self.current.scope_depth, captured });
+            return 0;
+        }
+        self.make_constant(Value::from(name.name.clone()))
+    }
+
+    fn define_variable(&mut self, global: u8) {
+        if self.current.scope_depth == 0 {
+            self.emit_bytes(OpCode::DefineGlobal, global);
+        }
+    }
+
+    fn function(&mut self, declaration: &Declaration, start: Span, end: Span) {
+        let mut state = FunctionState::default();
+        state.function.name = declaration.name.name.clone();
+        state.function.line = start.line;
+        state.function.arity = declaration.params.len();
+        state.function.upvalue_count = declaration.upvalues.borrow().len();
+        self.enclosing.push(mem::replace(&mut self.current, state));
+        self.begin_scope();
+        for param in &declaration.params {
+            self.parse_variable(param, false);
+        }
+        for statement in &declaration.body {
+            self.declaration(statement);
+        }
+        self.previous = end;
+        let function = self.end_function();
+        let constant = self.make_constant(Value::from(&function));
+        self.emit_bytes(OpCode::Closure, constant);
+        for upvalue in declaration.upvalues.borrow().iter() {
+            self.emit_byte(if upvalue.is_local { 1 } else { 0 });
+            self.emit_byte(upvalue.index);
+        }
+    }
+
+    fn end_function(&mut self) -> Rc<Function> {
+        self.emit_return();
+        let mut function = mem::take(&mut self.current.function);
+        if self.options.optimize && !self.reporter.had_error {
+            peephole::optimize(&mut function.chunk);
+        }
+        function.chunk.source = Rc::clone(self.source);
+        let function = Rc::new(function);
+        if let Some(enclosing) = self.enclosing.pop() {
+            self.current = enclosing;
+        }
+        function
+    }
+
+    fn statement(&mut self, statement: &Stmt) {
+        match &statement.kind {
+            StmtKind::Expression(expr) => {
+                self.expression(expr);
+                self.previous = statement.end;
+                if self.options.repl && self.nested == 0 && self.current.scope_depth == 0 && self.current.is_script {
+                    self.emit_byte(OpCode::Print);
+                } else {
+                    self.emit_byte(OpCode::Pop);
+                }
+            }
+            StmtKind::Print(expr) => {
+                self.expression(expr);
+                self.previous = statement.end;
+                self.emit_byte(OpCode::Print);
+            }
+            StmtKind::Block(statements) => {
+                self.begin_scope();
+                for statement in statements {
+                    self.declaration(statement);
+                }
+                self.previous = statement.end;
+                self.end_scope();
+            }
+            StmtKind::If { condition, right_paren, then_branch, else_branch } => {
+                self.expression(condition);
+                self.previous = *right_paren;
+                let then_jump = self.emit_jump(OpCode::JumpIfFalse);
+                self.emit_byte(OpCode::Pop);
+                self.nested_statement(then_branch);
+                let else_jump = self.emit_jump(OpCode::Jump);
+                self.patch_jump(then_jump);
+                self.emit_byte(OpCode::Pop);
+                if let Some(else_branch) = else_branch {
+                    self.nested_statement(else_branch);
+                }
+                self.patch_jump(else_jump);
+            }
+            StmtKind::While { condition, right_paren, body } => {
+                let loop_start = self.current.function.chunk.code.len();
+                self.expression(condition);
+                self.previous = *right_paren;
+                let exit_jump = self.emit_jump(OpCode::JumpIfFalse);
+                self.emit_byte(OpCode::Pop);
+                self.nested_statement(body);
+                self.emit_loop(loop_start);
+                self.patch_jump(exit_jump);
+                self.emit_byte(OpCode::Pop);
+            }
+            StmtKind::For { initializer, condition, semicolon, increment, right_paren, body } => {
+                self.begin_scope();
+                match initializer.as_deref() {
+                    Some(initializer @ Stmt { kind: StmtKind::Var { .. }, .. }) => self.var_declaration(initializer),
+                    Some(initializer) => self.statement(initializer),
+                    None => (),
+                }
+                let mut loop_start = self.current.function.chunk.code.len();
+                let mut exit_jump = None;
+                if let Some(condition) = condition {
+                    self.expression(condition);
+                    self.previous = *semicolon;
+                    exit_jump = Some(self.emit_jump(OpCode::JumpIfFalse));
+                    self.emit_byte(OpCode::Pop);
+                }
+                if let Some(increment) = increment {
+                    self.previous = *semicolon;
+                    let body_jump = self.emit_jump(OpCode::Jump);
+                    let increment_start = self.current.function.chunk.code.len();
+                    self.expression(increment);
+                    self.emit_byte(OpCode::Pop);
+                    self.previous = *right_paren;
+                    self.emit_loop(loop_start);
+                    loop_start = increment_start;
+                    self.patch_jump(body_jump);
+                }
+                self.nested_statement(body);
+                self.emit_loop(loop_start);
+                if let Some(exit_jump) = exit_jump {
+                    self.patch_jump(exit_jump);
+                    self.emit_byte(OpCode::Pop);
+                }
+                self.end_scope();
+            }
+            StmtKind::Return(None) => {
+                self.previous = statement.end;
+                self.emit_return();
+            }
+            StmtKind::Return(Some(value)) => {
+                self.last_call = None;
+                self.expression(value);
+                self.previous = statement.end;
+                // A call ending the expression is the last thing the function does,
+                // its result is returned as is. Jumps past it, as in `a or f()`,
+                // still land on the `Return`.
+                let chunk = &mut self.current.function.chunk;
+                if let Some(call) = self.last_call.filter(|&call| call + 2 == chunk.code.len()) {
+                    chunk.code[call] = OpCode::TailCall.into();
+                }
+                self.emit_byte(OpCode::Return);
+            }
+            StmtKind::Var { .. } | StmtKind::Function(_) => self.declaration(statement),
+        }
+        self.previous = statement.end;
+    }
+
+    fn nested_statement(&mut self, statement: &Stmt) {
+        self.nested += 1;
+        self.statement(statement);
+        self.nested -= 1;
+    }
+
+    fn begin_scope(&mut self) {
+        self.current.scope_depth += 1;
+    }
+
+    fn end_scope(&mut self) {
+        self.current.scope_depth -= 1;
+        while let Some(local) = self.current.locals.last() {
+            if local.depth <= self.current.scope_depth {
+                break;
+            }
+            if local.captured {
+                self.emit_byte(OpCode::CloseUpvalue);
+            } else {
+                self.emit_byte(OpCode::Pop);
+            }
+            self.current.locals.pop();
+        }
+    }
+
+    fn expression(&mut self, expr: &Expr) {
+        match &expr.kind {
+            ExprKind::Literal(literal) => {
+                self.previous = expr.end;
+                match literal {
+                    Literal::Nil            => self.emit_byte(OpCode::Nil),
+                    Literal::Bool(true)     => self.emit_byte(OpCode::True),
+                    Literal::Bool(false)    => self.emit_byte(OpCode::False),
+                    Literal::Number(n)      => self.emit_constant(Value::Number(*n)),
+                    Literal::String(s)      => self.emit_constant(Value::from(s.clone())),
+                }
+            }
+            ExprKind::Variable(variable) => {
+                self.previous = variable.name.span;
+                let (get_op, _, arg) = self.variable_ops(variable.binding.get(), &variable.name);
+                self.emit_bytes_at(get_op, arg, variable.name.span);
+            }
+            ExprKind::Assign(variable, value) => {
+                self.previous = variable.name.span;
+                let (_, set_op, arg) = self.variable_ops(variable.binding.get(), &variable.name);
+                self.expression(value);
+                self.previous = expr.end;
+                self.emit_bytes_at(set_op, arg, variable.name.span);
+            }
+            ExprKind::Unary { operator, operand, .. } => {
+                let start = self.current.function.chunk.code.len();
+                self.expression(operand);
+                self.previous = expr.end;
+                if !self.fold_unary(*operator, expr.span, start) {
+                    return;
+                }
+                match operator {
+                    UnaryOp::Negate => self.emit_byte_at(OpCode::Negate, expr.span),
+                    UnaryOp::Not    => self.emit_byte_at(OpCode::Not, expr.span),
+                }
+            }
+            ExprKind::Binary { operator, operator_span, left, right } => {
+                let left_start = self.current.function.chunk.code.len();
+                self.expression(left);
+                let right_start = self.current.function.chunk.code.len();
+                self.expression(right);
+                self.previous = expr.end;
+                let span = expr.span;
+                self.check_comparison(*operator, *operator_span, span, left_start, right_start);
+                if !self.fold_binary(*operator, span, left_start, right_start) {
+                    return;
+                }
+                use BinaryOp::*;
+                match operator {
+                    NotEqual        => self.emit_bytes_at(OpCode::Equal, OpCode::Not, span),
+                    Equal           => self.emit_byte_at (OpCode::Equal, span),
+                    Greater         => self.emit_byte_at (OpCode::Greater, span),
+                    GreaterEqual    => self.emit_bytes_at(OpCode::Less, OpCode::Not, span),
+                    Less            => self.emit_byte_at (OpCode::Less, span),
+                    LessEqual       => self.emit_bytes_at(OpCode::Greater, OpCode::Not, span),
+                    Add             => self.emit_byte_at (OpCode::Add, span),
+                    Subtract        => self.emit_byte_at (OpCode::Subtract, span),
+                    Multiply        => self.emit_byte_at (OpCode::Multiply, span),
+                    Divide          => self.emit_byte_at (OpCode::Divide, span),
+                }
+            }
+            ExprKind::Logical { operator: LogicalOp::And, operator_span, left, right } => {
+                self.expression(left);
+                self.previous = *operator_span;
+                let end_jump = self.emit_jump(OpCode::JumpIfFalse);
+                self.emit_byte(OpCode::Pop);
+                self.expression(right);
+                self.previous = expr.end;
+                self.patch_jump(end_jump);
+            }
+            ExprKind::Logical { operator: LogicalOp::Or, operator_span, left, right } => {
+                self.expression(left);
+                self.previous = *operator_span;
+                let else_jump = self.emit_jump(OpCode::JumpIfFalse);
+                let end_jump = self.emit_jump(OpCode::Jump);
+                self.patch_jump(else_jump);
+                self.emit_byte(OpCode::Pop);
+                self.expression(right);
+                self.previous = expr.end;
+                self.patch_jump(end_jump);
+            }
+            ExprKind::Call(callee, arguments) => {
+                self.expression(callee);
+                for argument in arguments {
+                    self.expression(argument);
+                    self.reporter.panic_mode = false;
+                }
+                self.previous = expr.end;
+                self.last_call = Some(self.current.function.chunk.code.len());
+                self.emit_bytes_at(OpCode::Call, arguments.len() as u8, expr.span);
+            }
+            ExprKind::Grouping(inner) => self.expression(inner),
+        }
+        self.previous = expr.end;
+    }
+
+    /// The instructions reading and writing a variable, and their operand.
+    fn variable_ops(&mut self, binding: Binding, name: &Identifier) -> (OpCode, OpCode, u8) {
+        match binding {
+            Binding::Local { slot, .. }         => (OpCode::GetLocal, OpCode::SetLocal, slot),
+            Binding::Upvalue { upvalue, .. }    => (OpCode::GetUpvalue, OpCode::SetUpvalue, upvalue),
+            Binding::Global                     => {
+                let constant = self.make_constant(Value::from(name.name.clone()));
+                (OpCode::GetGlobal, OpCode::SetGlobal, constant)
+            }
+        }
+    }
+
+    /// The operand compiled to `code[start..end]`, if that is a single
+    /// instruction pushing a literal or reading a variable.
+    pub(super) fn simple_operand(&self, start: usize, end: usize) -> Option<Operand> {
+        let chunk = &self.current.function.chunk;
+        if start >= end || chunk.instruction_len(start) != end - start {
+            return None;
+        }
+        match chunk.code[start].into() {
+            OpCode::Nil         => Some(Operand::Literal(Value::Nil)),
+            OpCode::True        => Some(Operand::Literal(Value::Bool(true))),
+            OpCode::False       => Some(Operand::Literal(Value::Bool(false))),
+            OpCode::Constant    => Some(Operand::Literal(chunk.constants[chunk.code[start + 1] as usize].clone())),
+            OpCode::GetGlobal   => Some(Operand::Variable(OpCode::GetGlobal, chunk.constants[chunk.code[start + 1] as usize].to_string())),
+            op @ (OpCode::GetLocal | OpCode::GetUpvalue) => Some(Operand::Variable(op, chunk.code[start + 1].to_string())),
+            _ => None,
+        }
+    }
+
+    fn check_comparison(&mut self, operator: BinaryOp, operator_span: Span, span: Span, left_start: usize, right_start: usize) {
+        use BinaryOp::*;
+        if !operator.is_comparison() {
+            return;
+        }
+        let end = self.current.function.chunk.code.len();
+        let left = self.simple_operand(left_start, right_start);
+        let right = self.simple_operand(right_start, end);
+        let result = match (left, right) {
+            (Some(Operand::Literal(a)), Some(Operand::Literal(b))) => match self.evaluate_binary(operator, &a, &b) {
+                Some(Value::Bool(result))   => Some(result),
+                _                           => None,
+            },
+            (Some(a @ Operand::Variable(..)), Some(b @ Operand::Variable(..))) if a == b => {
+                Some(matches!(operator, Equal | GreaterEqual | LessEqual))
+            }
+            _ => None,
+        };
+        if let Some(result) = result {
+            let message = format!("Comparison is always {result}.");
+            self.reporter.warning(Warning::ConstantComparison, self.previous.offset, operator_span, span, &message);
+        }
+    }
+
+    fn emit_constant(&mut self, value: Value) {
+        let constant = self.make_constant(value);
+        self.emit_bytes(OpCode::Constant, constant);
+    }
+
+    pub(super) fn make_constant(&mut self, value: Value) -> u8 {
+        let constant = self.current.function.chunk.add_constant(value);
+        if constant > u8::MAX as usize {
+            self.error("Too many constants in one chunk.");
+            0
+        } else {
+            constant as u8
+        }
+    }
+
+    fn emit_return(&mut self) {
+        self.emit_byte(OpCode::Nil);
+        self.emit_byte(OpCode::Return);
+    }
+
+    fn emit_jump<T: Into<u8>>(&mut self, instruction: T) -> usize {
+        self.emit_byte(instruction);
+        self.emit_byte(0xff);
+        self.emit_byte(0xff);
+        self.current.function.chunk.code.len() - 2
+    }
+
+    fn patch_jump(&mut self, offset: usize) {
+        let jump = self.current.function.chunk.code.len() - offset - 2;
+        if jump as u16 > u16::MAX {
+            self.error("Too much code to jump over.");
+        }
+        self.current.function.chunk.code[offset] = ((jump >> 8) & 0xff) as u8;
+        self.current.function.chunk.code[offset + 1] = (jump & 0xff) as u8;
+    }
+
+    fn emit_loop(&mut self, loop_start: usize) {
+        self.emit_byte(OpCode::Loop);
+        let offset = self.current.function.chunk.code.len() - loop_start + 2;
+        if offset as u16 > u16::MAX {
+            self.error("Loop body too large.");
+        }
+        self.emit_byte(((offset >> 8) & 0xff) as u8);
+        self.emit_byte((offset & 0xff) as u8);
+    }
+
+    fn error(&mut self, message: &str) {
+        self.reporter.error(self.previous, message);
+    }
+
+    fn emit_byte<T: Into<u8>>(&mut self, byte: T) {
+        self.emit_byte_at(byte, self.previous);
+    }
+
+    fn emit_bytes<T: Into<u8>, U: Into<u8>>(&mut self, byte1: T, byte2: U) {
+        self.emit_byte(byte1);
+        self.emit_byte(byte2);
+    }
+
+    /// Emits a byte attributed to `span` rather than to the previous token, so
+    /// runtime errors point at the whole expression.
+    pub(super) fn emit_byte_at<T: Into<u8>>(&mut self, byte: T, span: Span) {
+        self.current.function.chunk.write(byte, self.previous.line, span);
+    }
+
+    pub(super) fn emit_bytes_at<T: Into<u8>, U: Into<u8>>(&mut self, byte1: T, byte2: U, span: Span) {
+        self.emit_byte_at(byte1, span);
+        self.emit_byte_at(byte2, span);
+    }
+}
@@ -0,0 +1,197 @@
+use crate::ast::{BinaryOp, UnaryOp};
+use crate::chunk::{Chunk, OpCode};
+use crate::compiler::codegen::{Codegen, Operand};
+use crate::diagnostic::Span;
+use crate::value::{Slot, Value};
+
+
+/// What an expression is known to evaluate to, if it doesn't fail at runtime.
+#[derive(PartialEq)]
+enum Kind {
+    Number,
+    Bool,
+}
+
+impl<'a> Codegen<'a> {
+    /// Replaces the operands compiled at `left_start..` with their result when
+    /// both are literals, or drops operations that don't change their operand.
+    /// Returns whether `operator` still needs to be emitted.
+    pub(super) fn fold_binary(&mut self, operator: BinaryOp, span: Span, left_start: usize, right_start: usize) -> bool {
+        use BinaryOp::*;
+        let end = self.current.function.chunk.code.len();
+        let left = self.simple_operand(left_start, right_start);
+        let right = self.simple_operand(right_start, end);
+        if let (Some(Operand::Literal(a)), Some(Operand::Literal(b))) = (&left, &right) {
+            if let Some(result) = self.evaluate_binary(operator, a, b) {
+                self.truncate(left_start);
+                self.emit_literal(result, span);
+                return false;
+            }
+            return true;
+        }
+
+        // x - 0, x * 1 and x / 1 are x for every number, including NaN and -0.
+        // x + 0 is not, as -0 + 0 is 0. The other operand must be known to be a
+        // number, so that the runtime error for any other type is kept.
+        let is = |operand: &Option<Operand>, n: f64| matches!(operand, Some(Operand::Literal(Value::Number(m))) if *m == n && m.is_sign_positive());
+        if matches!(operator, Subtract | Multiply | Divide) && is(&right, if operator == Subtract { 0.0 } else { 1.0 })
+            && self.kind(left_start, right_start) == Some(Kind::Number) {
+            self.truncate(right_start);
+            return false;
+        }
+        if operator == Multiply && is(&left, 1.0) && self.kind(right_start, end) == Some(Kind::Number) {
+            self.remove(left_start, right_start);
+            return false;
+        }
+        true
+    }
+
+    /// Like `fold_binary` for the operand of a unary operator starting at `start`.
+    pub(super) fn fold_unary(&mut self, operator: UnaryOp, span: Span, start: usize) -> bool {
+        let end = self.current.function.chunk.code.len();
+        if let Some(Operand::Literal(value)) = self.simple_operand(start, end) {
+            let result = match (operator, value) {
+                (UnaryOp::Negate, Value::Number(n)) => Value::Number(-n),
+                (UnaryOp::Negate, _)                => return true,
+                (UnaryOp::Not, value)               => Value::Bool(value.is_falsey(self.options.strict)),
+            };
+            self.truncate(start);
+            self.emit_literal(result, span);
+            return false;
+        }
+
+        // - -x is x for a number, and ! !x is x for a boolean.
+        let last = match self.last_instruction(start, end) {
+            Some(last) if last > start  => last,
+            _                           => return true,
+        };
+        let inner = self.current.function.chunk.code[last].into();
+        let kind = match (operator, inner) {
+            (UnaryOp::Negate, OpCode::Negate)   => Kind::Number,
+            (UnaryOp::Not, OpCode::Not)         => Kind::Bool,
+            _                                   => return true,
+        };
+        if self.kind(start, last) == Some(kind) {
+            self.truncate(last);
+            return false;
+        }
+        true
+    }
+
+    /// The result of `a operator b` as the VM computes it, if it doesn't fail.
+    pub(super) fn evaluate_binary(&self, operator: BinaryOp, a: &Value, b: &Value) -> Option<Value> {
+        use BinaryOp::*;
+        let result = match (operator, a, b) {
+            (Add, Value::String(_), b) if self.options.strict && !matches!(b, Value::String(_)) => return None,
+            (Add, Value::String(a), b)                     => Value::from(format!("{a}{b}")),
+            (Add, Value::Number(a), Value::Number(b))      => Value::Number(a + b),
+            (Subtract, Value::Number(a), Value::Number(b))     => Value::Number(a - b),
+            (Multiply, Value::Number(a), Value::Number(b))      => Value::Number(a * b),
+            (Divide, Value::Number(a), Value::Number(b))     => Value::Number(a / b),
+            (Equal, a, b)                              => Value::Bool(a == b),
+            (NotEqual, a, b)                               => Value::Bool(a != b),
+            // `>=` and `<=` compile to a negated `<` and `>`, which are true
+            // rather than false for NaN.
+            (Greater, Value::Number(a), Value::Number(b))       => Value::Bool(a > b),
+            (GreaterEqual, Value::Number(a), Value::Number(b))  => Value::Bool(a >= b || a.is_nan() || b.is_nan()),
+            (Less, Value::Number(a), Value::Number(b))          => Value::Bool(a < b),
+            (LessEqual, Value::Number(a), Value::Number(b))     => Value::Bool(a <= b || a.is_nan() || b.is_nan()),
+            (Greater, Value::String(a), Value::String(b))       => Value::Bool(a > b),
+            (GreaterEqual, Value::String(a), Value::String(b))  => Value::Bool(a >= b),
+            (Less, Value::String(a), Value::String(b))          => Value::Bool(a < b),
+            (LessEqual, Value::String(a), Value::String(b))     => Value::Bool(a <= b),
+            _ => return None,
+        };
+        Some(result)
+    }
+
+    /// What the code in `start..end` evaluates to. Code containing jumps is
+    /// never known, as `and` and `or` may leave either of their operands.
+    fn kind(&self, start: usize, end: usize) -> Option<Kind> {
+        let chunk = &self.current.function.chunk;
+        let mut offset = start;
+        while offset < end {
+            if let OpCode::Jump | OpCode::JumpIfFalse = chunk.code[offset].into() {
+                return None;
+            }
+            offset += chunk.instruction_len(offset);
+        }
+        let last = self.last_instruction(start, end)?;
+        match chunk.code[last].into() {
+            OpCode::Subtract | OpCode::Multiply | OpCode::Divide | OpCode::Negate => Some(Kind::Number),
+            OpCode::Not | OpCode::Equal | OpCode::Greater | OpCode::Less | OpCode::True | OpCode::False => Some(Kind::Bool),
+            OpCode::Constant => match chunk.constants[chunk.code[last + 1] as usize] {
+                Value::Number(_)    => Some(Kind::Number),
+                _                   => None,
+            },
+            _ => None,
+        }
+    }
+
+    fn last_instruction(&self, start: usize, end: usize) -> Option<usize> {
+        let chunk = &self.current.function.chunk;
+        let mut offset = start;
+        let mut last = None;
+        while offset < end {
+            last = Some(offset);
+            offset += chunk.instruction_len(offset);
+        }
+        last
+    }
+
+    fn emit_literal(&mut self, value: Value, span: Span) {
+        match value {
+            Value::Nil          => self.emit_byte_at(OpCode::Nil, span),
+            Value::Bool(true)   => self.emit_byte_at(OpCode::True, span),
+            Value::Bool(false)  => self.emit_byte_at(OpCode::False, span),
+            value               => {
+                let constant = self.make_constant(value);
+                self.emit_bytes_at(OpCode::Constant, constant, span);
+            }
+        }
+    }
+
+    /// Drops the code from `start` on, along with the constants only it used.
+    fn truncate(&mut self, start: usize) {
+        let end = self.current.function.chunk.code.len();
+        self.remove(start, end);
+    }
+
+    /// Drops the code in `start..end`. The code following it must not jump
+    /// out of itself, which holds for the operands of an expression.
+    fn remove(&mut self, start: usize, end: usize) {
+        let chunk = &mut self.current.function.chunk;
+        let mut offset = start;
+        let mut constants = Vec::new();
+        while offset < end {
+            if let OpCode::Constant = chunk.code[offset].into() {
+                constants.push(chunk.code[offset + 1] as usize);
+            }
+            offset += chunk.instruction_len(offset);
+        }
+        chunk.code.drain(start..end);
+        chunk.lines.drain(start..end);
+        chunk.spans.drain(start..end);
+        // Literals add their constant last, so the constants of removed code
+        // are usually at the end of the pool and can be dropped as well.
+        for constant in constants.into_iter().rev() {
+            if constant + 1 == chunk.constants.len() && !uses_constant(chunk, constant, start) {
+                chunk.constants.pop();
+            }
+        }
+    }
+}
+
+/// Whether code from `from` on still loads `constant`.
+fn uses_constant(chunk: &Chunk, constant: usize, from: usize) -> bool {
+    let mut offset = from;
+    while offset < chunk.code.len() {
+        if let OpCode::Constant | OpCode::Closure | OpCode::GetGlobal | OpCode::SetGlobal | OpCode::DefineGlobal = chunk.code[offset].into() {
+            if chunk.code[offset + 1] as usize == constant {
+                return true;
+            }
+        }
+        offset += chunk.instruction_len(offset);
+    }
+    false
+}
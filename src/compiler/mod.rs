@@ -1,740 +1,169 @@
-use crate::compiler::rules::ParseRule;
-use crate::chunk::OpCode;
-use crate::scanner::Scanner;
-use crate::scanner::Token;
-use crate::scanner::TokenType;
+use crate::ast::{Builder, Script};
+use crate::diagnostic::{Diagnostic, Severity, Source};
 use crate::value::Function;
-use crate::value::Value;
 
-use std::mem;
+use std::collections::HashSet;
 use std::rc::Rc;
 
-mod rules;
-
-
-#[derive(Default)]
-enum FunctionType {
-    #[default]
-    Function,
-    Script,    
+mod codegen;
+mod fold;
+pub mod peephole;
+mod report;
+mod resolver;
+mod warning;
+
+pub use warning::{Warning, Warnings};
+
+use codegen::Codegen;
+use report::Report;
+use resolver::Resolver;
+
+
+#[derive(Debug, Default, Clone)]
+pub struct Options {
+    /// Print the value of expression statements at the top level, and accept
+    /// the last one without its `;`.
+    pub repl:       bool,
+    /// Show no more than this many errors, and no diagnostics after the last.
+    pub max_errors: Option<usize>,
+    pub warnings:   Warnings,
+    /// Run the peephole optimizer over every compiled function.
+    pub optimize:   bool,
+    /// Reference Lox semantics: only `nil` and `false` are falsey, and `+`
+    /// takes two numbers or two strings.
+    pub strict:     bool,
 }
 
-struct Compiler <'a> {
-    enclosing:      Option<Box<Compiler<'a>>>,
-    function:       Function,
-    function_type:  FunctionType,
-    locals:         Vec<Local<'a>>,
-    scope_depth:    i32,
-    upvalues:       Vec<Upvalue>,
+/// Compiles a source in three passes: `ast::Builder` parses it into a tree,
+/// `Resolver` finds what every name refers to and `Codegen` emits the bytecode.
+/// The diagnostics of all passes are printed together once it is done.
+pub struct Compiler<'a> {
+    options:            Options,
+    text:               &'a str,
+    source:             Rc<Source>,
+    declared_globals:   HashSet<String>,
+    diagnostics:        Vec<Diagnostic>,
+    /// Collect the diagnostics without printing them.
+    quiet:              bool,
 }
 
-impl <'a> Compiler<'a> {
-    fn new(function_type: FunctionType) -> Self {
-        let this_token = Token {
-            value: "this",
-            ..Default::default()
-        };
-        let local = Local {
-            depth: 0,
-            name: this_token,
-            is_captured: false,
-        };
-        Self {
-            enclosing: None,
-            function: Function::new(),
-            function_type,
-            locals: vec![local],
-            scope_depth: 0,
-            upvalues: Vec::new(),
-        }
-    }
-    
-    fn resolve_local(&mut self, name: &Token) -> Result<i32, &'static str> {
-        for (i, local) in self.locals.iter().enumerate().rev() {
-            if local.name.value == name.value {
-                if local.depth == -1 {
-                    return Err("Can't read local variable in its own initializer.");
-                }
-                return Ok(i as i32);
-            }
-        }
-        return Ok(-1);
-    }
-
-    fn resolve_upvalue(&mut self, name: &Token) -> Result<i32, &'static str> {
-        if self.enclosing.is_none() {
-            return Ok(-1);
-        }
-        let local = self.enclosing.as_mut().unwrap().resolve_local(name)?;
-        if local != -1 {
-            self.enclosing.as_mut().unwrap().locals[local as usize].is_captured = true;
-            return Ok(self.add_upvalue(local as u8, true)?);
-        } 
-        let upvalue = self.enclosing.as_mut().unwrap().resolve_upvalue(name)?;
-        if upvalue != -1 {
-            return Ok(self.add_upvalue(upvalue as u8, false)?);
-        }
-        Ok(-1)
-    }
-
-    fn add_upvalue(&mut self, index: u8, is_local: bool) -> Result<i32, &'static str> {
-        for (i, upvalue) in self.upvalues.iter().enumerate() {
-            if upvalue.index == index && upvalue.is_local == is_local {
-                return Ok(i as i32);
-            }
-        }
-        let upvalue = Upvalue {
-            is_local, index
-        };
-        self.upvalues.push(upvalue);
-        self.function.upvalue_count = self.upvalues.len();
-        Ok((self.upvalues.len() - 1) as i32)
-    }
-
-}
-
-impl <'a> Default for Compiler<'a> {
-    fn default() -> Self {
-        Self::new(FunctionType::Script)
-    }
-}
-
-#[derive(Default)]
-struct Local <'a> {
-    name:   Token<'a>,
-    depth:  i32,
-    is_captured: bool,
-}
-
-#[derive(Debug, Default, Clone, Copy)]
-struct Upvalue {
-    index: u8,
-    is_local: bool,
-}
-
-#[derive(Default)]
-pub struct Parser<'a> {
-    current:    Token<'a>,
-    previous:   Token<'a>,
-    had_error:  bool,
-    panic_mode: bool,
-    compiler:   Box<Compiler<'a>>,
-    scanner:    Scanner<'a>,
-}
-
-#[repr(u8)]
-#[derive(PartialEq, PartialOrd)]
-pub enum Precedence {
-    None,
-    Assignment,  // =
-    Or,          // or
-    And,         // and
-    Equality,    // == !=
-    Comparision, // < > <= >=
-    Term,        // + 1
-    Factor,      // * /
-    Unary,       // ! -
-    Call,        // . ()
-    Primary,
-}
-
-impl std::ops::Add<u8> for Precedence {
-    type Output = Self;
-
-    fn add(self, rhs: u8) -> Self::Output {
-        unsafe {
-            mem::transmute((self as u8 + rhs) % 11)
-        }
-    }
-}
-
-impl<'a> Parser<'a> {
+impl<'a> Compiler<'a> {
     pub fn new(source: &'a str) -> Self {
-        Self {
-            scanner: Scanner::new(source),
-            compiler: Box::new(Compiler::new(FunctionType::Script)),
-            ..Default::default()
-        }
-    }
-
-    fn init_compiler(&mut self, function_type: FunctionType) {
-        let compiler = mem::replace(&mut self.compiler, Box::new(Compiler::new(function_type)));
-        self.compiler.enclosing = Some(compiler);
-        self.compiler.function.name = self.previous.value.to_string();
-    }
-
-    pub fn compile(&mut self) -> Option<Rc<Function>> {
-        self.advance();
-        while !self.r#match(TokenType::EOF) {
-            self.declaration();
-        }
-        let function = self.end_compiler(false);
-        if self.had_error {
-            None
-        } else {
-            Some(function)
-        }
-    }
-
-    fn call(&mut self, _can_assign: bool) {
-        let arg_count = self.argument_list();
-        self.emit_bytes(OpCode::Call, arg_count);
-    }
-
-    fn argument_list(&mut self) -> u8 {
-        let mut arg_count: u8 = 0;
-        if !self.check(TokenType::RightParen) {
-            loop {
-                self.expression();
-                if arg_count == 255 {
-                    self.error("Can't have more than 255 arguments.");
-                }
-                arg_count += 1;
-                if !self.r#match(TokenType::Comma) {
-                    break;
-                }
-            }
-        }
-        self.consume(TokenType::RightParen, "Expect ')' after arguments.");
-        arg_count
-    }
-
-    fn declaration(&mut self) {
-        if self.r#match(TokenType::Fun) {
-            self.fun_declaration();
-        } else if self.r#match(TokenType::Var) {
-            self.var_declaration();
-        } else {
-            self.statement();
-        }
-        if self.panic_mode {
-            self.synchronize();
-        }
-    }
-
-    fn fun_declaration(&mut self) {
-        let global = self.parse_variable("Expect function name.");
-        self.mark_initialized();
-        self.function(FunctionType::Function);
-        self.define_variable(global);
-    }
-
-    fn var_declaration(&mut self) {
-        let global = self.parse_variable("Expect variable name.");
-        if self.r#match(TokenType::Equal) {
-            self.expression();
-        } else {
-            self.emit_byte(OpCode::Nil);
-        }
-        self.consume(TokenType::Semicolon, "Expect ';' after variable declaration.");
-        self.define_variable(global);
-    }
-
-    fn parse_variable(&mut self, error_message: &str) -> u8 {
-        self.consume(TokenType::Idenitifier, error_message);
-        self.declare_variable();
-        if self.compiler.scope_depth > 0 {
-            return 0;
-        }
-        self.idenitifier_constant(self.previous.clone())
-    }
-
-    fn declare_variable(&mut self) {
-        if self.compiler.scope_depth == 0 {
-            return;
-        }
-        let name = self.previous.clone();
-        for local in self.compiler.locals.iter().rev() {
-            if local.depth != -1 && local.depth < self.compiler.scope_depth {
-                break;
-            }
-            if name.value == local.name.value {
-                self.error("A variable with this name in this scope already exists.");
-                break; // After an error is reported, we don't need to check anymore.
-            }
-        }
-        self.add_local(name);
-    }
-
-    fn add_local(&mut self, name: Token<'a>) {
-        let local = Local {
-            name,
-            depth: -1,
-            is_captured: false,
-        };
-        self.compiler.locals.push(local);
-    }
-
-    fn idenitifier_constant(&mut self, token: Token) -> u8 {
-        self.make_constant(Value::from(token.value.to_string()))
-    }
-
-    fn define_variable(&mut self, global: u8) {
-        if self.compiler.scope_depth > 0 {
-            self.mark_initialized();
-            return;
-        }
-        self.emit_bytes(OpCode::DefineGlobal, global);
-    }
-
-    fn mark_initialized(&mut self) {
-        if self.compiler.scope_depth == 0 {
-            return;
-        }
-        self.compiler.locals.last_mut().unwrap().depth = self.compiler.scope_depth;
-    }
-
-    fn function(&mut self, function_type: FunctionType) {
-        self.init_compiler(function_type);
-        self.begin_scope();
-        self.consume(TokenType::LeftParen, "Expect '(' after function name.");
-        if !self.check(TokenType::RightParen) {
-            loop {
-                self.compiler.function.arity += 1;
-                if self.compiler.function.arity > 255 {
-                    self.error_at_current("Can't have more than 255 parameters.");
-                }
-                let constant = self.parse_variable("Expect parameter name.");
-                self.define_variable(constant);
-                if !self.r#match(TokenType::Comma) {
-                    break;
-                }
-            }
-        }
-        self.consume(TokenType::RightParen, "Expect ')' after parameters.");
-        self.consume(TokenType::LeftBrace, "Expect '{' before function body.");
-        self.block();
-        self.end_compiler(true);
-    }
-
-    fn synchronize(&mut self) {
-        use TokenType::*;
-        self.panic_mode = false;
-        while self.current.token_type != EOF {
-            if self.previous.token_type == Semicolon {
-                return;
-            }
-            match self.current.token_type {
-                Class | Fun | Var | For | If | While | Print | Return => return,
-                _ => ()
-            }
-            self.advance();
-        }
-    }
-
-    fn statement(&mut self) {
-        if self.r#match(TokenType::Print) {
-            self.print_statement();
-        } else if self.r#match(TokenType::LeftBrace) {
-            self.begin_scope();
-            self.block();
-            self.end_scope();
-        } else if self.r#match(TokenType::If) {
-            self.if_statement();
-        } else if self.r#match(TokenType::Return) {
-            self.return_statement();
-        } else if self.r#match(TokenType::While) {
-            self.while_statement();
-        } else if self.r#match(TokenType::For) {
-            self.for_statement();
-        } else {
-            self.expression_statement();
-        }
+        Self::with_options("script", source, Options::default())
     }
 
-    fn for_statement(&mut self) {
-        self.begin_scope();
-        self.consume(TokenType::LeftParen, "Expect '(' after 'for'.");
-        if self.r#match(TokenType::Semicolon) {
-            ()
-        } else if self.r#match(TokenType::Var) {
-            self.var_declaration();
-        } else {
-            self.expression_statement();
-        }
-        let mut loop_start = self.compiler.function.chunk.code.len();
-        let mut exit_jump = -1;
-        if !self.r#match(TokenType::Semicolon) {
-            self.expression();
-            self.consume(TokenType::Semicolon, "Expect ';' after loop condition");
-            exit_jump = self.emit_jump(OpCode::JumpIfFalse) as i32;
-            self.emit_byte(OpCode::Pop);
-        }
-        if !self.r#match(TokenType::RightParen) {
-            let body_jump = self.emit_jump(OpCode::Jump);
-            let increment_start = self.compiler.function.chunk.code.len();
-            self.expression();
-            self.emit_byte(OpCode::Pop);
-            self.consume(TokenType::RightParen, "Expect ')' after for clauses");
-            self.emit_loop(loop_start);
-            loop_start = increment_start;
-            self.patch_jump(body_jump);
-        }
-        self.statement();
-        self.emit_loop(loop_start);
-        if exit_jump != -1 {
-            self.patch_jump(exit_jump as usize);
-            self.emit_byte(OpCode::Pop);
+    /// `name` identifies the source in diagnostics, usually it is the path of the script.
+    pub fn with_options(name: &str, source: &'a str, options: Options) -> Self {
+        Self {
+            options,
+            text: source,
+            source: Rc::new(Source::new(name, source)),
+            declared_globals: HashSet::new(),
+            diagnostics: Vec::new(),
+            quiet: false,
         }
-        self.end_scope();
     }
 
-    fn while_statement(&mut self) {
-        let loop_start = self.compiler.function.chunk.code.len();
-        self.consume(TokenType::LeftParen, "Expect '(' after 'while'.");
-        self.expression();
-        self.consume(TokenType::RightParen, "Expect ')' after condition.");
-        let exit_jump = self.emit_jump(OpCode::JumpIfFalse);
-        self.emit_byte(OpCode::Pop);
-        self.statement();
-        self.emit_loop(loop_start);
-        self.patch_jump(exit_jump);
-        self.emit_byte(OpCode::Pop);
+    /// Globals defined before this source runs, such as natives and the
+    /// declarations of earlier REPL lines.
+    pub fn declare_globals<'g>(&mut self, names: impl IntoIterator<Item = &'g String>) {
+        self.declared_globals.extend(names.into_iter().cloned());
     }
 
-    fn emit_loop(&mut self, loop_start: usize) {
-        self.emit_byte(OpCode::Loop);
-        let offset = self.compiler.function.chunk.code.len() - loop_start + 2;
-        if offset as u16 > u16::MAX {
-            self.error("Loop body too large");
-        }
-        self.emit_byte(((offset >> 8) & 0xff) as u8);
-        self.emit_byte((offset & 0xff) as u8);
+    /// Keeps the diagnostics from being printed, `diagnostics` still has them.
+    pub fn set_quiet(&mut self, quiet: bool) {
+        self.quiet = quiet;
     }
 
-    fn if_statement(&mut self) {
-        self.consume(TokenType::LeftParen, "Expect '(' after 'if'.");
-        self.expression();
-        self.consume(TokenType::RightParen, "Expect ')' after 'if'.");
-    
-        let then_jump = self.emit_jump(OpCode::JumpIfFalse);
-        self.emit_byte(OpCode::Pop);
-        self.statement();
-        let else_jump = self.emit_jump(OpCode::Jump);
-        self.patch_jump(then_jump);
-        self.emit_byte(OpCode::Pop);
-        if self.r#match(TokenType::Else) {
-            self.statement();
-        }
-        self.patch_jump(else_jump);
+    /// The diagnostics printed so far, in the order they were printed.
+    pub fn diagnostics(&self) -> &[Diagnostic] {
+        &self.diagnostics
     }
 
-    fn emit_jump<T: Into<u8>>(&mut self, instruction: T) -> usize {
-        self.emit_byte(instruction);
-        self.emit_byte(0xff);
-        self.emit_byte(0xff);
-        return self.compiler.function.chunk.code.len() - 2;
+    pub fn source(&self) -> &Rc<Source> {
+        &self.source
     }
 
-    fn patch_jump(&mut self, offset: usize) {
-        let jump = self.compiler.function.chunk.code.len() - offset - 2;
-        if jump as u16 > u16::MAX {
-            self.error("Too much code to jump over.");
-        }
-        self.compiler.function.chunk.code[offset] = (((jump >> 8) & 0xff) as u8).into();
-        self.compiler.function.chunk.code[offset + 1] = ((jump & 0xff) as u8).into();
-    }
- 
-    fn begin_scope(&mut self) {
-        self.compiler.scope_depth += 1;
+    /// Parses and resolves the source without compiling it to bytecode.
+    pub fn analyze(&mut self) -> Option<Script> {
+        let mut reports = Vec::new();
+        let script = self.front_end(&mut reports);
+        self.finish(reports).then_some(script)
     }
 
-    fn end_scope(&mut self) {
-        self.compiler.scope_depth -= 1;
-        while let Some(local) = self.compiler.locals.last() {
-            if local.depth > self.compiler.scope_depth {
-                if local.is_captured {
-                    self.emit_byte(OpCode::CloseUpvalue);
-                } else {
-                    self.emit_byte(OpCode::Pop);
-                }
-                self.compiler.locals.pop();
-            } else {
-                break;
+    pub fn compile(&mut self) -> Option<Rc<Function>> {
+        let (_, function, ok) = self.all_passes();
+        ok.then_some(function)
+    }
+
+    /// Runs every pass for its diagnostics, and returns the tree even of a
+    /// source with errors.
+    pub fn check(&mut self) -> Script {
+        self.all_passes().0
+    }
+
+    fn all_passes(&mut self) -> (Script, Rc<Function>, bool) {
+        let mut reports = Vec::new();
+        let script = self.front_end(&mut reports);
+        // The code of a script with errors is only generated for its warnings.
+        let mut codegen = Codegen::new(&self.options, self.text, &self.source);
+        let function = codegen.compile(&script);
+        reports.append(&mut codegen.into_reports());
+        let ok = self.finish(reports);
+        (script, function, ok)
+    }
+
+    fn front_end(&mut self, reports: &mut Vec<Report>) -> Script {
+        let mut builder = Builder::new(self.text);
+        builder.set_repl(self.options.repl);
+        let script = builder.build();
+        reports.extend(builder.diagnostics().iter().map(|diagnostic| Report {
+            offset: diagnostic.span.offset,
+            diagnostic: diagnostic.clone(),
+        }));
+        let mut resolver = Resolver::new(self.text, self.options.warnings, &mut self.declared_globals);
+        resolver.resolve(&script);
+        reports.append(&mut resolver.into_reports());
+        script
+    }
+
+    /// Prints the diagnostics in the order of the source, up to `max_errors`
+    /// errors, and returns whether none of them is an error. Errors past the
+    /// cap are counted in the summary without being shown.
+    fn finish(&mut self, mut reports: Vec<Report>) -> bool {
+        reports.sort_by_key(|report| report.offset);
+        let mut errors = 0;
+        let mut shown = 0;
+        let mut at_end = false;
+        for Report { diagnostic, .. } in reports {
+            // Nothing can be reported past an error at the end of the source.
+            if at_end {
+                continue;
             }
-        }
-    }
-
-    fn block(&mut self) {
-        while !self.check(TokenType::RightBrace) && !self.check(TokenType::EOF) {
-            self.declaration();
-        }
-        self.consume(TokenType::RightBrace, "Expect '}' after block.");
-    }
-
-
-    fn expression_statement(&mut self) {
-        self.expression();
-        self.consume(TokenType::Semicolon, "Expect ';' after expression.");
-        self.emit_byte(OpCode::Pop);
-    }
-
-    fn r#match(&mut self, token_type: TokenType) -> bool {
-        if !self.check(token_type) {
-            return false;
-        }
-        self.advance();
-        return true;
-    }
-
-    fn check(&mut self, token_type: TokenType) -> bool {
-        self.current.token_type == token_type
-    }
-
-    fn print_statement(&mut self) {
-        self.expression();
-        self.consume(TokenType::Semicolon, "Expect ';' after value.");
-        self.emit_byte(OpCode::Print);
-    }
-
-    fn return_statement(&mut self) {
-        if matches!(self.compiler.function_type, FunctionType::Script) {
-            self.error("Can't return from top-level code.");
-        }
-        if self.r#match(TokenType::Semicolon) {
-            self.emit_return();
-        } else {
-            self.expression();
-            self.consume(TokenType::Semicolon, "Expect ';' after return value.");
-            self.emit_byte(OpCode::Return);
-        }
-    }
-
-    fn advance(&mut self) {
-        self.previous = mem::take(&mut self.current);
-        loop {
-            self.current = self.scanner.scan_token();
-            if self.current.token_type != TokenType::Error {
-                break;
+            let is_error = diagnostic.severity == Severity::Error;
+            at_end = is_error && diagnostic.location == " at end";
+            if is_error {
+                errors += 1;
             }
-            self.error_at_current(self.current.value);
-        }
-    }
-
-    fn error_at_current(&mut self, message: &str) {
-        self.error_at(self.current.clone(), message);
-    }
-
-    fn error(&mut self, message: &'static str) {
-        self.error_at(self.previous.clone(), message);
-    }
-
-    fn error_at(&mut self, token: Token, message: &str) {
-        if self.panic_mode {
-            return;
-        }
-        self.panic_mode = true;
-        eprint!("[line {}] Error", token.line);
-        if token.token_type == TokenType::EOF {
-            eprint!(" at end");
-        } else if token.token_type == TokenType::Error {
-            ()
-        } else {
-            eprint!(" at '{}'", token.value);
-        }
-        eprintln!(": {message}");
-        self.had_error = true;
-    }
-
-    fn consume(&mut self, token_type: TokenType, message: &str) {
-        if self.current.token_type == token_type {
-            self.advance();
-        } else {
-            self.error_at_current(message);
-        }
-    }
-
-    fn emit_byte<T: Into<u8>>(&mut self, byte: T) {
-        self.compiler.function.chunk.write(byte, self.previous.line);
-    }
-
-    fn emit_bytes<T: Into<u8>, U: Into<u8>>(&mut self, byte1: T, byte2: U) {
-        self.emit_byte(byte1);
-        self.emit_byte(byte2);
-    }
-
-    fn end_compiler(&mut self, from_function: bool) -> Rc<Function> {
-        self.emit_return();
-        let function = mem::take(&mut self.compiler.function);
-        let function = Rc::new(function);
-        #[cfg(feature = "debug_print_code")]
-        {
-            use crate::debug::Disassembler;
-            if !self.had_error {
-                let disassembler = Disassembler::new(&function.chunk);
-                disassembler.disassemble_chunk(
-                    if matches!(self.compiler.function_type, FunctionType::Script) {
-                        "<script>"
-                    } else {
-                        &function.name
-                    });
+            if self.options.max_errors.is_some_and(|max| shown >= max) {
+                continue;
             }
-        }
-        if let Some(enclosing) = self.compiler.enclosing.take() {
-            let compiler = mem::replace(&mut self.compiler, enclosing);
-            if from_function {
-                let constant = self.make_constant(Value::from(&function));
-                self.emit_bytes(OpCode::Closure, constant);
-                for upvalue in compiler.upvalues.iter() {
-                    self.emit_byte(if upvalue.is_local { 1 } else { 0 });
-                    self.emit_byte(upvalue.index);
-                }
+            if !self.quiet {
+                eprint!("{}", diagnostic.render(&self.source));
             }
-        }
-        function
-    }
-
-    fn emit_return(&mut self) {
-        self.emit_byte(OpCode::Nil);
-        self.emit_byte(OpCode::Return);
-    }
-
-    fn variable(&mut self, can_assign: bool) {
-        self.named_variable(self.previous.clone(), can_assign);
-    }
-
-    fn unwrap_err(&mut self, res: Result<i32, &'static str>) -> i32 {
-        match res {
-            Ok(i) => i,
-            Err(msg) => {
-                self.error(&msg);
-                0
+            self.diagnostics.push(diagnostic);
+            if is_error {
+                shown += 1;
             }
         }
-    }
-
-    fn named_variable(&mut self, name: Token, can_assign: bool) {
-        let res = self.compiler.resolve_local(&name);
-        let mut arg = self.unwrap_err(res);
-        let get_op: OpCode;
-        let set_op: OpCode;
-        if arg != -1 {
-            get_op = OpCode::GetLocal;
-            set_op = OpCode::SetLocal;
-        } else {
-            let res = self.compiler.resolve_upvalue(&name); 
-            arg = self.unwrap_err(res);
-            if arg != -1 {
-                get_op = OpCode::GetUpvalue;
-                set_op = OpCode::SetUpvalue;
+        if errors > 0 && !self.quiet {
+            let plural = if errors == 1 { "" } else { "s" };
+            if shown < errors {
+                eprintln!("Compilation failed with {errors} error{plural} (showing {shown}).");
             } else {
-                arg = self.idenitifier_constant(name) as i32;
-                get_op = OpCode::GetGlobal;
-                set_op = OpCode::SetGlobal;
+                eprintln!("Compilation failed with {errors} error{plural}.");
             }
         }
-        if can_assign && self.r#match(TokenType::Equal) {
-            self.expression();
-            self.emit_bytes(set_op, arg as u8);
-        } else {
-            self.emit_bytes(get_op, arg as u8);
-        }
-    }
-
-    fn expression(&mut self) {
-        self.parse_precendence(Precedence::Assignment);
-    }
-
-    fn number(&mut self, _can_assign: bool) {
-        let value = Value::from(self.previous.value.parse::<f64>().unwrap());
-        self.emit_constant(value);
-    }
-
-    fn grouping(&mut self, _can_assign: bool) {
-        self.expression();
-        self.consume(TokenType::RightParen, "Expect ')' after expression.");
-    }
-
-    fn unary(&mut self, _can_assign: bool) {
-        let operator_type = self.previous.token_type;
-        self.parse_precendence(Precedence::Unary);
-        match operator_type {
-            TokenType::Minus => self.emit_byte(OpCode::Negate),
-            TokenType::Bang => self.emit_byte(OpCode::Not),
-            _ => (),
-        }
-    }
-
-    fn binary(&mut self, _can_assign: bool) {
-        let operator_type = self.previous.token_type;
-        let rule = ParseRule::get_rule(operator_type);
-        self.parse_precendence(rule.precedence + 1);
-        use TokenType::*;
-        match operator_type {
-            BangEqual       => self.emit_bytes(OpCode::Equal, OpCode::Not),
-            EqualEqual      => self.emit_byte (OpCode::Equal),
-            Greater         => self.emit_byte (OpCode::Greater),
-            GreaterEqual    => self.emit_bytes(OpCode::Less, OpCode::Not),
-            Less            => self.emit_byte (OpCode::Less),
-            LessEqual       => self.emit_bytes(OpCode::Greater, OpCode::Not),
-            Plus            => self.emit_byte (OpCode::Add),
-            Minus           => self.emit_byte (OpCode::Subtract),
-            Star            => self.emit_byte (OpCode::Multiply),
-            Slash           => self.emit_byte (OpCode::Divide),
-            _               => (),
-        }
-    }
-
-    fn literal(&mut self, _can_assign: bool) {
-        match self.previous.token_type {
-            TokenType::False => self.emit_byte(OpCode::False),
-            TokenType::Nil =>   self.emit_byte(OpCode::Nil),
-            TokenType::True =>  self.emit_byte(OpCode::True),
-            _ => (),
-        }
-    }
-
-    fn parse_precendence(&mut self, precendence: Precedence) {
-        self.advance();
-        let prefix_rule = ParseRule::get_rule(self.previous.token_type).prefix;
-        let can_assign = precendence <= Precedence::Assignment;
-        match prefix_rule {
-            None => return self.error("Expect expression."),
-            Some(rule) => rule(self, can_assign),
-        }
-        while precendence <= ParseRule::get_rule(self.current.token_type).precedence {
-            self.advance();
-            let infix_rule = ParseRule::get_rule(self.previous.token_type).infix.unwrap();
-            infix_rule(self, can_assign);
-        }
-        if can_assign && self.r#match(TokenType::Equal) {
-            self.error("Invalid assignment target.");
-        }
-    }
-
-    fn string(&mut self, _can_assign: bool) {
-        let s = String::from(self.previous.value);
-        self.emit_constant(Value::from(s[1..s.len() - 1].to_string()));
-    }
-
-    fn emit_constant(&mut self, value: Value) {
-        let constant = self.make_constant(value);
-        self.emit_bytes(OpCode::Constant, constant);
-    }
-
-    fn make_constant(&mut self, value: Value) -> u8 {
-        let constant = self.compiler.function.chunk.add_constant(value);
-        if constant > u8::MAX as usize {
-            self.error("Too many constants in one chunk.");
-            0
-        } else {
-            constant as u8
-        }
-    }
-
-    fn and(&mut self, _can_assign: bool) {
-        let end_jump = self.emit_jump(OpCode::JumpIfFalse);
-        self.emit_byte(OpCode::Pop);
-        self.parse_precendence(Precedence::And);
-        self.patch_jump(end_jump);
-    }
-
-    fn or(&mut self, _can_assign: bool) {
-        let else_jump = self.emit_jump(OpCode::JumpIfFalse);
-        let end_jump = self.emit_jump(OpCode::Jump);
-        self.patch_jump(else_jump);
-        self.emit_byte(OpCode::Pop);
-        self.parse_precendence(Precedence::Or);
-        self.patch_jump(end_jump);
+        errors == 0
     }
 }
@@ -0,0 +1,121 @@
+use crate::chunk::{Chunk, OpCode};
+
+use std::collections::HashSet;
+
+
+/// Rewrites short instruction sequences of `chunk` into cheaper ones until
+/// none is left:
+///
+/// - `Jump 0` is removed,
+/// - a literal that is popped right away is removed,
+/// - `Not; JumpIfFalse` becomes `JumpIfTrue` when both of its successors pop
+///   the condition, so that it doesn't matter which of the two is left,
+/// - `GetLocal; Constant; Add` becomes `AddLocalConstant`.
+///
+/// Sequences some jump lands in the middle of are left alone.
+pub fn optimize(chunk: &mut Chunk) {
+    while pass(chunk) {}
+}
+
+fn pass(chunk: &mut Chunk) -> bool {
+    use OpCode::*;
+    let offsets = instructions(chunk);
+    let targets: HashSet<usize> = offsets.iter().filter_map(|&offset| jump_target(chunk, offset)).collect();
+    let op = |i: usize| offsets.get(i).map(|&offset| OpCode::from(chunk.code[offset]));
+    let is_target = |i: usize| offsets.get(i).is_some_and(|offset| targets.contains(offset));
+
+    let mut code = Vec::with_capacity(chunk.code.len());
+    let mut lines = Vec::with_capacity(chunk.lines.len());
+    let mut spans = Vec::with_capacity(chunk.spans.len());
+    // New offset of every old instruction, and of the end of the code.
+    let mut moved = vec![0; chunk.code.len() + 1];
+    // New offset of every jump, and the old offset it jumps to.
+    let mut jumps = Vec::new();
+    let mut changed = false;
+
+    let mut i = 0;
+    while i < offsets.len() {
+        let offset = offsets[i];
+        moved[offset] = code.len();
+        let removed = match (op(i).unwrap(), op(i + 1), op(i + 2)) {
+            (Jump, ..) if jump_target(chunk, offset) == offsets.get(i + 1).copied() => 1,
+            (Constant | Nil | True | False, Some(Pop), _) if !is_target(i + 1) => 2,
+            (Not, Some(JumpIfFalse), Some(Pop)) if !is_target(i + 1) && pops(chunk, jump_target(chunk, offsets[i + 1])) => {
+                let jump = offsets[i + 1];
+                jumps.push((code.len(), jump_target(chunk, jump).unwrap()));
+                for byte in [JumpIfTrue.into(), 0, 0] {
+                    code.push(byte);
+                    lines.push(chunk.lines[jump]);
+                    spans.push(chunk.spans[jump]);
+                }
+                2
+            }
+            (GetLocal, Some(Constant), Some(Add)) if !is_target(i + 1) && !is_target(i + 2) => {
+                let add = offsets[i + 2];
+                for byte in [AddLocalConstant.into(), chunk.code[offset + 1], chunk.code[offsets[i + 1] + 1]] {
+                    code.push(byte);
+                    lines.push(chunk.lines[add]);
+                    spans.push(chunk.spans[add]);
+                }
+                3
+            }
+            _ => 0,
+        };
+        if removed > 0 {
+            for &offset in &offsets[i + 1..i + removed] {
+                moved[offset] = code.len();
+            }
+            i += removed;
+            changed = true;
+            continue;
+        }
+
+        if let Some(target) = jump_target(chunk, offset) {
+            jumps.push((code.len(), target));
+        }
+        let end = offset + chunk.instruction_len(offset);
+        code.extend_from_slice(&chunk.code[offset..end]);
+        lines.extend_from_slice(&chunk.lines[offset..end]);
+        spans.extend_from_slice(&chunk.spans[offset..end]);
+        i += 1;
+    }
+    moved[chunk.code.len()] = code.len();
+
+    for (at, target) in jumps {
+        let target = moved[target];
+        let distance = match OpCode::from(code[at]) {
+            Loop    => at + 3 - target,
+            _       => target - (at + 3),
+        };
+        code[at + 1] = ((distance >> 8) & 0xff) as u8;
+        code[at + 2] = (distance & 0xff) as u8;
+    }
+    chunk.code = code;
+    chunk.lines = lines;
+    chunk.spans = spans;
+    changed
+}
+
+fn instructions(chunk: &Chunk) -> Vec<usize> {
+    let mut offsets = Vec::new();
+    let mut offset = 0;
+    while offset < chunk.code.len() {
+        offsets.push(offset);
+        offset += chunk.instruction_len(offset);
+    }
+    offsets
+}
+
+/// The offset the jump at `offset` lands on, if it is a jump.
+fn jump_target(chunk: &Chunk, offset: usize) -> Option<usize> {
+    let distance = || ((chunk.code[offset + 1] as usize) << 8) | chunk.code[offset + 2] as usize;
+    match chunk.code[offset].into() {
+        OpCode::Jump | OpCode::JumpIfFalse | OpCode::JumpIfTrue => Some(offset + 3 + distance()),
+        OpCode::Loop => Some(offset + 3 - distance()),
+        _ => None,
+    }
+}
+
+fn pops(chunk: &Chunk, offset: Option<usize>) -> bool {
+    offset.is_some_and(|offset| chunk.code.get(offset).is_some_and(|&byte| OpCode::from(byte) == OpCode::Pop))
+}
@@ -0,0 +1,69 @@
+use crate::compiler::{Warning, Warnings};
+use crate::diagnostic::{Diagnostic, Severity, Span};
+
+
+/// A diagnostic along with the offset in the source the compiler is at when
+/// reporting it, which orders the diagnostics of all passes as if they had
+/// been found in a single one.
+#[derive(Debug, Clone)]
+pub(super) struct Report {
+    pub offset:     usize,
+    pub diagnostic: Diagnostic,
+}
+
+/// Collects the diagnostics of a pass over the tree. Like the parser, a pass
+/// reports nothing more in a statement once it found an error in it.
+pub(super) struct Reporter<'a> {
+    text:           &'a str,
+    warnings:       Warnings,
+    pub reports:    Vec<Report>,
+    pub panic_mode: bool,
+    pub had_error:  bool,
+}
+
+impl<'a> Reporter<'a> {
+    pub fn new(text: &'a str, warnings: Warnings) -> Self {
+        Self {
+            text,
+            warnings,
+            reports: Vec::new(),
+            panic_mode: false,
+            had_error: false,
+        }
+    }
+
+    /// Reports an error at the token spanning `token`.
+    pub fn error(&mut self, token: Span, message: &str) {
+        if self.panic_mode {
+            return;
+        }
+        self.panic_mode = true;
+        self.had_error = true;
+        self.report(token.offset, Severity::Error, token, token, message);
+    }
+
+    /// Reports a warning at `token` underlining `span`, once the compiler
+    /// is at `offset`.
+    pub fn warning(&mut self, warning: Warning, offset: usize, token: Span, span: Span, message: &str) {
+        if self.panic_mode || !self.warnings.is_enabled(warning) {
+            return;
+        }
+        self.report(offset, Severity::Warning, token, span, message);
+    }
+
+    fn report(&mut self, offset: usize, severity: Severity, token: Span, span: Span, message: &str) {
+        // Only the end of the source is empty.
+        let location = match self.text.get(token.offset..token.offset + token.len) {
+            Some(lexeme) if !lexeme.is_empty()  => format!(" at '{lexeme}'"),
+            _                                   => " at end".to_string(),
+        };
+        let diagnostic = Diagnostic {
+            severity,
+            message: message.to_string(),
+            line: token.line,
+            span,
+            location,
+        };
+        self.reports.push(Report { offset, diagnostic });
+    }
+}
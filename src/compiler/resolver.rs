@@ -0,0 +1,385 @@
+use crate::ast::{Binding, Capture, Expr, ExprKind, Function, Identifier, Script, Stmt, StmtKind};
+use crate::compiler::report::{Report, Reporter};
+use crate::compiler::{Warning, Warnings};
+use crate::diagnostic::Span;
+
+use std::cell::Cell;
+use std::collections::HashSet;
+
+
+/// What a variable is bound to after an error resolving it, which is not
+/// taken for a global to avoid reporting it as an undeclared one as well.
+const UNRESOLVED: Binding = Binding::Local { depth: 0, index: 0, slot: 0 };
+
+struct Local<'a> {
+    name:       &'a str,
+    span:       Span,
+    /// Scope depth of the local, -1 until its initializer is resolved.
+    depth:      i32,
+    used:       bool,
+    parameter:  bool,
+    /// Set once a closure captures the local. `None` for parameters, which
+    /// are never popped by their function.
+    captured:   Option<&'a Cell<bool>>,
+}
+
+/// The script or a function being resolved.
+struct FunctionScope<'a> {
+    /// The stack slots of the function, slot zero included.
+    locals:         Vec<Local<'a>>,
+    scope_depth:    i32,
+    upvalues:       Vec<Capture>,
+    is_script:      bool,
+}
+
+/// Binds every variable of a tree to a global, a stack slot or an upvalue,
+/// lists the upvalues of every function and marks the locals closures
+/// capture. Reports the errors about names and scopes and the warnings that
+/// don't depend on the compiled code.
+pub(super) struct Resolver<'a> {
+    reporter:           Reporter<'a>,
+    /// The script and every function being resolved, innermost last.
+    functions:          Vec<FunctionScope<'a>>,
+    declared_globals:   &'a mut HashSet<String>,
+    /// Assignments to globals, checked against every declaration once the whole
+    /// source is resolved since functions may assign globals declared after them.
+    assigned_globals:   Vec<&'a Identifier>,
+    /// Set after a `return` statement, to find the statements following it.
+    returned:           bool,
+}
+
+impl<'a> FunctionScope<'a> {
+    fn new(is_script: bool) -> Self {
+        let reserved = Local {
+            name: "",
+            span: Span::default(),
+            depth: 0,
+            used: true,
+            parameter: false,
+            captured: None,
+        };
+        Self {
+            locals: vec![reserved],
+            scope_depth: 0,
+            upvalues: Vec::new(),
+            is_script,
+        }
+    }
+
+    fn resolve_local(&self, name: &str) -> Result<Option<usize>, &'static str> {
+        match self.locals.iter().rposition(|local| local.name == name) {
+            Some(slot) if self.locals[slot].depth == -1 => Err("Can't read local variable in its own initializer."),
+            slot                                        => Ok(slot),
+        }
+    }
+
+    fn add_upvalue(&mut self, index: u8, is_local: bool) -> Result<u8, &'static str> {
+        let capture = Capture { index, is_local };
+        if let Some(upvalue) = self.upvalues.iter().position(|upvalue| *upvalue == capture) {
+            return Ok(upvalue as u8);
+        }
+        if self.upvalues.len() > u8::MAX as usize {
+            return Err("Too many closure variables in function.");
+        }
+        self.upvalues.push(capture);
+        Ok((self.upvalues.len() - 1) as u8)
+    }
+}
+
+impl<'a> Resolver<'a> {
+    pub fn new(text: &'a str, warnings: Warnings, declared_globals: &'a mut HashSet<String>) -> Self {
+        Self {
+            reporter: Reporter::new(text, warnings),
+            functions: vec![FunctionScope::new(true)],
+            declared_globals,
+            assigned_globals: Vec::new(),
+            returned: false,
+        }
+    }
+
+    pub fn into_reports(self) -> Vec<Report> {
+        self.reporter.reports
+    }
+
+    pub fn resolve(&mut self, script: &'a Script) {
+        for statement in &script.statements {
+            self.declaration(statement);
+        }
+        for name in std::mem::take(&mut self.assigned_globals) {
+            if !self.declared_globals.contains(&name.name) {
+                let message = format!("Assignment to undeclared global '{}'.", name.name);
+                self.reporter.warning(Warning::UndeclaredGlobal, name.span.offset, name.span, name.span, &message);
+            }
+        }
+    }
+
+    fn declaration(&mut self, statement: &'a Stmt) {
+        match &statement.kind {
+            StmtKind::Function(function) => {
+                self.declare_variable(&function.name, Some(&function.captured));
+                self.mark_initialized();
+                self.function(function, statement.end);
+            }
+            StmtKind::Var { .. } => self.var_declaration(statement),
+            _ => self.statement(statement),
+        }
+        self.reporter.panic_mode = false;
+    }
+
+    fn var_declaration(&mut self, statement: &'a Stmt) {
+        let StmtKind::Var { name, initializer, captured } = &statement.kind else {
+            unreachable!("not a variable declaration");
+        };
+        self.declare_variable(name, Some(captured));
+        if let Some(initializer) = initializer {
+            self.expression(initializer);
+        }
+        self.mark_initialized();
+    }
+
+    fn function(&mut self, function: &'a Function, end: Span) {
+        self.functions.push(FunctionScope::new(false));
+        self.begin_scope();
+        for param in &function.params {
+            self.declare_variable(param, None);
+            self.mark_initialized();
+            if let Some(local) = self.current().locals.last_mut() {
+                local.parameter = true;
+            }
+            self.reporter.panic_mode = false;
+        }
+        self.block(&function.body);
+        // A return at the end of the body doesn't make the code after the
+        // declaration unreachable.
+        self.returned = false;
+        // Function bodies never end their outermost scope, the frame is simply
+        // discarded on return.
+        for slot in 1..self.current().locals.len() {
+            self.warn_unused(slot, end.offset);
+        }
+        let scope = self.functions.pop().unwrap();
+        *function.upvalues.borrow_mut() = scope.upvalues;
+    }
+
+    fn statement(&mut self, statement: &'a Stmt) {
+        match &statement.kind {
+            StmtKind::Expression(expr) | StmtKind::Print(expr) => self.expression(expr),
+            StmtKind::Block(statements) => {
+                self.begin_scope();
+                self.block(statements);
+                self.end_scope(statement.end.offset);
+            }
+            StmtKind::If { condition, then_branch, else_branch, .. } => {
+                self.expression(condition);
+                self.nested_statement(then_branch);
+                if let Some(else_branch) = else_branch {
+                    self.nested_statement(else_branch);
+                }
+            }
+            StmtKind::While { condition, body, .. } => {
+                self.expression(condition);
+                self.nested_statement(body);
+            }
+            StmtKind::For { initializer, condition, increment, body, .. } => {
+                self.begin_scope();
+                match initializer.as_deref() {
+                    Some(initializer @ Stmt { kind: StmtKind::Var { .. }, .. }) => self.var_declaration(initializer),
+                    Some(initializer) => self.statement(initializer),
+                    None => (),
+                }
+                if let Some(condition) = condition {
+                    self.expression(condition);
+                }
+                if let Some(increment) = increment {
+                    self.expression(increment);
+                }
+                self.nested_statement(body);
+                self.end_scope(statement.end.offset);
+            }
+            StmtKind::Return(value) => {
+                if self.current().is_script {
+                    self.reporter.error(statement.start, "Can't return from top-level code.");
+                }
+                if let Some(value) = value {
+                    self.expression(value);
+                }
+                self.returned = true;
+            }
+            StmtKind::Var { .. } | StmtKind::Function(_) => self.declaration(statement),
+        }
+    }
+
+    fn nested_statement(&mut self, statement: &'a Stmt) {
+        self.statement(statement);
+        // The other branch or the next iteration may still run what follows.
+        self.returned = false;
+    }
+
+    fn block(&mut self, statements: &'a [Stmt]) {
+        let mut unreachable = false;
+        for statement in statements {
+            if self.returned && !unreachable {
+                let start = statement.start;
+                self.reporter.warning(Warning::UnreachableCode, start.offset, start, start, "Unreachable code.");
+                unreachable = true;
+            }
+            self.returned = false;
+            self.declaration(statement);
+        }
+    }
+
+    fn expression(&mut self, expr: &'a Expr) {
+        match &expr.kind {
+            ExprKind::Literal(_) => (),
+            ExprKind::Variable(variable) => {
+                let binding = self.resolve_variable(&variable.name);
+                if let Binding::Local { slot, .. } = binding {
+                    self.current().locals[slot as usize].used = true;
+                }
+                variable.binding.set(binding);
+            }
+            ExprKind::Assign(variable, value) => {
+                let binding = self.resolve_variable(&variable.name);
+                if binding == Binding::Global {
+                    self.assigned_globals.push(&variable.name);
+                }
+                variable.binding.set(binding);
+                self.expression(value);
+            }
+            ExprKind::Unary { operand, .. } => self.expression(operand),
+            ExprKind::Binary { left, right, .. } | ExprKind::Logical { left, right, .. } => {
+                self.expression(left);
+                self.expression(right);
+            }
+            ExprKind::Call(callee, arguments) => {
+                self.expression(callee);
+                for argument in arguments {
+                    self.expression(argument);
+                    // The parser resumes at the next argument after an error.
+                    self.reporter.panic_mode = false;
+                }
+            }
+            ExprKind::Grouping(inner) => self.expression(inner),
+        }
+    }
+
+    fn resolve_variable(&mut self, name: &Identifier) -> Binding {
+        let innermost = self.functions.len() - 1;
+        for function in (0..=innermost).rev() {
+            let slot = match self.functions[function].resolve_local(&name.name) {
+                Ok(Some(slot))  => slot,
+                Ok(None)        => continue,
+                Err(message)    => {
+                    self.reporter.error(name.span, message);
+                    return UNRESOLVED;
+                }
+            };
+            let (depth, index) = self.locate(function, slot);
+            if function == innermost {
+                return Binding::Local { depth, index, slot: slot as u8 };
+            }
+            let local = &mut self.functions[function].locals[slot];
+            local.used = true;
+            if let Some(captured) = local.captured {
+                captured.set(true);
+            }
+            // Every function from the one declaring the local in gets an
+            // upvalue for it, each one capturing that of the function around it.
+            let (mut index_in_enclosing, mut is_local) = (slot as u8, true);
+            for scope in &mut self.functions[function + 1..] {
+                match scope.add_upvalue(index_in_enclosing, is_local) {
+                    Ok(upvalue)     => (index_in_enclosing, is_local) = (upvalue, false),
+                    Err(message)    => {
+                        self.reporter.error(name.span, message);
+                        return UNRESOLVED;
+                    }
+                }
+            }
+            return Binding::Upvalue { depth, index, upvalue: index_in_enclosing };
+        }
+        Binding::Global
+    }
+
+    /// How many scopes out from the current one the local in `slot` of
+    /// `function` is declared, and its index among the locals of that scope.
+    fn locate(&self, function: usize, slot: usize) -> (usize, usize) {
+        let scope = &self.functions[function];
+        let local = &scope.locals[slot];
+        let index = scope.locals[..slot].iter().rev().take_while(|other| other.depth == local.depth).count();
+        let depth = (scope.scope_depth - local.depth) as usize
+            + self.functions[function + 1..].iter().map(|inner| inner.scope_depth as usize).sum::<usize>();
+        (depth, index)
+    }
+
+    fn current(&mut self) -> &mut FunctionScope<'a> {
+        self.functions.last_mut().unwrap()
+    }
+
+    fn declare_variable(&mut self, name: &'a Identifier, captured: Option<&'a Cell<bool>>) {
+        let scope_depth = self.current().scope_depth;
+        if scope_depth == 0 {
+            self.declared_globals.insert(name.name.clone());
+            return;
+        }
+        let locals = &self.current().locals;
+        let duplicate = locals.iter().rev()
+            .take_while(|local| local.depth == -1 || local.depth >= scope_depth)
+            .any(|local| local.name == name.name);
+        if duplicate {
+            self.reporter.error(name.span, "Already a variable with this name in this scope.");
+        } else if locals.iter().any(|local| local.depth > 0 && local.depth < scope_depth && local.name == name.name) {
+            let message = format!("Variable '{}' shadows a variable in an enclosing scope.", name.name);
+            self.reporter.warning(Warning::Shadowing, name.span.offset, name.span, name.span, &message);
+        }
+        if self.current().locals.len() > u8::MAX as usize {
+            self.reporter.error(name.span, "Too many local variables in function.");
+            return;
+        }
+        self.current().locals.push(Local {
+            name: &name.name,
+            span: name.span,
+            depth: -1,
+            used: false,
+            parameter: false,
+            captured,
+        });
+    }
+
+    fn mark_initialized(&mut self) {
+        let scope = self.current();
+        if scope.scope_depth > 0 {
+            scope.locals.last_mut().unwrap().depth = scope.scope_depth;
+        }
+    }
+
+    fn begin_scope(&mut self) {
+        self.current().scope_depth += 1;
+    }
+
+    /// Ends the innermost scope, with the compiler at `offset`.
+    fn end_scope(&mut self, offset: usize) {
+        self.current().scope_depth -= 1;
+        while let Some(local) = self.current().locals.last() {
+            if local.depth <= self.current().scope_depth {
+                break;
+            }
+            let slot = self.current().locals.len() - 1;
+            self.warn_unused(slot, offset);
+            self.current().locals.pop();
+        }
+    }
+
+    fn warn_unused(&mut self, slot: usize, offset: usize) {
+        let local = &self.functions.last().unwrap().locals[slot];
+        if local.used || local.name.starts_with('_') {
+            return;
+        }
+        let message = if local.parameter {
+            format!("Unused parameter '{}'.", local.name)
+        } else {
+            format!("Unused variable '{}'.", local.name)
+        };
+        let span = local.span;
+        self.reporter.warning(Warning::UnusedVariable, offset, span, span, &message);
+    }
+}
@@ -0,0 +1,67 @@
+/// Checks the compiler runs besides reporting errors. Every one of them can be
+/// turned off on its own.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum Warning {
+    UnusedVariable,
+    UnreachableCode,
+    Shadowing,
+    UndeclaredGlobal,
+    ConstantComparison,
+}
+
+impl Warning {
+    pub const ALL: [Warning; 5] = [
+        Warning::UnusedVariable,
+        Warning::UnreachableCode,
+        Warning::Shadowing,
+        Warning::UndeclaredGlobal,
+        Warning::ConstantComparison,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Warning::UnusedVariable     => "unused-variable",
+            Warning::UnreachableCode    => "unreachable-code",
+            Warning::Shadowing          => "shadowing",
+            Warning::UndeclaredGlobal   => "undeclared-global",
+            Warning::ConstantComparison => "constant-comparison",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Warning> {
+        Self::ALL.into_iter().find(|warning| warning.name() == name)
+    }
+}
+
+/// The set of enabled warnings, all of them by default.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Warnings(u8);
+
+impl Default for Warnings {
+    fn default() -> Self {
+        Self::all()
+    }
+}
+
+impl Warnings {
+    pub fn all() -> Self {
+        Self(Warning::ALL.iter().fold(0, |bits, warning| bits | 1 << *warning as u8))
+    }
+
+    pub fn none() -> Self {
+        Self(0)
+    }
+
+    pub fn enable(&mut self, warning: Warning) {
+        self.0 |= 1 << warning as u8;
+    }
+
+    pub fn disable(&mut self, warning: Warning) {
+        self.0 &= !(1 << warning as u8);
+    }
+
+    pub fn is_enabled(&self, warning: Warning) -> bool {
+        self.0 & (1 << warning as u8) != 0
+    }
+}
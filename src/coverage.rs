@@ -0,0 +1,164 @@
+use crate::chunk::OpCode;
+use crate::value::{Function, Value};
+
+use std::collections::{BTreeMap, HashMap};
+use std::io;
+use std::rc::Rc;
+
+
+struct FunctionCoverage {
+    file:       usize,
+    function:   Rc<Function>,
+    hits:       Vec<u64>,
+    calls:      u64,
+    /// Outcomes of every `JumpIfFalse` and `JumpIfTrue`, keyed by its offset: (fell through, jumped).
+    branches:   BTreeMap<usize, (u64, u64)>,
+}
+
+#[derive(Default)]
+pub struct Coverage {
+    /// The name of every registered source and its number of lines.
+    files:      Vec<(String, usize)>,
+    functions:  Vec<FunctionCoverage>,
+    index:      HashMap<*const Function, usize>,
+}
+
+impl Coverage {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registers a compiled script and every function nested in it, so that
+    /// code which never runs is still reported.
+    pub fn register(&mut self, file: &str, source: &str, function: &Rc<Function>) {
+        let lines = source.lines().count();
+        let file = match self.files.iter().position(|(f, _)| f == file) {
+            Some(index) => {
+                self.files[index].1 = self.files[index].1.max(lines);
+                index
+            }
+            None        => {
+                self.files.push((file.to_string(), lines));
+                self.files.len() - 1
+            }
+        };
+        self.register_function(file, function);
+    }
+
+    fn register_function(&mut self, file: usize, function: &Rc<Function>) {
+        if self.index.contains_key(&Rc::as_ptr(function)) {
+            return;
+        }
+        self.index.insert(Rc::as_ptr(function), self.functions.len());
+        self.functions.push(FunctionCoverage {
+            file,
+            function: Rc::clone(function),
+            hits: vec![0; function.chunk.code.len()],
+            calls: 0,
+            branches: BTreeMap::new(),
+        });
+        for constant in &function.chunk.constants {
+            if let Value::Function(nested) = constant {
+                self.register_function(file, nested);
+            }
+        }
+    }
+
+    pub fn call(&mut self, function: &Rc<Function>) {
+        if let Some(&index) = self.index.get(&Rc::as_ptr(function)) {
+            self.functions[index].calls += 1;
+        }
+    }
+
+    pub fn instruction(&mut self, function: &Rc<Function>, offset: usize) {
+        if let Some(&index) = self.index.get(&Rc::as_ptr(function)) {
+            self.functions[index].hits[offset] += 1;
+        }
+    }
+
+    pub fn branch(&mut self, function: &Rc<Function>, offset: usize, jumped: bool) {
+        if let Some(&index) = self.index.get(&Rc::as_ptr(function)) {
+            let outcome = self.functions[index].branches.entry(offset).or_insert((0, 0));
+            if jumped {
+                outcome.1 += 1;
+            } else {
+                outcome.0 += 1;
+            }
+        }
+    }
+
+    /// Writes one lcov record per registered file.
+    pub fn write_lcov<W: io::Write>(&self, out: &mut W) -> io::Result<()> {
+        writeln!(out, "TN:")?;
+        for (file, (name, count)) in self.files.iter().enumerate() {
+            let functions: Vec<&FunctionCoverage> = self.functions.iter()
+                .filter(|f| f.file == file)
+                .collect();
+            writeln!(out, "SF:{name}")?;
+
+            let named: Vec<&&FunctionCoverage> = functions.iter()
+                .filter(|f| !f.function.name.is_empty())
+                .collect();
+            for function in &named {
+                writeln!(out, "FN:{},{}", function.function.line, function.function.name)?;
+            }
+            for function in &named {
+                writeln!(out, "FNDA:{},{}", function.calls, function.function.name)?;
+            }
+            writeln!(out, "FNF:{}", named.len())?;
+            writeln!(out, "FNH:{}", named.iter().filter(|f| f.calls > 0).count())?;
+
+            let mut lines: BTreeMap<usize, u64> = BTreeMap::new();
+            let mut branches: Vec<(usize, Option<(u64, u64)>)> = Vec::new();
+            for function in &functions {
+                let chunk = &function.function.chunk;
+                let mut offset = 0;
+                while offset < chunk.code.len() {
+                    let hits = function.hits[offset];
+                    let line = lines.entry(chunk.lines[offset]).or_insert(0);
+                    *line = (*line).max(hits);
+                    if let OpCode::JumpIfFalse | OpCode::JumpIfTrue = chunk.code[offset].into() {
+                        let outcome = if hits == 0 {
+                            None
+                        } else {
+                            Some(function.branches.get(&offset).copied().unwrap_or((0, 0)))
+                        };
+                        branches.push((chunk.lines[offset], outcome));
+                    }
+                    offset += chunk.instruction_len(offset);
+                }
+            }
+            for (block, (line, outcome)) in branches.iter().enumerate() {
+                match outcome {
+                    Some((fell, jumped)) => {
+                        writeln!(out, "BRDA:{line},{block},0,{fell}")?;
+                        writeln!(out, "BRDA:{line},{block},1,{jumped}")?;
+                    }
+                    None => {
+                        writeln!(out, "BRDA:{line},{block},0,-")?;
+                        writeln!(out, "BRDA:{line},{block},1,-")?;
+                    }
+                }
+            }
+            let taken: usize = branches.iter()
+                .map(|(_, outcome)| match outcome {
+                    Some((fell, jumped)) => (*fell > 0) as usize + (*jumped > 0) as usize,
+                    None => 0,
+                })
+                .sum();
+            writeln!(out, "BRF:{}", branches.len() * 2)?;
+            writeln!(out, "BRH:{taken}")?;
+
+            // The implicit return at the end of a script is on the line
+            // after its last one, which lcov has nothing to show for.
+            lines.retain(|line, _| line <= count);
+            for (line, hits) in &lines {
+                writeln!(out, "DA:{line},{hits}")?;
+            }
+            writeln!(out, "LF:{}", lines.len())?;
+            writeln!(out, "LH:{}", lines.values().filter(|hits| **hits > 0).count())?;
+            writeln!(out, "end_of_record")?;
+        }
+        Ok(())
+    }
+}
@@ -1,120 +1,237 @@
-use crate::{chunk::{Chunk, OpCode}, value::Value};
+use crate::{chunk::{Chunk, OpCode}, json::Json, value::{Function, Value}};
+
+use std::fmt;
+use std::io;
+use std::rc::Rc;
+
+
+/// An operand of an instruction, as decoded from the bytes following its opcode.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operand {
+    /// A stack slot, an upvalue index or an argument count.
+    Byte(u8),
+    /// An index into the constants of the chunk.
+    Constant(u8),
+    /// The offset a jump or a loop continues at.
+    Jump(usize),
+    /// A variable a closure captures, see `ast::Capture`.
+    Capture { is_local: bool, index: u8 },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Instruction {
+    pub offset:     usize,
+    pub line:       usize,
+    pub opcode:     OpCode,
+    pub operands:   Vec<Operand>,
+}
+
+/// The instructions of a chunk in order, see `Disassembler::instructions`.
+pub struct Instructions<'a> {
+    chunk:  &'a Chunk,
+    offset: usize,
+}
 
 pub struct Disassembler<'a> {
     chunk: &'a Chunk,
 }
 
-impl<'a> Disassembler<'a> {
-    pub fn new(chunk: &'a Chunk) -> Self {
-        Self { chunk }
+fn mnemonic(opcode: OpCode) -> &'static str {
+    match opcode {
+        OpCode::Add                 => "ADD",
+        OpCode::AddLocalConstant    => "ADD LOCAL CONST",
+        OpCode::Call                => "CALL",
+        OpCode::CloseUpvalue        => "CLOSE UPVALUE",
+        OpCode::Closure             => "CLOSURE",
+        OpCode::Constant            => "CONSTANT",
+        OpCode::DefineGlobal        => "DEFINE GLOBAL",
+        OpCode::Divide              => "DIVIDE",
+        OpCode::Equal               => "EQUAL",
+        OpCode::False               => "FALSE",
+        OpCode::GetGlobal           => "GET GLOBAL",
+        OpCode::GetLocal            => "GET LOCAL",
+        OpCode::GetUpvalue          => "GET UPVALUE",
+        OpCode::Greater             => "GREATER",
+        OpCode::Jump                => "JUMP",
+        OpCode::JumpIfFalse         => "JUMP IF FALSE",
+        OpCode::JumpIfTrue          => "JUMP IF TRUE",
+        OpCode::Less                => "LESS",
+        OpCode::Loop                => "LOOP",
+        OpCode::Multiply            => "MULTIPLY",
+        OpCode::Negate              => "NEGATE",
+        OpCode::Nil                 => "NIL",
+        OpCode::Not                 => "NOT",
+        OpCode::Pop                 => "POP",
+        OpCode::Print               => "PRINT",
+        OpCode::Return              => "RETURN",
+        OpCode::SetGlobal           => "SET GLOBAL",
+        OpCode::SetLocal            => "SET LOCAL",
+        OpCode::SetUpvalue          => "SET UPVALUE",
+        OpCode::Subtract            => "SUBTRACT",
+        OpCode::TailCall            => "TAIL CALL",
+        OpCode::True                => "TRUE",
+    }
+}
+
+impl Instruction {
+    /// Decodes the instruction starting at `offset` of `chunk`.
+    pub fn decode(chunk: &Chunk, offset: usize) -> Self {
+        use OpCode::*;
+        let opcode: OpCode = chunk.code[offset].into();
+        let byte = |i: usize| chunk.code[offset + i];
+        let operands = match opcode {
+            Constant | GetGlobal | DefineGlobal | SetGlobal => vec![Operand::Constant(byte(1))],
+            GetLocal | SetLocal | GetUpvalue | SetUpvalue | Call | TailCall => vec![Operand::Byte(byte(1))],
+            AddLocalConstant => vec![Operand::Byte(byte(1)), Operand::Constant(byte(2))],
+            Jump | JumpIfFalse | JumpIfTrue | Loop => {
+                let jump = (byte(1) as usize) << 8 | byte(2) as usize;
+                let next = offset + 3;
+                vec![Operand::Jump(if opcode == Loop { next.wrapping_sub(jump) } else { next + jump })]
+            }
+            Closure => {
+                let mut operands = vec![Operand::Constant(byte(1))];
+                for i in (2..chunk.instruction_len(offset)).step_by(2) {
+                    operands.push(Operand::Capture { is_local: byte(i) != 0, index: byte(i + 1) });
+                }
+                operands
+            }
+            _ => Vec::new(),
+        };
+        Self { offset, line: chunk.lines[offset], opcode, operands }
     }
 
-    pub fn disassemble_chunk(&self, name: &str) {
-        println!("== {name} ==");
-        let mut offset = 0;
-        let count = self.chunk.code.len();
-        while offset < count {
-            offset = self.disassemble_instruction(offset);
+    pub fn to_json(&self, chunk: &Chunk) -> Json {
+        let operands = self.operands.iter().map(|operand| match *operand {
+            Operand::Byte(byte) => Json::object([("byte", Json::from(byte as usize))]),
+            Operand::Constant(constant) => Json::object([
+                ("constant", Json::from(constant as usize)),
+                ("value", Json::from(chunk.constants[constant as usize].to_string())),
+            ]),
+            Operand::Jump(target) => Json::object([("jump", Json::from(target))]),
+            Operand::Capture { is_local, index } => Json::object([
+                ("capture", Json::from(if is_local { "local" } else { "upvalue" })),
+                ("index", Json::from(index as usize)),
+            ]),
+        });
+        Json::object([
+            ("offset", Json::from(self.offset)),
+            ("line", Json::from(self.line)),
+            ("opcode", Json::from(format!("{:?}", self.opcode))),
+            ("operands", Json::Array(operands.collect())),
+        ])
+    }
+}
+
+impl<'a> Iterator for Instructions<'a> {
+    type Item = Instruction;
+
+    fn next(&mut self) -> Option<Instruction> {
+        if self.offset >= self.chunk.code.len() {
+            return None;
         }
+        let instruction = Instruction::decode(self.chunk, self.offset);
+        self.offset += self.chunk.instruction_len(self.offset);
+        Some(instruction)
     }
+}
 
-    fn constant_instruction(&self, name: &str, offset: usize) -> usize {
-        let constant = self.chunk.code[offset + 1] as usize;
-        println!("{name:<16} {constant:>4} '{}'", self.chunk.constants[constant]);
-        offset + 2
+impl<'a> Disassembler<'a> {
+    pub fn new(chunk: &'a Chunk) -> Self {
+        Self { chunk }
     }
 
-    fn simple_instruction(&self, name: &str, offset: usize) -> usize {
-        println!("{name}");
-        offset + 1
+    pub fn instructions(&self) -> Instructions<'a> {
+        Instructions { chunk: self.chunk, offset: 0 }
     }
 
-    fn byte_instruction(&self, name: &str, offset: usize) -> usize {
-        let slot = self.chunk.code[offset + 1] as usize;
-        println!("{name:<16} {slot:>4}");
-        offset + 2
+    /// Writes the chunk as a listing of its instructions under a `== name ==` header.
+    pub fn disassemble_chunk(&self, name: &str, out: &mut impl fmt::Write) -> fmt::Result {
+        writeln!(out, "== {name} ==")?;
+        for instruction in self.instructions() {
+            self.write_instruction(&instruction, out)?;
+        }
+        Ok(())
     }
 
-    fn jump_instruction(&self, name: &str, sign: i32, offset: usize) -> usize {
-        let mut jump = (self.chunk.code[offset + 1] as i16) << 8;
-        jump |= self.chunk.code[offset + 2] as i16;
-        println!("{name:<16} {offset:>4} -> {}", offset as i32 + 3 + sign * jump as i32);
-        offset + 3
+    /// `disassemble_chunk` for a writer of bytes, such as stdout or a file.
+    pub fn print_chunk(&self, name: &str, out: &mut impl io::Write) -> io::Result<()> {
+        let mut listing = String::new();
+        let _ = self.disassemble_chunk(name, &mut listing);
+        out.write_all(listing.as_bytes())
     }
 
-    pub fn disassemble_instruction(&self, mut offset: usize) -> usize {
-        print!("{offset:04} ");
+    /// Writes the instruction starting at `offset` and returns the offset of
+    /// the next one.
+    pub fn disassemble_instruction(&self, offset: usize, out: &mut impl fmt::Write) -> Result<usize, fmt::Error> {
+        self.write_instruction(&Instruction::decode(self.chunk, offset), out)?;
+        Ok(offset + self.chunk.instruction_len(offset))
+    }
+
+    fn write_instruction(&self, instruction: &Instruction, out: &mut impl fmt::Write) -> fmt::Result {
+        let offset = instruction.offset;
+        write!(out, "{offset:04} ")?;
         if offset > 0 && self.chunk.lines[offset] == self.chunk.lines[offset - 1] {
-            print!("   | ");
+            write!(out, "   | ")?;
         } else {
-            print!("{:>4} ", self.chunk.lines[offset]);
+            write!(out, "{:>4} ", instruction.line)?;
         }
-        let instruction = self.chunk.code[offset].into();
-        match instruction {
-            OpCode::Constant        => self.constant_instruction("CONSTANT", offset),
-            
-            OpCode::Nil             => self.simple_instruction("NIL", offset),
-            OpCode::True            => self.simple_instruction("TRUE", offset),
-            OpCode::False           => self.simple_instruction("FLASE", offset),
-            OpCode::Pop             => self.simple_instruction("POP", offset),
-
-            OpCode::GetLocal        => self.byte_instruction("GET LOCAL", offset),
-            OpCode::SetLocal        => self.byte_instruction("SET LOCAL", offset),
-            
-            OpCode::GetGlobal       => self.constant_instruction("GET GLOBAL", offset),
-            OpCode::DefineGlobal    => self.constant_instruction("DEFINE GLOBAL", offset),
-            OpCode::SetGlobal       => self.constant_instruction("SET GLOBAL", offset),
-
-            OpCode::Equal           => self.simple_instruction("EQUAL", offset),
-            OpCode::Greater         => self.simple_instruction("GREATER", offset),
-            OpCode::Less            => self.simple_instruction("LESS", offset),
-
-            OpCode::Add             => self.simple_instruction("ADD", offset),
-            OpCode::Subtract        => self.simple_instruction("SUBTRACT", offset),
-            OpCode::Multiply        => self.simple_instruction("MULTIPLY", offset),
-            OpCode::Divide          => self.simple_instruction("DIVIDE", offset),
-            OpCode::Not             => self.simple_instruction("NOT", offset),
-            OpCode::Negate          => self.simple_instruction("NEGATE", offset),
-
-            OpCode::Print           => self.simple_instruction("PRINT", offset),
-
-            OpCode::Jump            => self.jump_instruction("JUMP", 1, offset),
-            OpCode::JumpIfFalse     => self.jump_instruction("JUMP IF FALSE", 1, offset),
-            OpCode::Loop            => self.jump_instruction("LOOP", -1, offset),
-
-            OpCode::Call            => self.byte_instruction("CALL", offset),
-            OpCode::Closure         => {
-                offset += 1;
-                let constant = self.chunk.code[offset] as usize;
-                offset += 1;
-                println!("{:<16} {:>4} {}", "CLOSURE", constant, self.chunk.constants[constant]);
-                
-                if let Value::Function(function) = self.chunk.constants[constant].clone() {
-                    for _ in 0..function.upvalue_count {
-                        let is_local = self.chunk.code[offset];
-                        offset += 1;
-                        let index = self.chunk.code[offset];
-                        offset += 1;
-                        println!("{:04}    |                       {} {}", offset - 2, if is_local as u8 != 0 {"local"} else {"upvalue"}, index as u8)
+        let name = mnemonic(instruction.opcode);
+        let constant = |index: u8| &self.chunk.constants[index as usize];
+        match instruction.operands[..] {
+            [] => writeln!(out, "{name}"),
+            [Operand::Byte(byte)] => writeln!(out, "{name:<16} {byte:>4}"),
+            [Operand::Constant(index)] => writeln!(out, "{name:<16} {index:>4} '{}'", constant(index)),
+            [Operand::Jump(target)] => writeln!(out, "{name:<16} {offset:>4} -> {target}"),
+            [Operand::Byte(slot), Operand::Constant(index)] => {
+                writeln!(out, "{name:<16} {slot:>4} {index:>4} '{}'", constant(index))
+            }
+            [Operand::Constant(index), ref captures @ ..] => {
+                writeln!(out, "{name:<16} {index:>4} '{}'", constant(index))?;
+                for (i, capture) in captures.iter().enumerate() {
+                    if let Operand::Capture { is_local, index } = capture {
+                        let kind = if *is_local { "local" } else { "upvalue" };
+                        writeln!(out, "{:04}    |                       {kind} {index}", offset + 2 + 2 * i)?;
                     }
                 }
-
-                offset
+                Ok(())
             }
+            _ => writeln!(out, "{name} {:?}", instruction.operands),
+        }
+    }
+}
 
-            OpCode::CloseUpvalue    => self.simple_instruction("CLOSE UPVALUE", offset),
-
-            OpCode::GetUpvalue      => self.byte_instruction("GET UPVALUE", offset),
-            OpCode::SetUpvalue      => self.byte_instruction("GET SETVALUE", offset),
-
-            OpCode::Return          => self.simple_instruction("RETURN", offset),
-            #[allow(unreachable_patterns)]
-            _                       => {
-                println!("Unknown opcode");
-                offset + 1
-            }
-            
+/// `function` and the functions among its constants, their own included,
+/// in the order they are declared.
+pub fn functions(function: &Rc<Function>) -> Vec<Rc<Function>> {
+    let mut functions = vec![Rc::clone(function)];
+    for constant in &function.chunk.constants {
+        if let Value::Function(nested) = constant {
+            functions.extend(self::functions(nested));
         }
     }
+    functions
+}
+
+/// The name of `function` in listings: `<script>` for the top level.
+pub fn function_name(function: &Function) -> &str {
+    if function.name.is_empty() { "<script>" } else { &function.name }
+}
 
+/// `function` and the functions declared in it as JSON: an object with its
+/// name, arity, upvalue count, instructions and the same for every nested
+/// function.
+pub fn function_json(function: &Function) -> Json {
+    let chunk = &function.chunk;
+    let code = Disassembler::new(chunk).instructions().map(|instruction| instruction.to_json(chunk));
+    let nested = chunk.constants.iter().filter_map(|constant| match constant {
+        Value::Function(nested) => Some(function_json(nested)),
+        _                       => None,
+    });
+    Json::object([
+        ("name", Json::from(function_name(function))),
+        ("arity", Json::from(function.arity)),
+        ("upvalues", Json::from(function.upvalue_count)),
+        ("code", Json::Array(code.collect())),
+        ("functions", Json::Array(nested.collect())),
+    ])
 }
@@ -0,0 +1,101 @@
+use std::fmt::Write as _;
+
+
+/// Location of a token or an expression in the source, `line` and `column`
+/// are 1-based and `offset` and `len` are in bytes.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Span {
+    pub line:   usize,
+    pub column: usize,
+    pub offset: usize,
+    pub len:    usize,
+}
+
+impl Span {
+    /// The span covering both `self` and `other`, which must come after it.
+    pub fn to(self, other: Span) -> Span {
+        Span {
+            len: (other.offset + other.len).saturating_sub(self.offset),
+            ..self
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+/// An error or a warning reported by the compiler.
+#[derive(Debug, Clone)]
+pub struct Diagnostic {
+    pub severity:   Severity,
+    pub message:    String,
+    pub line:       usize,
+    pub span:       Span,
+    /// What the diagnostic points at, e.g. ` at 'x'` or ` at end`.
+    pub location:   String,
+}
+
+impl Diagnostic {
+    /// The `[line N] Error at 'x': message` header followed by the source snippet.
+    pub fn render(&self, source: &Source) -> String {
+        let severity = match self.severity {
+            Severity::Error     => "Error",
+            Severity::Warning   => "Warning",
+        };
+        format!("[line {}] {severity}{}: {}\n{}", self.line, self.location, self.message, source.snippet(self.span))
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Source {
+    pub name:   String,
+    pub text:   String,
+}
+
+impl Source {
+    pub fn new(name: &str, text: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            text: text.to_string(),
+        }
+    }
+
+    /// Renders the line `span` starts on with the span underlined, e.g.
+    ///
+    /// ```text
+    ///  --> script.lox:3:11
+    ///   |
+    /// 3 | print 1 + ;
+    ///   |           ^
+    /// ```
+    pub fn snippet(&self, span: Span) -> String {
+        let mut out = String::new();
+        // Only the end of the source lies past its last line, e.g. after its
+        // final newline, and it is shown right after the last character.
+        let mut offset = span.offset.min(self.text.trim_end().len());
+        while !self.text.is_char_boundary(offset) {
+            offset -= 1;
+        }
+        let line_start = self.text[..offset].rfind('\n').map_or(0, |i| i + 1);
+        let line_end = self.text[offset..].find('\n').map_or(self.text.len(), |i| offset + i);
+        let line = self.text[line_start..line_end].trim_end_matches('\r');
+        let number = self.text[..line_start].matches('\n').count() + 1;
+        let prefix = &self.text[line_start..offset];
+        let column = prefix.chars().count() + 1;
+
+        let gutter = " ".repeat(number.to_string().len());
+        let _ = writeln!(out, "{gutter}--> {}:{number}:{column}", self.name);
+        let _ = writeln!(out, "{gutter} |");
+        let _ = writeln!(out, "{number} | {line}");
+
+        // Tabs are kept so the underline lines up with the source above it.
+        let indent: String = prefix.chars().map(|c| if c == '\t' { '\t' } else { ' ' }).collect();
+        let end = (offset + span.len).min(line_start + line.len());
+        let width = self.text.get(offset..end).map_or(0, |s| s.chars().count()).max(1);
+        let _ = writeln!(out, "{gutter} | {indent}^{}", "~".repeat(width - 1));
+        out
+    }
+}
@@ -0,0 +1,255 @@
+use crate::ast::{Builder, Expr, ExprKind, Literal, Stmt, StmtKind};
+use crate::diagnostic::{Diagnostic, Span};
+
+
+const INDENT: &str = "  ";
+
+/// Formats a script the canonical way: one statement per line, blocks
+/// indented by two spaces, one space around binary operators and after
+/// commas, and at most one blank line between statements. Comments are kept,
+/// those inside a statement's header or expressions move to the line above it.
+/// Returns the syntax errors instead if there are any.
+pub fn format(source: &str) -> Result<String, Vec<Diagnostic>> {
+    let mut builder = Builder::new(source);
+    builder.keep_comments();
+    let script = builder.build();
+    if builder.had_error() {
+        return Err(builder.diagnostics().to_vec());
+    }
+    let mut formatter = Formatter {
+        text: source,
+        comments: builder.comments(),
+        next_comment: 0,
+        out: String::new(),
+        depth: 0,
+        last_line: 0,
+        block_start: true,
+    };
+    formatter.statements(&script.statements);
+    formatter.comments_before(usize::MAX);
+    Ok(formatter.out)
+}
+
+struct Formatter<'a> {
+    text:           &'a str,
+    comments:       &'a [Span],
+    /// The first comment not written yet.
+    next_comment:   usize,
+    out:            String,
+    depth:          usize,
+    /// Source line of the last statement or comment written, to keep the
+    /// blank line after it.
+    last_line:      usize,
+    /// Set at the start of the script and of every block, which no blank
+    /// line follows.
+    block_start:    bool,
+}
+
+/// Where the comments written above `statement` end: at the brace of its
+/// body, or at its end if it has none.
+fn header_end(statement: &Stmt) -> usize {
+    match &statement.kind {
+        StmtKind::Block(_)                  => statement.start.offset,
+        StmtKind::Function(function)        => function.left_brace.offset,
+        StmtKind::If { then_branch, .. }    => header_end(then_branch),
+        StmtKind::While { body, .. }        => header_end(body),
+        StmtKind::For { body, .. }          => header_end(body),
+        _                                   => statement.end.offset,
+    }
+}
+
+impl<'a> Formatter<'a> {
+    fn lexeme(&self, span: Span) -> &'a str {
+        &self.text[span.offset..span.offset + span.len]
+    }
+
+    /// Starts the line of something on `line` of the source.
+    fn start_line(&mut self, line: usize) {
+        if !self.block_start && line > self.last_line + 1 {
+            self.out.push('\n');
+        }
+        self.block_start = false;
+        for _ in 0..self.depth {
+            self.out.push_str(INDENT);
+        }
+    }
+
+    fn end_line(&mut self, line: usize) {
+        self.out.push('\n');
+        self.last_line = line;
+    }
+
+    /// Writes the comments before `offset` on lines of their own.
+    fn comments_before(&mut self, offset: usize) {
+        while let Some(&comment) = self.comments.get(self.next_comment).filter(|comment| comment.offset < offset) {
+            self.next_comment += 1;
+            self.start_line(comment.line);
+            self.out.push_str(self.lexeme(comment).trim_end());
+            self.end_line(comment.line);
+        }
+    }
+
+    /// Ends the line of the token `after`, with the comment following it on
+    /// its line if there is one.
+    fn end_line_after(&mut self, after: Span) {
+        if let Some(&comment) = self.comments.get(self.next_comment) {
+            let between = &self.text[after.offset + after.len..comment.offset];
+            if between.chars().all(|c| matches!(c, ' ' | '\t' | '\r')) {
+                self.next_comment += 1;
+                self.out.push(' ');
+                self.out.push_str(self.lexeme(comment).trim_end());
+            }
+        }
+        self.end_line(after.line);
+    }
+
+    fn statements(&mut self, statements: &[Stmt]) {
+        for statement in statements {
+            self.comments_before(header_end(statement));
+            self.start_line(statement.start.line);
+            self.statement(statement);
+            self.end_line_after(statement.end);
+        }
+    }
+
+    /// Writes a block from its `{` to its `}`, with the comments before
+    /// `close` at its end.
+    fn block(&mut self, statements: &[Stmt], left_brace: Span, close: usize) {
+        let empty = statements.is_empty()
+            && self.comments.get(self.next_comment).is_none_or(|comment| comment.offset >= close);
+        if empty {
+            self.out.push_str("{}");
+            return;
+        }
+        self.out.push('{');
+        self.end_line_after(left_brace);
+        self.depth += 1;
+        self.block_start = true;
+        self.statements(statements);
+        self.comments_before(close);
+        self.depth -= 1;
+        self.start_line(0);
+        self.out.push('}');
+    }
+
+    /// Writes the body of an `if`, `while` or `for` after its header.
+    fn body(&mut self, body: &Stmt, close: usize) {
+        self.out.push(' ');
+        match &body.kind {
+            StmtKind::Block(statements) => self.block(statements, body.start, close),
+            // The lines it continues on, like the `else` of a nested `if`,
+            // are indented under the statement it is the body of.
+            _                           => {
+                self.depth += 1;
+                self.statement(body);
+                self.depth -= 1;
+            }
+        }
+    }
+
+    /// Writes a statement from its first to its last token.
+    fn statement(&mut self, statement: &Stmt) {
+        match &statement.kind {
+            StmtKind::Expression(expr)  => {
+                let expr = self.expr(expr);
+                self.out.push_str(&expr);
+                self.out.push(';');
+            }
+            StmtKind::Print(expr)       => {
+                let expr = self.expr(expr);
+                self.out.push_str(&format!("print {expr};"));
+            }
+            StmtKind::Var { name, initializer, .. } => {
+                self.out.push_str(&format!("var {}", name.name));
+                if let Some(initializer) = initializer {
+                    let initializer = self.expr(initializer);
+                    self.out.push_str(&format!(" = {initializer}"));
+                }
+                self.out.push(';');
+            }
+            StmtKind::Function(function) => {
+                let params: Vec<&str> = function.params.iter().map(|param| param.name.as_str()).collect();
+                self.out.push_str(&format!("fun {}({}) ", function.name.name, params.join(", ")));
+                self.block(&function.body, function.left_brace, statement.end.offset);
+            }
+            StmtKind::Block(statements) => self.block(statements, statement.start, statement.end.offset),
+            StmtKind::If { condition, then_branch, else_branch, .. } => {
+                let condition = self.expr(condition);
+                self.out.push_str(&format!("if ({condition})"));
+                let Some(else_branch) = else_branch else {
+                    self.body(then_branch, statement.end.offset);
+                    return;
+                };
+                // The comments up to the header of the `else` branch end the
+                // `then` block, or go above the `else` line.
+                self.body(then_branch, header_end(else_branch));
+                if let StmtKind::Block(_) = then_branch.kind {
+                    self.out.push_str(" else");
+                } else {
+                    self.end_line_after(then_branch.end);
+                    self.comments_before(header_end(else_branch));
+                    self.start_line(else_branch.start.line);
+                    self.out.push_str("else");
+                }
+                if let StmtKind::If { .. } = else_branch.kind {
+                    // An `else if` chain stays at the depth of its first `if`.
+                    self.out.push(' ');
+                    self.statement(else_branch);
+                } else {
+                    self.body(else_branch, statement.end.offset);
+                }
+            }
+            StmtKind::While { condition, body, .. } => {
+                let condition = self.expr(condition);
+                self.out.push_str(&format!("while ({condition})"));
+                self.body(body, statement.end.offset);
+            }
+            StmtKind::For { initializer, condition, increment, body, .. } => {
+                self.out.push_str("for (");
+                match initializer {
+                    Some(initializer)   => self.statement(initializer),
+                    None                => self.out.push(';'),
+                }
+                if let Some(condition) = condition {
+                    let condition = self.expr(condition);
+                    self.out.push_str(&format!(" {condition}"));
+                }
+                self.out.push(';');
+                if let Some(increment) = increment {
+                    let increment = self.expr(increment);
+                    self.out.push_str(&format!(" {increment}"));
+                }
+                self.out.push(')');
+                self.body(body, statement.end.offset);
+            }
+            StmtKind::Return(None)          => self.out.push_str("return;"),
+            StmtKind::Return(Some(value))   => {
+                let value = self.expr(value);
+                self.out.push_str(&format!("return {value};"));
+            }
+        }
+    }
+
+    fn expr(&self, expr: &Expr) -> String {
+        match &expr.kind {
+            ExprKind::Literal(Literal::Nil)         => "nil".to_string(),
+            ExprKind::Literal(Literal::Bool(b))     => b.to_string(),
+            // Numbers and strings are kept as written.
+            ExprKind::Literal(_)                    => self.lexeme(expr.span).to_string(),
+            ExprKind::Variable(variable)            => variable.name.name.clone(),
+            ExprKind::Assign(variable, value)       => format!("{} = {}", variable.name.name, self.expr(value)),
+            ExprKind::Unary { operator, operand, .. } => format!("{}{}", operator.symbol(), self.expr(operand)),
+            ExprKind::Binary { operator, left, right, .. } => {
+                format!("{} {} {}", self.expr(left), operator.symbol(), self.expr(right))
+            }
+            ExprKind::Logical { operator, left, right, .. } => {
+                format!("{} {} {}", self.expr(left), operator.symbol(), self.expr(right))
+            }
+            ExprKind::Call(callee, arguments) => {
+                let arguments: Vec<String> = arguments.iter().map(|argument| self.expr(argument)).collect();
+                format!("{}({})", self.expr(callee), arguments.join(", "))
+            }
+            ExprKind::Grouping(inner) => format!("({})", self.expr(inner)),
+        }
+    }
+}
@@ -0,0 +1,469 @@
+use crate::ast::{self, BinaryOp, Binding, Expr, ExprKind, Literal, LogicalOp, Stmt, StmtKind, UnaryOp};
+use crate::compiler::{self, Compiler};
+use crate::diagnostic::{Source, Span};
+use crate::native::NATIVES;
+use crate::value::{NativeFunction, Slot, Value};
+use crate::vm::{InterpretResult, FRAMES_MAX};
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+
+/// A function declaration together with the scope it was declared in.
+#[derive(Debug)]
+pub struct Closure {
+    pub declaration:    Rc<ast::Function>,
+    environment:        Option<Rc<Environment>>,
+}
+
+/// The variables of one scope, in the order they were declared.
+#[derive(Debug, Default)]
+struct Environment {
+    values:     RefCell<Vec<Value>>,
+    enclosing:  Option<Rc<Environment>>,
+}
+
+struct Frame {
+    /// `None` for the script.
+    function:   Option<Rc<ast::Function>>,
+    /// Line of the expression the frame is evaluating, as far as calls and
+    /// errors are concerned.
+    line:       usize,
+    elided:     usize,
+}
+
+/// Why the evaluation of a statement stopped early.
+enum Unwind {
+    Return(Value),
+    /// A call in tail position, made by the caller of the returning function
+    /// in its place.
+    TailCall {
+        callee:     Value,
+        arguments:  Vec<Value>,
+        span:       Span,
+        line:       usize,
+    },
+    /// A runtime error, which has already been reported.
+    Error,
+}
+
+type Exec<T = ()> = Result<T, Unwind>;
+
+/// Runs scripts by walking the tree `Compiler::analyze` resolves. It serves as a
+/// reference for the VM: a script should print the same output and report the
+/// same errors, down to the line, with either. Tail calls don't grow the call
+/// stack here either, so the same scripts overflow it.
+pub struct Interpreter {
+    globals:        HashMap<String, Value>,
+    environment:    Option<Rc<Environment>>,
+    frames:         Vec<Frame>,
+    source:         Rc<Source>,
+    options:        compiler::Options,
+    max_frames:     usize,
+}
+
+impl Default for Interpreter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Environment {
+    fn new(enclosing: Option<Rc<Environment>>) -> Rc<Self> {
+        Rc::new(Self {
+            values: RefCell::new(Vec::new()),
+            enclosing,
+        })
+    }
+
+    fn ancestor(self: &Rc<Self>, depth: usize) -> &Rc<Self> {
+        let mut environment = self;
+        for _ in 0..depth {
+            environment = environment.enclosing.as_ref().expect("binding deeper than the scopes");
+        }
+        environment
+    }
+}
+
+impl Interpreter {
+    pub fn new() -> Self {
+        let mut globals = HashMap::new();
+        for (name, arity, native) in NATIVES {
+            let function = NativeFunction {
+                arity,
+                function: native,
+                name: Rc::new(name.to_string()),
+            };
+            globals.insert(name.to_string(), Value::from(function));
+        }
+        Self {
+            globals,
+            environment:    None,
+            frames:         Vec::new(),
+            source:         Rc::default(),
+            options:        compiler::Options::default(),
+            max_frames:     FRAMES_MAX,
+        }
+    }
+
+    /// Only `strict` and `max_errors` have an effect here.
+    pub fn set_compiler_options(&mut self, options: compiler::Options) {
+        self.options = options;
+    }
+
+    /// Maximum depth of nested calls, see `vm::Limits::frames`.
+    pub fn set_max_frames(&mut self, max_frames: usize) {
+        self.max_frames = max_frames;
+    }
+
+    pub fn globals(&self) -> &HashMap<String, Value> {
+        &self.globals
+    }
+
+    pub fn interpret(&mut self, source: &str) -> InterpretResult {
+        self.interpret_named("script", source)
+    }
+
+    /// Like `interpret`, with `name` identifying the source in diagnostics.
+    pub fn interpret_named(&mut self, name: &str, source: &str) -> InterpretResult {
+        let mut compiler = Compiler::with_options(name, source, self.options.clone());
+        compiler.declare_globals(self.globals.keys());
+        let script = match compiler.analyze() {
+            Some(script)    => script,
+            None            => return InterpretResult::CompileError,
+        };
+        self.source = Rc::clone(compiler.source());
+        self.environment = None;
+        self.frames = vec![Frame { function: None, line: 0, elided: 0 }];
+        for statement in &script.statements {
+            if self.execute(statement).is_err() {
+                return InterpretResult::RuntimeError;
+            }
+        }
+        InterpretResult::Ok
+    }
+
+    fn execute(&mut self, statement: &Stmt) -> Exec {
+        match &statement.kind {
+            StmtKind::Expression(expr) => {
+                self.evaluate(expr)?;
+            }
+            StmtKind::Print(expr) => {
+                let value = self.evaluate(expr)?;
+                println!("{value}");
+            }
+            StmtKind::Var { name, initializer, .. } => {
+                let value = match initializer {
+                    Some(initializer)   => self.evaluate(initializer)?,
+                    None                => Value::Nil,
+                };
+                self.define(&name.name, value);
+            }
+            StmtKind::Function(declaration) => {
+                let closure = Closure {
+                    declaration: Rc::clone(declaration),
+                    environment: self.environment.clone(),
+                };
+                self.define(&declaration.name.name, Value::AstClosure(Rc::new(closure)));
+            }
+            StmtKind::Block(statements) => {
+                let environment = Environment::new(self.environment.clone());
+                self.execute_in(statements, environment)?;
+            }
+            StmtKind::If { condition, then_branch, else_branch, .. } => {
+                if !self.evaluate(condition)?.is_falsey(self.options.strict) {
+                    self.execute(then_branch)?;
+                } else if let Some(else_branch) = else_branch {
+                    self.execute(else_branch)?;
+                }
+            }
+            StmtKind::While { condition, body, .. } => {
+                while !self.evaluate(condition)?.is_falsey(self.options.strict) {
+                    self.execute(body)?;
+                }
+            }
+            StmtKind::For { initializer, condition, increment, body, .. } => {
+                let enclosing = self.environment.replace(Environment::new(self.environment.clone()));
+                let result = self.execute_for(initializer.as_deref(), condition.as_ref(), increment.as_ref(), body);
+                self.environment = enclosing;
+                result?;
+            }
+            StmtKind::Return(value) => {
+                let value = match value {
+                    Some(value) => self.evaluate_returned(value)?,
+                    None        => Value::Nil,
+                };
+                return Err(Unwind::Return(value));
+            }
+        }
+        Ok(())
+    }
+
+    fn execute_for(&mut self, initializer: Option<&Stmt>, condition: Option<&Expr>, increment: Option<&Expr>, body: &Stmt) -> Exec {
+        if let Some(initializer) = initializer {
+            self.execute(initializer)?;
+        }
+        loop {
+            if let Some(condition) = condition {
+                if self.evaluate(condition)?.is_falsey(self.options.strict) {
+                    return Ok(());
+                }
+            }
+            self.execute(body)?;
+            if let Some(increment) = increment {
+                self.evaluate(increment)?;
+            }
+        }
+    }
+
+    /// Runs `statements` in `environment`, returning to the current one after.
+    fn execute_in(&mut self, statements: &[Stmt], environment: Rc<Environment>) -> Exec {
+        let enclosing = self.environment.replace(environment);
+        let result = statements.iter().try_for_each(|statement| self.execute(statement));
+        self.environment = enclosing;
+        result
+    }
+
+    fn define(&mut self, name: &str, value: Value) {
+        match &self.environment {
+            Some(environment)   => environment.values.borrow_mut().push(value),
+            None                => {
+                self.globals.insert(name.to_string(), value);
+            }
+        }
+    }
+
+    fn evaluate(&mut self, expr: &Expr) -> Exec<Value> {
+        match &expr.kind {
+            ExprKind::Literal(literal) => Ok(match literal {
+                Literal::Nil        => Value::Nil,
+                Literal::Bool(b)    => Value::Bool(*b),
+                Literal::Number(n)  => Value::Number(*n),
+                Literal::String(s)  => Value::from(s.clone()),
+            }),
+            ExprKind::Variable(variable) => match variable.binding.get() {
+                Binding::Local { depth, index, .. } | Binding::Upvalue { depth, index, .. } => {
+                    let environment = self.environment.as_ref().expect("local binding at the top level");
+                    Ok(environment.ancestor(depth).values.borrow()[index].clone())
+                }
+                Binding::Global => match self.globals.get(&variable.name.name) {
+                    Some(value) => Ok(value.clone()),
+                    None        => {
+                        let message = format!("Undefined variable '{}'.", variable.name.name);
+                        Err(self.error(&message, variable.name.span, expr.line()))
+                    }
+                },
+            },
+            ExprKind::Assign(variable, value) => {
+                let value = self.evaluate(value)?;
+                match variable.binding.get() {
+                    Binding::Local { depth, index, .. } | Binding::Upvalue { depth, index, .. } => {
+                        let environment = self.environment.as_ref().expect("local binding at the top level");
+                        environment.ancestor(depth).values.borrow_mut()[index] = value.clone();
+                    }
+                    Binding::Global => match self.globals.get_mut(&variable.name.name) {
+                        Some(global)    => *global = value.clone(),
+                        None            => {
+                            let message = format!("Undefined variable '{}'.", variable.name.name);
+                            return Err(self.error(&message, variable.name.span, expr.line()));
+                        }
+                    },
+                }
+                Ok(value)
+            }
+            ExprKind::Unary { operator, operand, .. } => {
+                let value = self.evaluate(operand)?;
+                match operator {
+                    UnaryOp::Not    => Ok(Value::Bool(value.is_falsey(self.options.strict))),
+                    UnaryOp::Negate => match value {
+                        Value::Number(n)    => Ok(Value::Number(-n)),
+                        _                   => Err(self.error("Operand must be a number.", expr.span, expr.line())),
+                    },
+                }
+            }
+            ExprKind::Binary { operator, left, right, .. } => {
+                let left = self.evaluate(left)?;
+                let right = self.evaluate(right)?;
+                self.binary(*operator, left, right, expr)
+            }
+            ExprKind::Logical { operator, left, right, .. } => {
+                let left = self.evaluate(left)?;
+                if self.short_circuits(*operator, &left) {
+                    return Ok(left);
+                }
+                self.evaluate(right)
+            }
+            ExprKind::Call(callee, arguments) => {
+                let (callee, arguments) = self.evaluate_call(callee, arguments)?;
+                self.call(callee, arguments, expr.span, expr.line())
+            }
+            ExprKind::Grouping(inner) => self.evaluate(inner),
+        }
+    }
+
+    fn short_circuits(&self, operator: LogicalOp, left: &Value) -> bool {
+        match operator {
+            LogicalOp::And  => left.is_falsey(self.options.strict),
+            LogicalOp::Or   => !left.is_falsey(self.options.strict),
+        }
+    }
+
+    fn evaluate_call(&mut self, callee: &Expr, arguments: &[Expr]) -> Exec<(Value, Vec<Value>)> {
+        let callee = self.evaluate(callee)?;
+        let arguments = arguments.iter()
+            .map(|argument| self.evaluate(argument))
+            .collect::<Exec<Vec<Value>>>()?;
+        Ok((callee, arguments))
+    }
+
+    /// Evaluates the value of a `return`, handing a call it ends with back to
+    /// the caller instead of making it, as the VM makes it a tail call.
+    fn evaluate_returned(&mut self, expr: &Expr) -> Exec<Value> {
+        match &expr.kind {
+            ExprKind::Call(callee, arguments) => {
+                let (callee, arguments) = self.evaluate_call(callee, arguments)?;
+                Err(Unwind::TailCall { callee, arguments, span: expr.span, line: expr.line() })
+            }
+            ExprKind::Grouping(inner) => self.evaluate_returned(inner),
+            ExprKind::Logical { operator, left, right, .. } => {
+                let left = self.evaluate(left)?;
+                if self.short_circuits(*operator, &left) {
+                    return Ok(left);
+                }
+                self.evaluate_returned(right)
+            }
+            _ => self.evaluate(expr),
+        }
+    }
+
+    fn binary(&mut self, operator: BinaryOp, left: Value, right: Value, expr: &Expr) -> Exec<Value> {
+        use BinaryOp::*;
+        let result = match (operator, &left, &right) {
+            (Equal, ..)     => Value::Bool(left == right),
+            (NotEqual, ..)  => Value::Bool(left != right),
+            (Add, Value::Number(a), Value::Number(b))       => Value::Number(a + b),
+            (Add, Value::String(a), Value::String(b))       => Value::from(format!("{a}{b}")),
+            (Add, Value::String(a), b) if !self.options.strict => Value::from(format!("{a}{b}")),
+            (Add, ..) if self.options.strict => {
+                return Err(self.error("Operands must be two numbers or two strings.", expr.span, expr.line()));
+            }
+            (Subtract, Value::Number(a), Value::Number(b))  => Value::Number(a - b),
+            (Multiply, Value::Number(a), Value::Number(b))  => Value::Number(a * b),
+            (Divide, Value::Number(a), Value::Number(b))    => Value::Number(a / b),
+            // `>=` and `<=` are compiled to the negation of `<` and `>`.
+            (Greater | Less | GreaterEqual | LessEqual, Value::Number(a), Value::Number(b)) => Value::Bool(compare(operator, a, b)),
+            (Greater | Less | GreaterEqual | LessEqual, Value::String(a), Value::String(b)) => Value::Bool(compare(operator, a, b)),
+            _ => return Err(self.error("Operands must be numbers.", expr.span, expr.line())),
+        };
+        Ok(result)
+    }
+
+    fn call(&mut self, callee: Value, arguments: Vec<Value>, span: Span, line: usize) -> Exec<Value> {
+        self.frames.last_mut().unwrap().line = line;
+        match callee {
+            Value::AstClosure(closure)  => self.call_closure(closure, arguments, span, line),
+            Value::Native(native)       => {
+                if arguments.len() != native.arity as usize {
+                    let message = format!("Expected {} arguments but got {}.", native.arity, arguments.len());
+                    return Err(self.error(&message, span, line));
+                }
+                (native.function)(arguments.len() as u8, &arguments).map_err(|message| self.error(&message, span, line))
+            }
+            _ => Err(self.error("Can only call functions and classes.", span, line)),
+        }
+    }
+
+    fn call_closure(&mut self, mut closure: Rc<Closure>, mut arguments: Vec<Value>, span: Span, line: usize) -> Exec<Value> {
+        self.check_arity(&closure, arguments.len(), span, line)?;
+        if self.frames.len() >= self.max_frames {
+            return Err(self.error("Stack overflow.", span, line));
+        }
+        self.frames.push(Frame {
+            function: Some(Rc::clone(&closure.declaration)),
+            line,
+            elided: 0,
+        });
+        let result = loop {
+            let environment = Environment::new(closure.environment.clone());
+            environment.values.borrow_mut().append(&mut arguments);
+            match self.execute_in(&closure.declaration.body, environment) {
+                Ok(())                      => break Ok(Value::Nil),
+                Err(Unwind::Return(value))  => break Ok(value),
+                Err(Unwind::Error)          => break Err(Unwind::Error),
+                Err(Unwind::TailCall { callee, arguments: tail_arguments, span: tail_span, line: tail_line }) => {
+                    let next = match callee {
+                        Value::AstClosure(next) => next,
+                        callee                  => break self.call(callee, tail_arguments, tail_span, tail_line),
+                    };
+                    self.frames.last_mut().unwrap().line = tail_line;
+                    if let Err(error) = self.check_arity(&next, tail_arguments.len(), tail_span, tail_line) {
+                        break Err(error);
+                    }
+                    let frame = self.frames.last_mut().unwrap();
+                    frame.function = Some(Rc::clone(&next.declaration));
+                    frame.elided += 1;
+                    (closure, arguments) = (next, tail_arguments);
+                }
+            }
+        };
+        self.frames.pop();
+        result
+    }
+
+    fn check_arity(&mut self, closure: &Closure, arg_count: usize, span: Span, line: usize) -> Exec {
+        let arity = closure.declaration.params.len();
+        if arg_count != arity {
+            return Err(self.error(&format!("Expected {arity} arguments but got {arg_count}."), span, line));
+        }
+        Ok(())
+    }
+
+    /// Reports a runtime error at `span` on `line` the way the VM does, with
+    /// the snippet of the source and a trace of the calls.
+    fn error(&mut self, message: &str, span: Span, line: usize) -> Unwind {
+        eprintln!("{message}");
+        eprint!("{}", self.source.snippet(span));
+        self.frames.last_mut().unwrap().line = line;
+        let mut frames = self.frames.iter().rev().peekable();
+        while let Some(frame) = frames.next() {
+            let mut repeated = 1;
+            let mut elided = frame.elided;
+            while let Some(next) = frames.peek() {
+                let same_function = match (&next.function, &frame.function) {
+                    (Some(a), Some(b))  => Rc::ptr_eq(a, b),
+                    (None, None)        => true,
+                    _                   => false,
+                };
+                if !same_function || next.line != frame.line {
+                    break;
+                }
+                repeated += 1;
+                elided += next.elided;
+                frames.next();
+            }
+            eprint!("[line {}] in ", frame.line);
+            match &frame.function {
+                Some(function)  => eprint!("{}", function.name.name),
+                None            => eprint!("script"),
+            }
+            if repeated > 1 {
+                eprint!(" (x {repeated})");
+            }
+            if elided > 0 {
+                eprint!(" ({elided} tail {} elided)", if elided == 1 { "call" } else { "calls" });
+            }
+            eprintln!();
+        }
+        Unwind::Error
+    }
+}
+
+fn compare<T: PartialOrd + ?Sized>(operator: BinaryOp, a: &T, b: &T) -> bool {
+    match operator {
+        BinaryOp::Greater       => a > b,
+        BinaryOp::Less          => a < b,
+        BinaryOp::GreaterEqual  => !matches!(a.partial_cmp(b), Some(std::cmp::Ordering::Less)),
+        _                       => !matches!(a.partial_cmp(b), Some(std::cmp::Ordering::Greater)),
+    }
+}
@@ -0,0 +1,300 @@
+use std::fmt;
+
+
+/// A JSON value, written compactly by its `Display` implementation and read
+/// by `Json::parse`. Objects keep their keys in the order they were given.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Json {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>),
+}
+
+impl Json {
+    /// An object with the given keys, in order.
+    pub fn object<'k>(entries: impl IntoIterator<Item = (&'k str, Json)>) -> Json {
+        Json::Object(entries.into_iter().map(|(key, value)| (key.to_string(), value)).collect())
+    }
+
+    pub fn parse(text: &str) -> Result<Json, String> {
+        let mut parser = Parser { text, offset: 0 };
+        let value = parser.value()?;
+        parser.skip_whitespace();
+        match parser.peek() {
+            None    => Ok(value),
+            Some(_) => Err(parser.error("Expect end of input.")),
+        }
+    }
+
+    /// The value of `key` if this is an object that has it.
+    pub fn get(&self, key: &str) -> Option<&Json> {
+        match self {
+            Json::Object(entries)   => entries.iter().find(|(k, _)| k == key).map(|(_, value)| value),
+            _                       => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Json::String(s) => Some(s),
+            _               => None,
+        }
+    }
+
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            Json::Number(n) => Some(*n),
+            _               => None,
+        }
+    }
+
+    pub fn as_array(&self) -> Option<&[Json]> {
+        match self {
+            Json::Array(values) => Some(values),
+            _                   => None,
+        }
+    }
+}
+
+struct Parser<'a> {
+    text:   &'a str,
+    offset: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn error(&self, message: &str) -> String {
+        format!("{message} At byte {}.", self.offset)
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.text[self.offset..].chars().next()
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.offset += c.len_utf8();
+        Some(c)
+    }
+
+    fn skip_whitespace(&mut self) {
+        while matches!(self.peek(), Some(' ' | '\t' | '\n' | '\r')) {
+            self.offset += 1;
+        }
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), String> {
+        self.skip_whitespace();
+        match self.next() {
+            Some(c) if c == expected    => Ok(()),
+            _                           => Err(self.error(&format!("Expect '{expected}'."))),
+        }
+    }
+
+    fn keyword(&mut self, keyword: &str, value: Json) -> Result<Json, String> {
+        if !self.text[self.offset..].starts_with(keyword) {
+            return Err(self.error("Expect a value."));
+        }
+        self.offset += keyword.len();
+        Ok(value)
+    }
+
+    fn value(&mut self) -> Result<Json, String> {
+        self.skip_whitespace();
+        match self.peek() {
+            Some('n')   => self.keyword("null", Json::Null),
+            Some('t')   => self.keyword("true", Json::Bool(true)),
+            Some('f')   => self.keyword("false", Json::Bool(false)),
+            Some('"')   => self.string().map(Json::String),
+            Some('[')   => self.array(),
+            Some('{')   => self.object(),
+            Some(c) if c == '-' || c.is_ascii_digit() => self.number(),
+            _           => Err(self.error("Expect a value.")),
+        }
+    }
+
+    fn number(&mut self) -> Result<Json, String> {
+        let start = self.offset;
+        while matches!(self.peek(), Some(c) if c.is_ascii_digit() || matches!(c, '-' | '+' | '.' | 'e' | 'E')) {
+            self.offset += 1;
+        }
+        self.text[start..self.offset].parse().map(Json::Number).map_err(|_| self.error("Invalid number."))
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        self.expect('"')?;
+        let mut out = String::new();
+        loop {
+            match self.next() {
+                None        => return Err(self.error("Unterminated string.")),
+                Some('"')   => return Ok(out),
+                Some('\\') => match self.next() {
+                    Some('b')   => out.push('\u{8}'),
+                    Some('f')   => out.push('\u{c}'),
+                    Some('n')   => out.push('\n'),
+                    Some('r')   => out.push('\r'),
+                    Some('t')   => out.push('\t'),
+                    Some('u')   => out.push(self.escaped_char()?),
+                    Some(c @ ('"' | '\\' | '/')) => out.push(c),
+                    _           => return Err(self.error("Invalid escape.")),
+                },
+                Some(c)     => out.push(c),
+            }
+        }
+    }
+
+    /// The character of a `\u` escape, which may be a surrogate pair.
+    fn escaped_char(&mut self) -> Result<char, String> {
+        let high = self.hex4()?;
+        let code = if (0xD800..0xDC00).contains(&high) && self.text[self.offset..].starts_with("\\u") {
+            self.offset += 2;
+            let low = self.hex4()?;
+            0x10000 + ((high - 0xD800) << 10) + low.wrapping_sub(0xDC00)
+        } else {
+            high
+        };
+        Ok(char::from_u32(code).unwrap_or(char::REPLACEMENT_CHARACTER))
+    }
+
+    fn hex4(&mut self) -> Result<u32, String> {
+        let digits = self.text.get(self.offset..self.offset + 4).ok_or_else(|| self.error("Invalid escape."))?;
+        let code = u32::from_str_radix(digits, 16).map_err(|_| self.error("Invalid escape."))?;
+        self.offset += 4;
+        Ok(code)
+    }
+
+    fn array(&mut self) -> Result<Json, String> {
+        self.expect('[')?;
+        let mut values = Vec::new();
+        self.skip_whitespace();
+        if self.peek() == Some(']') {
+            self.offset += 1;
+            return Ok(Json::Array(values));
+        }
+        loop {
+            values.push(self.value()?);
+            self.skip_whitespace();
+            match self.next() {
+                Some(',')   => continue,
+                Some(']')   => return Ok(Json::Array(values)),
+                _           => return Err(self.error("Expect ',' or ']'.")),
+            }
+        }
+    }
+
+    fn object(&mut self) -> Result<Json, String> {
+        self.expect('{')?;
+        let mut entries = Vec::new();
+        self.skip_whitespace();
+        if self.peek() == Some('}') {
+            self.offset += 1;
+            return Ok(Json::Object(entries));
+        }
+        loop {
+            self.skip_whitespace();
+            let key = self.string()?;
+            self.expect(':')?;
+            entries.push((key, self.value()?));
+            self.skip_whitespace();
+            match self.next() {
+                Some(',')   => continue,
+                Some('}')   => return Ok(Json::Object(entries)),
+                _           => return Err(self.error("Expect ',' or '}'.")),
+            }
+        }
+    }
+}
+
+impl From<&str> for Json {
+    fn from(value: &str) -> Self {
+        Json::String(value.to_string())
+    }
+}
+
+impl From<String> for Json {
+    fn from(value: String) -> Self {
+        Json::String(value)
+    }
+}
+
+impl From<bool> for Json {
+    fn from(value: bool) -> Self {
+        Json::Bool(value)
+    }
+}
+
+impl From<usize> for Json {
+    fn from(value: usize) -> Self {
+        Json::Number(value as f64)
+    }
+}
+
+impl From<f64> for Json {
+    fn from(value: f64) -> Self {
+        Json::Number(value)
+    }
+}
+
+impl<T: Into<Json>> From<Option<T>> for Json {
+    fn from(value: Option<T>) -> Self {
+        value.map_or(Json::Null, Into::into)
+    }
+}
+
+impl<T: Into<Json>> From<Vec<T>> for Json {
+    fn from(value: Vec<T>) -> Self {
+        Json::Array(value.into_iter().map(Into::into).collect())
+    }
+}
+
+fn write_string(f: &mut fmt::Formatter, s: &str) -> fmt::Result {
+    f.write_str("\"")?;
+    for c in s.chars() {
+        match c {
+            '"'                     => f.write_str("\\\"")?,
+            '\\'                    => f.write_str("\\\\")?,
+            '\n'                    => f.write_str("\\n")?,
+            '\r'                    => f.write_str("\\r")?,
+            '\t'                    => f.write_str("\\t")?,
+            c if (c as u32) < 0x20  => write!(f, "\\u{:04x}", c as u32)?,
+            c                       => write!(f, "{c}")?,
+        }
+    }
+    f.write_str("\"")
+}
+
+impl fmt::Display for Json {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Json::Null      => f.write_str("null"),
+            Json::Bool(b)   => write!(f, "{b}"),
+            // JSON has no infinities nor NaN.
+            Json::Number(n) if !n.is_finite()   => f.write_str("null"),
+            Json::Number(n) => write!(f, "{n}"),
+            Json::String(s) => write_string(f, s),
+            Json::Array(values) => {
+                f.write_str("[")?;
+                for (i, value) in values.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(",")?;
+                    }
+                    write!(f, "{value}")?;
+                }
+                f.write_str("]")
+            }
+            Json::Object(entries) => {
+                f.write_str("{")?;
+                for (i, (key, value)) in entries.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(",")?;
+                    }
+                    write_string(f, key)?;
+                    write!(f, ":{value}")?;
+                }
+                f.write_str("}")
+            }
+        }
+    }
+}
@@ -0,0 +1,20 @@
+pub mod ast;
+pub mod chunk;
+pub mod compiler;
+pub mod coverage;
+pub mod debug;
+pub mod diagnostic;
+pub mod format;
+pub mod interpreter;
+pub mod json;
+pub mod lint;
+pub mod lsp;
+pub mod memory;
+#[cfg(feature = "nan_boxing")]
+pub mod nanbox;
+pub mod native;
+pub mod profiler;
+pub mod scanner;
+pub mod trace;
+pub mod value;
+pub mod vm;
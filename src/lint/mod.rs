@@ -0,0 +1,152 @@
+use crate::ast::Script;
+use crate::diagnostic::{Diagnostic, Severity, Span};
+use crate::json::Json;
+
+mod rules;
+
+
+/// Checks of `rslox lint`, which look further than the compiler's warnings.
+/// Every one of them can be turned off in the configuration.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum Rule {
+    ArityMismatch,
+    UseBeforeDefinition,
+    ConstantNilComparison,
+    DeepNesting,
+    DuplicateBranches,
+}
+
+impl Rule {
+    pub const ALL: [Rule; 5] = [
+        Rule::ArityMismatch,
+        Rule::UseBeforeDefinition,
+        Rule::ConstantNilComparison,
+        Rule::DeepNesting,
+        Rule::DuplicateBranches,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Rule::ArityMismatch         => "arity-mismatch",
+            Rule::UseBeforeDefinition   => "use-before-definition",
+            Rule::ConstantNilComparison => "constant-nil-comparison",
+            Rule::DeepNesting           => "deep-nesting",
+            Rule::DuplicateBranches     => "duplicate-branches",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Rule> {
+        Self::ALL.into_iter().find(|rule| rule.name() == name)
+    }
+}
+
+/// Which rules run and how. The configuration file has a `key = value` per
+/// line and `#` comments:
+///
+/// ```text
+/// # Rules are on unless turned off.
+/// duplicate-branches = off
+/// max-depth = 3
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Config {
+    rules:          u8,
+    /// The deepest `deep-nesting` allows functions and control flow to nest.
+    pub max_depth:  usize,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            rules: Rule::ALL.iter().fold(0, |bits, rule| bits | 1 << *rule as u8),
+            max_depth: 4,
+        }
+    }
+}
+
+impl Config {
+    pub fn parse(text: &str) -> Result<Config, String> {
+        let mut config = Config::default();
+        for (number, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or("").trim();
+            if line.is_empty() {
+                continue;
+            }
+            let error = |message: String| format!("line {}: {message}", number + 1);
+            let Some((key, value)) = line.split_once('=') else {
+                return Err(error(format!("Expect 'key = value', found '{line}'.")));
+            };
+            let (key, value) = (key.trim(), value.trim());
+            if key == "max-depth" {
+                config.max_depth = value.parse().map_err(|_| error(format!("Expect a number for 'max-depth', found '{value}'.")))?;
+                continue;
+            }
+            let Some(rule) = Rule::from_name(key) else {
+                let names: Vec<&str> = Rule::ALL.iter().map(|rule| rule.name()).collect();
+                return Err(error(format!("Unknown rule '{key}', expected one of: {}.", names.join(", "))));
+            };
+            match value {
+                "on"    => config.enable(rule),
+                "off"   => config.disable(rule),
+                _       => return Err(error(format!("Expect 'on' or 'off' for '{key}', found '{value}'."))),
+            }
+        }
+        Ok(config)
+    }
+
+    pub fn enable(&mut self, rule: Rule) {
+        self.rules |= 1 << rule as u8;
+    }
+
+    pub fn disable(&mut self, rule: Rule) {
+        self.rules &= !(1 << rule as u8);
+    }
+
+    pub fn is_enabled(&self, rule: Rule) -> bool {
+        self.rules & 1 << rule as u8 != 0
+    }
+}
+
+/// A finding of a rule.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Lint {
+    pub rule:       Rule,
+    pub message:    String,
+    /// The token the lint is reported at.
+    pub token:      Span,
+    /// What is underlined, from `token` on.
+    pub span:       Span,
+}
+
+impl Lint {
+    /// The lint as a warning of the compiler, with the rule after the message.
+    pub fn to_diagnostic(&self, text: &str) -> Diagnostic {
+        let lexeme = text.get(self.token.offset..self.token.offset + self.token.len).unwrap_or("");
+        Diagnostic {
+            severity: Severity::Warning,
+            message: format!("{} [{}]", self.message, self.rule.name()),
+            line: self.token.line,
+            span: self.span,
+            location: format!(" at '{lexeme}'"),
+        }
+    }
+
+    pub fn to_json(&self, file: &str) -> Json {
+        Json::object([
+            ("file", Json::from(file)),
+            ("line", Json::from(self.token.line)),
+            ("column", Json::from(self.token.column)),
+            ("rule", Json::from(self.rule.name())),
+            ("message", Json::from(self.message.as_str())),
+        ])
+    }
+}
+
+/// Runs the enabled rules over a resolved script, and returns their
+/// findings in the order of the source.
+pub fn lint(script: &Script, config: &Config) -> Vec<Lint> {
+    let mut lints = rules::Linter::new(script, config).run(script);
+    lints.sort_by_key(|lint| lint.token.offset);
+    lints
+}
@@ -0,0 +1,261 @@
+use crate::ast::dump::stmt_sexpr;
+use crate::ast::index::{Index, SymbolKind};
+use crate::ast::{BinaryOp, Expr, ExprKind, Literal, Script, Stmt, StmtKind, Variable};
+use crate::diagnostic::Span;
+use crate::lint::{Config, Lint, Rule};
+
+use std::collections::HashSet;
+
+
+/// Walks a script once for all the rules.
+pub(super) struct Linter<'a> {
+    config:         &'a Config,
+    index:          Index,
+    lints:          Vec<Lint>,
+    /// The globals the top-level code defined so far.
+    defined:        HashSet<String>,
+    function_depth: usize,
+    block_depth:    usize,
+    /// How many functions and control flow statements the walk is in.
+    nesting:        usize,
+}
+
+impl<'a> Linter<'a> {
+    pub fn new(script: &Script, config: &'a Config) -> Self {
+        Self {
+            config,
+            index: Index::new(script),
+            lints: Vec::new(),
+            defined: HashSet::new(),
+            function_depth: 0,
+            block_depth: 0,
+            nesting: 0,
+        }
+    }
+
+    pub fn run(mut self, script: &Script) -> Vec<Lint> {
+        for statement in &script.statements {
+            self.statement(statement);
+        }
+        self.lints
+    }
+
+    fn report(&mut self, rule: Rule, token: Span, span: Span, message: String) {
+        if self.config.is_enabled(rule) {
+            self.lints.push(Lint { rule, message, token, span });
+        }
+    }
+
+    /// The arity of the function `variable` refers to, if it is declared
+    /// once and never assigned.
+    fn known_arity(&self, variable: &Variable) -> Option<usize> {
+        let symbol = self.index.reference(variable.name.span)?.symbol?;
+        let redefined = self.index.references.iter().any(|reference| reference.assignment && reference.symbol == Some(symbol))
+            || self.index.symbols[symbol].global && self.index.symbols.iter()
+                .filter(|other| other.global && other.name == variable.name.name)
+                .count() > 1;
+        match &self.index.symbols[symbol].kind {
+            _ if redefined                  => None,
+            SymbolKind::Function(params)    => Some(params.len()),
+            SymbolKind::Native(arity)       => Some(*arity as usize),
+            _                               => None,
+        }
+    }
+
+    /// Whether `expr` evaluates to anything but `nil`, if it evaluates at all.
+    fn never_nil(&self, expr: &Expr) -> bool {
+        match &expr.kind {
+            ExprKind::Literal(literal)              => *literal != Literal::Nil,
+            ExprKind::Variable(variable)            => self.known_arity(variable).is_some(),
+            ExprKind::Assign(_, value)              => self.never_nil(value),
+            ExprKind::Unary { .. }                  => true,
+            ExprKind::Binary { .. }                 => true,
+            ExprKind::Logical { left, right, .. }   => self.never_nil(left) && self.never_nil(right),
+            ExprKind::Call(..)                      => false,
+            ExprKind::Grouping(inner)               => self.never_nil(inner),
+        }
+    }
+
+    /// Walks a function or a control flow statement one level deeper.
+    fn nested(&mut self, statement: &Stmt, walk: impl FnOnce(&mut Self)) {
+        self.nesting += 1;
+        if self.nesting == self.config.max_depth + 1 {
+            let message = format!("Nesting deeper than {} levels.", self.config.max_depth);
+            self.report(Rule::DeepNesting, statement.start, statement.start, message);
+        }
+        walk(self);
+        self.nesting -= 1;
+    }
+
+    fn statement(&mut self, statement: &Stmt) {
+        match &statement.kind {
+            StmtKind::Expression(expr) | StmtKind::Print(expr) => self.expression(expr, false),
+            StmtKind::Var { name, initializer, .. } => {
+                if let Some(initializer) = initializer {
+                    self.expression(initializer, false);
+                }
+                if self.function_depth == 0 && self.block_depth == 0 {
+                    self.defined.insert(name.name.clone());
+                }
+            }
+            StmtKind::Function(function) => {
+                if self.function_depth == 0 && self.block_depth == 0 {
+                    self.defined.insert(function.name.name.clone());
+                }
+                self.nested(statement, |linter| {
+                    linter.function_depth += 1;
+                    for statement in &function.body {
+                        linter.statement(statement);
+                    }
+                    linter.function_depth -= 1;
+                });
+            }
+            StmtKind::Block(statements) => {
+                self.block_depth += 1;
+                for statement in statements {
+                    self.statement(statement);
+                }
+                self.block_depth -= 1;
+            }
+            StmtKind::If { .. } => {
+                self.duplicate_branches(statement);
+                self.nested(statement, |linter| linter.if_chain(statement));
+            }
+            StmtKind::While { condition, body, .. } => self.nested(statement, |linter| {
+                linter.expression(condition, true);
+                linter.statement(body);
+            }),
+            StmtKind::For { initializer, condition, increment, body, .. } => self.nested(statement, |linter| {
+                linter.block_depth += 1;
+                if let Some(initializer) = initializer {
+                    linter.statement(initializer);
+                }
+                if let Some(condition) = condition {
+                    linter.expression(condition, true);
+                }
+                if let Some(increment) = increment {
+                    linter.expression(increment, false);
+                }
+                linter.statement(body);
+                linter.block_depth -= 1;
+            }),
+            StmtKind::Return(value) => {
+                if let Some(value) = value {
+                    self.expression(value, false);
+                }
+            }
+        }
+    }
+
+    /// Walks an `if` and the `else if`s following it, which nest no deeper.
+    fn if_chain(&mut self, statement: &Stmt) {
+        let StmtKind::If { condition, then_branch, else_branch, .. } = &statement.kind else {
+            return self.statement(statement);
+        };
+        self.expression(condition, true);
+        self.statement(then_branch);
+        if let Some(else_branch) = else_branch {
+            self.if_chain(else_branch);
+        }
+    }
+
+    fn duplicate_branches(&mut self, statement: &Stmt) {
+        let mut branches = Vec::new();
+        let mut current = statement;
+        while let StmtKind::If { then_branch, else_branch, .. } = &current.kind {
+            branches.push(then_branch.as_ref());
+            match else_branch {
+                Some(else_branch)   => current = else_branch,
+                None                => break,
+            }
+        }
+        if !matches!(current.kind, StmtKind::If { .. }) {
+            branches.push(current);
+        }
+        let bodies: Vec<String> = branches.iter().map(|branch| stmt_sexpr(branch)).collect();
+        for (i, branch) in branches.iter().enumerate() {
+            if let Some(same) = bodies[..i].iter().position(|body| *body == bodies[i]) {
+                let message = format!("This branch is the same as the one on line {}.", branches[same].start.line);
+                self.report(Rule::DuplicateBranches, branch.start, branch.start.to(branch.end), message);
+            }
+        }
+    }
+
+    /// Walks an expression, which is a `condition` when its value only
+    /// decides where control flows.
+    fn expression(&mut self, expr: &Expr, condition: bool) {
+        match &expr.kind {
+            ExprKind::Literal(_)            => {}
+            ExprKind::Variable(variable)    => self.variable(variable),
+            ExprKind::Assign(variable, value) => {
+                self.expression(value, false);
+                self.variable(variable);
+            }
+            ExprKind::Unary { operand, .. } => self.expression(operand, condition),
+            ExprKind::Binary { operator, operator_span, left, right } => {
+                if condition && matches!(operator, BinaryOp::Equal | BinaryOp::NotEqual) {
+                    self.nil_comparison(expr, *operator, *operator_span, left, right);
+                }
+                self.expression(left, false);
+                self.expression(right, false);
+            }
+            ExprKind::Logical { left, right, .. } => {
+                self.expression(left, condition);
+                self.expression(right, condition);
+            }
+            ExprKind::Call(callee, arguments) => {
+                if let ExprKind::Variable(variable) = &callee.kind {
+                    self.arity(expr, variable, arguments.len());
+                }
+                self.expression(callee, false);
+                for argument in arguments {
+                    self.expression(argument, false);
+                }
+            }
+            ExprKind::Grouping(inner)       => self.expression(inner, condition),
+        }
+    }
+
+    fn variable(&mut self, variable: &Variable) {
+        let name = &variable.name;
+        let Some(reference) = self.index.reference(name.span) else {
+            return;
+        };
+        let message = match reference.symbol.map(|symbol| &self.index.symbols[symbol]) {
+            None => format!("Global '{}' is never defined.", name.name),
+            Some(symbol) if symbol.global && symbol.span.is_some() && self.function_depth == 0 && !self.defined.contains(&name.name) => {
+                format!("Global '{}' is used before it is defined.", name.name)
+            }
+            Some(_) => return,
+        };
+        self.report(Rule::UseBeforeDefinition, name.span, name.span, message);
+    }
+
+    fn arity(&mut self, call: &Expr, callee: &Variable, arguments: usize) {
+        let Some(arity) = self.known_arity(callee) else {
+            return;
+        };
+        if arity != arguments {
+            let message = format!(
+                "'{}' takes {arity} argument{} but is called with {arguments}.",
+                callee.name.name,
+                if arity == 1 { "" } else { "s" },
+            );
+            self.report(Rule::ArityMismatch, callee.name.span, call.span, message);
+        }
+    }
+
+    fn nil_comparison(&mut self, comparison: &Expr, operator: BinaryOp, operator_span: Span, left: &Expr, right: &Expr) {
+        let is_nil = |expr: &Expr| matches!(expr.kind, ExprKind::Literal(Literal::Nil));
+        let other = match (is_nil(left), is_nil(right)) {
+            (true, false)   => right,
+            (false, true)   => left,
+            _               => return,
+        };
+        if self.never_nil(other) {
+            let always = operator == BinaryOp::NotEqual;
+            let message = format!("This comparison is always {always}, the operand compared with nil is never nil.");
+            self.report(Rule::ConstantNilComparison, operator_span, comparison.span, message);
+        }
+    }
+}
@@ -0,0 +1,365 @@
+use crate::ast::index::{Index, Symbol, SymbolKind};
+use crate::ast::{Script, Stmt, StmtKind};
+use crate::compiler::{Compiler, Options};
+use crate::diagnostic::{Diagnostic, Severity, Span};
+use crate::json::Json;
+use crate::native::NATIVES;
+
+use std::collections::HashMap;
+use std::io::{self, BufRead, Write};
+
+
+const PARSE_ERROR: f64 = -32700.0;
+const METHOD_NOT_FOUND: f64 = -32601.0;
+const INVALID_PARAMS: f64 = -32602.0;
+
+/// `SymbolKind` and `CompletionItemKind` values of the protocol.
+const SYMBOL_FUNCTION: usize = 12;
+const COMPLETION_FUNCTION: usize = 3;
+const COMPLETION_VARIABLE: usize = 6;
+
+/// An open file, compiled again on every change.
+struct Document {
+    text:           String,
+    /// Offset of the start of every line.
+    lines:          Vec<usize>,
+    script:         Script,
+    index:          Index,
+    diagnostics:    Vec<Diagnostic>,
+}
+
+/// A language server for Lox. It keeps the open documents and answers the
+/// messages of a client, see `run` for the transport.
+#[derive(Default)]
+pub struct Server {
+    documents:  HashMap<String, Document>,
+    shut_down:  bool,
+    exited:     bool,
+}
+
+impl Document {
+    fn new(uri: &str, text: String) -> Self {
+        let natives: Vec<String> = NATIVES.iter().map(|(name, _, _)| name.to_string()).collect();
+        let mut compiler = Compiler::with_options(uri, &text, Options::default());
+        compiler.set_quiet(true);
+        compiler.declare_globals(&natives);
+        let script = compiler.check();
+        let diagnostics = compiler.diagnostics().to_vec();
+        let lines = std::iter::once(0).chain(text.match_indices('\n').map(|(i, _)| i + 1)).collect();
+        Self {
+            index: Index::new(&script),
+            text,
+            lines,
+            script,
+            diagnostics,
+        }
+    }
+
+    /// The protocol's position of `offset`: a 0-based line and a column in
+    /// UTF-16 code units.
+    fn position(&self, offset: usize) -> Json {
+        let offset = offset.min(self.text.len());
+        let line = self.lines.partition_point(|start| *start <= offset) - 1;
+        let prefix = self.text.get(self.lines[line]..offset).unwrap_or("");
+        Json::object([
+            ("line", Json::from(line)),
+            ("character", Json::from(prefix.encode_utf16().count())),
+        ])
+    }
+
+    fn range(&self, span: Span) -> Json {
+        Json::object([
+            ("start", self.position(span.offset)),
+            ("end", self.position(span.offset + span.len)),
+        ])
+    }
+
+    /// The offset of a position of the protocol, clamped to its line.
+    fn offset(&self, position: &Json) -> Option<usize> {
+        let line = position.get("line")?.as_f64()? as usize;
+        let character = position.get("character")?.as_f64()? as usize;
+        let start = *self.lines.get(line)?;
+        let end = self.lines.get(line + 1).map_or(self.text.len(), |next| next - 1);
+        let mut units = 0;
+        for (i, c) in self.text[start..end].char_indices() {
+            if units >= character {
+                return Some(start + i);
+            }
+            units += c.len_utf16();
+        }
+        Some(end)
+    }
+
+    fn diagnostics_json(&self) -> Json {
+        Json::Array(self.diagnostics.iter().map(|diagnostic| Json::object([
+            ("range", self.range(diagnostic.span)),
+            ("severity", Json::from(match diagnostic.severity {
+                Severity::Error     => 1,
+                Severity::Warning   => 2,
+            } as usize)),
+            ("source", Json::from("rslox")),
+            ("message", Json::from(diagnostic.message.as_str())),
+        ])).collect())
+    }
+
+    fn document_symbols(&self, statements: &[Stmt]) -> Vec<Json> {
+        let mut symbols = Vec::new();
+        for statement in statements {
+            match &statement.kind {
+                StmtKind::Function(function) => {
+                    let params: Vec<String> = function.params.iter().map(|param| param.name.clone()).collect();
+                    symbols.push(Json::object([
+                        ("name", Json::from(function.name.name.as_str())),
+                        ("detail", Json::from(signature(&function.name.name, &params))),
+                        ("kind", Json::from(SYMBOL_FUNCTION)),
+                        ("range", self.range(statement.start.to(statement.end))),
+                        ("selectionRange", self.range(function.name.span)),
+                        ("children", Json::Array(self.document_symbols(&function.body))),
+                    ]));
+                }
+                StmtKind::Block(statements) => symbols.extend(self.document_symbols(statements)),
+                StmtKind::If { then_branch, else_branch, .. } => {
+                    symbols.extend(self.document_symbols(std::slice::from_ref(then_branch)));
+                    if let Some(else_branch) = else_branch {
+                        symbols.extend(self.document_symbols(std::slice::from_ref(else_branch)));
+                    }
+                }
+                StmtKind::While { body, .. } | StmtKind::For { body, .. } => {
+                    symbols.extend(self.document_symbols(std::slice::from_ref(body)));
+                }
+                _ => {}
+            }
+        }
+        symbols
+    }
+}
+
+fn signature(name: &str, params: &[String]) -> String {
+    format!("fun {name}({})", params.join(", "))
+}
+
+fn arguments(arity: usize) -> String {
+    format!("Takes {arity} argument{}.", if arity == 1 { "" } else { "s" })
+}
+
+/// The Markdown shown when hovering `symbol`.
+fn hover_text(symbol: &Symbol) -> String {
+    let (code, text) = match &symbol.kind {
+        SymbolKind::Function(params)    => (signature(&symbol.name, params), arguments(params.len())),
+        SymbolKind::Native(arity)       => (format!("fun {}", symbol.name), format!("Native function. {}", arguments(*arity as usize))),
+        SymbolKind::Parameter           => (format!("var {}", symbol.name), "Parameter.".to_string()),
+        SymbolKind::Variable if symbol.global => (format!("var {}", symbol.name), "Global variable.".to_string()),
+        SymbolKind::Variable            => (format!("var {}", symbol.name), "Local variable.".to_string()),
+    };
+    format!("```lox\n{code}\n```\n{text}")
+}
+
+fn response(id: &Json, result: Json) -> Json {
+    Json::object([("jsonrpc", Json::from("2.0")), ("id", id.clone()), ("result", result)])
+}
+
+fn error_response(id: &Json, code: f64, message: &str) -> Json {
+    let error = Json::object([("code", Json::Number(code)), ("message", Json::from(message))]);
+    Json::object([("jsonrpc", Json::from("2.0")), ("id", id.clone()), ("error", error)])
+}
+
+fn notification(method: &str, params: Json) -> Json {
+    Json::object([("jsonrpc", Json::from("2.0")), ("method", Json::from(method)), ("params", params)])
+}
+
+impl Server {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Whether the client sent `exit`.
+    pub fn exited(&self) -> bool {
+        self.exited
+    }
+
+    /// The exit code the protocol asks for: 0 after a `shutdown` request.
+    pub fn exit_code(&self) -> i32 {
+        if self.shut_down { 0 } else { 1 }
+    }
+
+    /// Handles a message of the client and returns the messages to send back.
+    pub fn handle(&mut self, message: &Json) -> Vec<Json> {
+        let method = message.get("method").and_then(Json::as_str).unwrap_or("");
+        let params = message.get("params").unwrap_or(&Json::Null);
+        let Some(id) = message.get("id") else {
+            return self.notify(method, params);
+        };
+        let result = match method {
+            "initialize"                    => Some(self.initialize()),
+            "shutdown"                      => {
+                self.shut_down = true;
+                Some(Json::Null)
+            }
+            "textDocument/definition"       => self.definition(params),
+            "textDocument/hover"            => self.hover(params),
+            "textDocument/documentSymbol"   => self.document_symbol(params),
+            "textDocument/completion"       => self.completion(params),
+            _ => return vec![error_response(id, METHOD_NOT_FOUND, &format!("Unknown method '{method}'."))],
+        };
+        match result {
+            Some(result)    => vec![response(id, result)],
+            None            => vec![error_response(id, INVALID_PARAMS, "Invalid parameters.")],
+        }
+    }
+
+    fn notify(&mut self, method: &str, params: &Json) -> Vec<Json> {
+        let document = params.get("textDocument");
+        let Some(uri) = document.and_then(|document| document.get("uri")).and_then(Json::as_str) else {
+            if method == "exit" {
+                self.exited = true;
+            }
+            return Vec::new();
+        };
+        let text = match method {
+            "textDocument/didOpen"      => document.and_then(|document| document.get("text")),
+            // Only whole documents are synchronized, the last change has all of it.
+            "textDocument/didChange"    => params.get("contentChanges")
+                .and_then(Json::as_array)
+                .and_then(|changes| changes.last())
+                .and_then(|change| change.get("text")),
+            "textDocument/didClose"     => {
+                self.documents.remove(uri);
+                return vec![self.publish(uri, Json::Array(Vec::new()))];
+            }
+            _                           => None,
+        };
+        let Some(text) = text.and_then(Json::as_str) else {
+            return Vec::new();
+        };
+        let document = Document::new(uri, text.to_string());
+        let diagnostics = document.diagnostics_json();
+        self.documents.insert(uri.to_string(), document);
+        vec![self.publish(uri, diagnostics)]
+    }
+
+    fn publish(&self, uri: &str, diagnostics: Json) -> Json {
+        notification("textDocument/publishDiagnostics", Json::object([
+            ("uri", Json::from(uri)),
+            ("diagnostics", diagnostics),
+        ]))
+    }
+
+    fn initialize(&self) -> Json {
+        Json::object([
+            ("capabilities", Json::object([
+                ("textDocumentSync", Json::from(1)),
+                ("definitionProvider", Json::from(true)),
+                ("hoverProvider", Json::from(true)),
+                ("documentSymbolProvider", Json::from(true)),
+                ("completionProvider", Json::object([])),
+            ])),
+            ("serverInfo", Json::object([
+                ("name", Json::from("rslox")),
+                ("version", Json::from(env!("CARGO_PKG_VERSION"))),
+            ])),
+        ])
+    }
+
+    /// The document and the offset in it a request is about.
+    fn document_at<'p>(&self, params: &'p Json) -> Option<(&'p str, &Document, usize)> {
+        let uri = params.get("textDocument")?.get("uri")?.as_str()?;
+        let document = self.documents.get(uri)?;
+        let offset = document.offset(params.get("position")?)?;
+        Some((uri, document, offset))
+    }
+
+    fn definition(&self, params: &Json) -> Option<Json> {
+        let (uri, document, offset) = self.document_at(params)?;
+        let span = document.index.symbol_at(offset).and_then(|symbol| symbol.span);
+        Some(span.map_or(Json::Null, |span| Json::object([
+            ("uri", Json::from(uri)),
+            ("range", document.range(span)),
+        ])))
+    }
+
+    fn hover(&self, params: &Json) -> Option<Json> {
+        let (_, document, offset) = self.document_at(params)?;
+        let symbol = document.index.symbol_at(offset);
+        Some(symbol.map_or(Json::Null, |symbol| Json::object([
+            ("contents", Json::object([
+                ("kind", Json::from("markdown")),
+                ("value", Json::from(hover_text(symbol))),
+            ])),
+        ])))
+    }
+
+    fn document_symbol(&self, params: &Json) -> Option<Json> {
+        let uri = params.get("textDocument")?.get("uri")?.as_str()?;
+        let document = self.documents.get(uri)?;
+        Some(Json::Array(document.document_symbols(&document.script.statements)))
+    }
+
+    fn completion(&self, params: &Json) -> Option<Json> {
+        let (_, document, offset) = self.document_at(params)?;
+        let items = document.index.visible_at(offset).into_iter().map(|symbol| {
+            let (kind, detail) = match &symbol.kind {
+                SymbolKind::Function(params)    => (COMPLETION_FUNCTION, signature(&symbol.name, params)),
+                SymbolKind::Native(arity)       => (COMPLETION_FUNCTION, format!("native fun, {}", arguments(*arity as usize))),
+                _                               => (COMPLETION_VARIABLE, format!("var {}", symbol.name)),
+            };
+            Json::object([
+                ("label", Json::from(symbol.name.as_str())),
+                ("kind", Json::from(kind)),
+                ("detail", Json::from(detail)),
+            ])
+        });
+        Some(Json::Array(items.collect()))
+    }
+}
+
+/// Reads a message framed by a `Content-Length` header, `None` at the end
+/// of the input.
+fn read_message(input: &mut impl BufRead) -> io::Result<Option<String>> {
+    let mut length = None;
+    loop {
+        let mut header = String::new();
+        if input.read_line(&mut header)? == 0 {
+            return Ok(None);
+        }
+        let header = header.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            if name.eq_ignore_ascii_case("Content-Length") {
+                length = value.trim().parse().ok();
+            }
+        }
+    }
+    let Some(length) = length else {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "Missing Content-Length header."));
+    };
+    let mut body = vec![0; length];
+    input.read_exact(&mut body)?;
+    String::from_utf8(body).map(Some).map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))
+}
+
+fn write_message(output: &mut impl Write, message: &Json) -> io::Result<()> {
+    let body = message.to_string();
+    write!(output, "Content-Length: {}\r\n\r\n{body}", body.len())?;
+    output.flush()
+}
+
+/// Serves a client speaking the protocol over `input` and `output` until it
+/// sends `exit` or closes the input, and returns the exit code.
+pub fn run(mut input: impl BufRead, mut output: impl Write) -> io::Result<i32> {
+    let mut server = Server::new();
+    while let Some(body) = read_message(&mut input)? {
+        let replies = match Json::parse(&body) {
+            Ok(message) => server.handle(&message),
+            Err(error)  => vec![error_response(&Json::Null, PARSE_ERROR, &error)],
+        };
+        for reply in &replies {
+            write_message(&mut output, reply)?;
+        }
+        if server.exited() {
+            break;
+        }
+    }
+    Ok(server.exit_code())
+}
@@ -1,53 +1,457 @@
-use crate::vm::InterpretResult;
-use crate::vm::VM;
+use rslox::ast::dump;
+use rslox::debug::{self, Disassembler};
+use rslox::compiler::{self, Compiler, Warning};
+use rslox::diagnostic::Source;
+use rslox::format;
+use rslox::interpreter::Interpreter;
+use rslox::json::Json;
+use rslox::lint;
+use rslox::lsp;
+use rslox::trace::{self, Trace};
+use rslox::vm::InterpretResult;
+use rslox::vm::Limits;
+use rslox::vm::VM;
 
-use std::io;
-use std::io::Write;
-use std::process::exit;
+use std::process::{exit, Command};
+use std::time::Duration;
 
-pub mod chunk;
-pub mod compiler;
-pub mod debug;
-pub mod native {
-    pub mod clock;
-    pub mod sqrt;
+mod repl;
+
+#[derive(Default)]
+struct Options {
+    path:           Option<String>,
+    profile:        bool,
+    profile_stacks: Option<String>,
+    profile_interval: Option<u64>,
+    coverage:       Option<String>,
+    limits:         Limits,
+    compiler:       compiler::Options,
+    tree_walk:      bool,
+    differential:   bool,
+    dump_ast:       Option<AstFormat>,
+    print_code:     bool,
+    trace:          bool,
+    trace_json:     Option<String>,
+    trace_filter:   trace::Filter,
+}
+
+/// How `--dump-ast` prints the syntax tree.
+#[derive(Clone, Copy)]
+enum AstFormat {
+    Sexpr,
+    Json,
 }
-pub mod scanner;
-pub mod value;
-pub mod vm;
+
+/// Stack reserved for each call the tree-walking interpreter may nest, which
+/// evaluates a call through a few nested calls of its own.
+const TREE_WALK_STACK_PER_FRAME: usize = 32 * 1024;
 
 fn main() {
-    let args: Vec<String> = std::env::args().collect();
-    if args.len() == 1 {
-        repl();
-    } else if args.len() == 2 {
-        run_file(&args[1]);
-    } else {
-        eprintln!("Usage: rslox [path]");
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    match args.first().map(String::as_str) {
+        Some("lsp") => run_lsp(),
+        Some("fmt") => run_fmt(&args[1..]),
+        Some("lint") => run_lint(&args[1..]),
+        Some("disasm") => run_disasm(&args[1..]),
+        _           => {}
+    }
+    let options = parse_args(args.iter().cloned());
+    let vm_only = options.profile || options.coverage.is_some() || options.print_code || options.trace || options.trace_json.is_some()
+        || options.limits.instructions.is_some() || options.limits.timeout.is_some()
+        || options.limits.stack != Limits::default().stack || options.limits.heap.is_some();
+    if options.differential && vm_only {
+        eprintln!("--differential cannot be combined with --profile, --profile-stacks, --profile-interval, --coverage, --print-code, --trace, --trace-json, --max-instructions, --timeout, --max-stack or --max-heap, which only the VM supports.");
         exit(64);
     }
+    match &options.path {
+        None                            => repl::run(),
+        Some(_) if options.differential => run_differential(&args),
+        Some(path) if options.dump_ast.is_some() => run_dump_ast(path, &options),
+        Some(path) if options.tree_walk => run_tree_walk(path, &options),
+        Some(path)                      => run_file(path, &options),
+    }
 }
 
-fn repl() {
-    let mut vm = VM::new();
-    let mut line = String::new();
-    loop {
-        print!("> ");
-        io::stdout().flush().unwrap();
-        std::io::stdin()
-            .read_line(&mut line)
-            .expect("Failed to read line");
-        vm.interpret(&line);
-        line.clear();
+fn usage() -> ! {
+    eprintln!("Usage: rslox [--profile] [--profile-stacks <file>] [--profile-interval <n>]\n             [--coverage <file>] [--max-instructions <n>] [--timeout <ms>]\n             [--max-frames <n>] [--max-stack <n>] [--max-heap <bytes>]\n             [--max-errors <n>] [-W<warning>] [-Wno-<warning>] [-O] [--strict]\n             [--tree-walk] [--differential] [--dump-ast[=json]]\n             [--print-code] [--trace] [--trace-json <file>]\n             [--trace-function <name>] [--trace-lines <from>-<to>] [path]\n       rslox fmt [--check | --write] <path>...\n       rslox lint [--json] [--config <file>] <path>...\n       rslox disasm [--json] [-O] <path>\n       rslox lsp");
+    exit(64);
+}
+
+fn unknown_warning(name: &str) -> ! {
+    let names: Vec<&str> = Warning::ALL.iter().map(|warning| warning.name()).collect();
+    eprintln!("Unknown warning '{name}', expected one of: {}.", names.join(", "));
+    exit(64);
+}
+
+fn number(arg: Option<String>) -> u64 {
+    arg.and_then(|arg| arg.parse().ok()).unwrap_or_else(|| usage())
+}
+
+fn parse_args(mut args: impl Iterator<Item = String>) -> Options {
+    let mut options = Options::default();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--profile"         => options.profile = true,
+            "--profile-stacks"  => {
+                options.profile = true;
+                options.profile_stacks = Some(args.next().unwrap_or_else(|| usage()));
+            }
+            "--profile-interval" => {
+                options.profile = true;
+                options.profile_interval = Some(number(args.next()));
+            }
+            "--coverage"        => options.coverage = Some(args.next().unwrap_or_else(|| usage())),
+            "--max-instructions"    => options.limits.instructions = Some(number(args.next())),
+            "--timeout"             => options.limits.timeout = Some(Duration::from_millis(number(args.next()))),
+            "--max-frames"          => options.limits.frames = number(args.next()) as usize,
+            "--max-stack"           => options.limits.stack = number(args.next()) as usize,
+            "--max-heap"            => options.limits.heap = Some(number(args.next()) as usize),
+            "--max-errors"          => options.compiler.max_errors = Some(number(args.next()) as usize),
+            "-O"                    => options.compiler.optimize = true,
+            "--strict"              => options.compiler.strict = true,
+            "--tree-walk"           => options.tree_walk = true,
+            "--differential"        => options.differential = true,
+            "--dump-ast"            => options.dump_ast = Some(AstFormat::Sexpr),
+            "--dump-ast=sexpr"      => options.dump_ast = Some(AstFormat::Sexpr),
+            "--dump-ast=json"       => options.dump_ast = Some(AstFormat::Json),
+            "--print-code"          => options.print_code = true,
+            "--trace"               => options.trace = true,
+            "--trace-json"          => options.trace_json = Some(args.next().unwrap_or_else(|| usage())),
+            "--trace-function"      => options.trace_filter.functions.push(args.next().unwrap_or_else(|| usage())),
+            "--trace-lines"         => match trace::Filter::parse_lines(&args.next().unwrap_or_else(|| usage())) {
+                Ok(lines)   => options.trace_filter.lines = Some(lines),
+                Err(error)  => {
+                    eprintln!("Invalid --trace-lines: {error}");
+                    exit(64);
+                }
+            },
+            "-Wall"                 => options.compiler.warnings = compiler::Warnings::all(),
+            "-Wnone"                => options.compiler.warnings = compiler::Warnings::none(),
+            _ if arg.starts_with("-Wno-") => match Warning::from_name(&arg[5..]) {
+                Some(warning)   => options.compiler.warnings.disable(warning),
+                None            => unknown_warning(&arg[5..]),
+            },
+            _ if arg.starts_with("-W") => match Warning::from_name(&arg[2..]) {
+                Some(warning)   => options.compiler.warnings.enable(warning),
+                None            => unknown_warning(&arg[2..]),
+            },
+            _ if arg.starts_with("--")  => usage(),
+            _ if options.path.is_none() => options.path = Some(arg),
+            _                           => usage(),
+        }
     }
+    options
 }
 
-fn run_file(path: &str) {
+fn run_file(path: &str, options: &Options) {
     let mut vm = VM::new();
+    vm.set_limits(options.limits.clone());
+    vm.set_compiler_options(options.compiler.clone());
+    if options.profile {
+        vm.enable_profiler();
+    }
+    if let (Some(profiler), Some(interval)) = (vm.profiler_mut(), options.profile_interval) {
+        profiler.set_sample_interval(interval);
+    }
+    if options.coverage.is_some() {
+        vm.enable_coverage();
+    }
+    if options.print_code || options.trace || options.trace_json.is_some() {
+        vm.enable_trace(new_trace(options));
+    }
     let source = std::fs::read_to_string(path).expect("Could not open file.");
-    match vm.interpret(&source) {
+    let result = vm.interpret_named(path, &source);
+    if let Some(Err(error)) = vm.trace_mut().map(Trace::finish) {
+        eprintln!("Could not write the trace: {error}");
+    }
+    if let Some(profiler) = vm.profiler() {
+        eprint!("{}", profiler.report());
+        if let Some(stacks) = &options.profile_stacks {
+            let written = std::fs::File::create(stacks)
+                .and_then(|mut file| profiler.write_collapsed(&mut file));
+            if let Err(error) = written {
+                eprintln!("Could not write '{stacks}': {error}");
+            }
+        }
+    }
+    if let (Some(coverage), Some(lcov)) = (vm.coverage(), &options.coverage) {
+        let written = std::fs::File::create(lcov)
+            .and_then(|mut file| coverage.write_lcov(&mut file));
+        if let Err(error) = written {
+            eprintln!("Could not write '{lcov}': {error}");
+        }
+    }
+    exit_with(result);
+}
+
+/// The trace of `--print-code`, `--trace` and `--trace-json`. Listings and
+/// the readable trace go to stderr to keep them apart from the script's output.
+fn new_trace(options: &Options) -> Trace {
+    let mut trace = Trace::new(options.trace_filter.clone());
+    if options.print_code {
+        trace.print_code(Box::new(std::io::stderr()));
+    }
+    if options.trace {
+        trace.trace_text(Box::new(std::io::stderr()));
+    }
+    if let Some(path) = &options.trace_json {
+        match std::fs::File::create(path) {
+            Ok(file)    => trace.trace_json(Box::new(std::io::BufWriter::new(file))),
+            Err(error)  => {
+                eprintln!("Could not write '{path}': {error}");
+                exit(74);
+            }
+        }
+    }
+    trace
+}
+
+/// Formats the scripts: prints them, rewrites them with `--write`, or with
+/// `--check` lists the ones that are not formatted and exits with 1.
+fn run_fmt(args: &[String]) -> ! {
+    let mut check = false;
+    let mut write = false;
+    let mut paths = Vec::new();
+    for arg in args {
+        match arg.as_str() {
+            "--check"                   => check = true,
+            "--write"                   => write = true,
+            _ if arg.starts_with("--")  => usage(),
+            _                           => paths.push(arg),
+        }
+    }
+    if paths.is_empty() || (check && write) {
+        usage();
+    }
+    let mut code = 0;
+    for path in paths {
+        let source = std::fs::read_to_string(path).expect("Could not open file.");
+        match format::format(&source) {
+            Err(diagnostics) => {
+                let source = Source::new(path, &source);
+                for diagnostic in diagnostics {
+                    eprint!("{}", diagnostic.render(&source));
+                }
+                code = 65;
+            }
+            Ok(formatted) if formatted == source && (check || write) => {}
+            Ok(_) if check => {
+                eprintln!("{path} is not formatted.");
+                code = code.max(1);
+            }
+            Ok(formatted) if write => {
+                if let Err(error) = std::fs::write(path, formatted) {
+                    eprintln!("Could not write '{path}': {error}");
+                    code = 74;
+                }
+            }
+            Ok(formatted) => print!("{formatted}"),
+        }
+    }
+    exit(code);
+}
+
+/// Where `rslox lint` looks for its configuration without `--config`.
+const LINT_CONFIG: &str = ".rsloxlint";
+
+/// Lints the scripts and prints what the rules found, as warnings or as a
+/// JSON array. Exits with 1 if they found anything.
+fn run_lint(args: &[String]) -> ! {
+    let mut json = false;
+    let mut config_path = None;
+    let mut paths = Vec::new();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--json"                    => json = true,
+            "--config"                  => config_path = Some(args.next().unwrap_or_else(|| usage()).as_str()),
+            _ if arg.starts_with("--")  => usage(),
+            _                           => paths.push(arg),
+        }
+    }
+    if paths.is_empty() {
+        usage();
+    }
+    let config_text = match config_path {
+        Some(path)  => std::fs::read_to_string(path).map(Some),
+        None        => std::fs::read_to_string(LINT_CONFIG).map(Some).or_else(|error| match error.kind() {
+            std::io::ErrorKind::NotFound    => Ok(None),
+            _                               => Err(error),
+        }),
+    };
+    let config_name = config_path.unwrap_or(LINT_CONFIG);
+    let config = match config_text.map(|text| text.map(|text| lint::Config::parse(&text))) {
+        Ok(None)                => lint::Config::default(),
+        Ok(Some(Ok(config)))    => config,
+        Ok(Some(Err(error)))    => {
+            eprintln!("{config_name}: {error}");
+            exit(64);
+        }
+        Err(error)              => {
+            eprintln!("Could not read '{config_name}': {error}");
+            exit(64);
+        }
+    };
+
+    let mut code = 0;
+    let mut found = Vec::new();
+    for path in paths {
+        let source = std::fs::read_to_string(path).expect("Could not open file.");
+        let options = compiler::Options { warnings: compiler::Warnings::none(), ..Default::default() };
+        let mut compiler = Compiler::with_options(path, &source, options);
+        let Some(script) = compiler.analyze() else {
+            code = 65;
+            continue;
+        };
+        for found_lint in lint::lint(&script, &config) {
+            if json {
+                found.push(found_lint.to_json(path));
+            } else {
+                print!("{}", found_lint.to_diagnostic(&source).render(compiler.source()));
+            }
+            code = code.max(1);
+        }
+    }
+    if json {
+        println!("{}", Json::Array(found));
+    }
+    exit(code);
+}
+
+/// Prints the bytecode of the script and of every function declared in it,
+/// as listings or as one JSON object.
+fn run_disasm(args: &[String]) -> ! {
+    let mut json = false;
+    let mut options = compiler::Options::default();
+    let mut path = None;
+    for arg in args {
+        match arg.as_str() {
+            "--json"                            => json = true,
+            "-O"                                => options.optimize = true,
+            _ if arg.starts_with('-')           => usage(),
+            _ if path.is_none()                 => path = Some(arg),
+            _                                   => usage(),
+        }
+    }
+    let Some(path) = path else {
+        usage();
+    };
+    let source = std::fs::read_to_string(path).expect("Could not open file.");
+    let Some(function) = Compiler::with_options(path, &source, options).compile() else {
+        exit(65);
+    };
+    if json {
+        println!("{}", debug::function_json(&function));
+    } else {
+        let mut listing = String::new();
+        for function in debug::functions(&function) {
+            let _ = Disassembler::new(&function.chunk).disassemble_chunk(debug::function_name(&function), &mut listing);
+        }
+        print!("{listing}");
+    }
+    exit(0);
+}
+
+/// Serves the language server protocol over stdin and stdout.
+fn run_lsp() -> ! {
+    match lsp::run(std::io::stdin().lock(), std::io::stdout().lock()) {
+        Ok(code)    => exit(code),
+        Err(error)  => {
+            eprintln!("Language server failed: {error}");
+            exit(1);
+        }
+    }
+}
+
+/// Prints the resolved syntax tree of the script instead of running it.
+fn run_dump_ast(path: &str, options: &Options) {
+    let source = std::fs::read_to_string(path).expect("Could not open file.");
+    let mut compiler = Compiler::with_options(path, &source, options.compiler.clone());
+    let Some(script) = compiler.analyze() else {
+        exit(65);
+    };
+    match options.dump_ast {
+        Some(AstFormat::Json)   => println!("{}", dump::json(&script)),
+        _                       => print!("{}", dump::sexpr(&script)),
+    }
+}
+
+fn exit_with(result: InterpretResult) -> ! {
+    match result {
         InterpretResult::CompileError   => exit(65),
         InterpretResult::RuntimeError   => exit(70),
+        InterpretResult::Interrupted    => exit(70),
         InterpretResult::Ok             => exit(0),
     }
 }
+
+fn run_tree_walk(path: &str, options: &Options) {
+    let path = path.to_string();
+    let compiler = options.compiler.clone();
+    let frames = options.limits.frames;
+    let source = std::fs::read_to_string(&path).expect("Could not open file.");
+    let interpreter = std::thread::Builder::new()
+        .stack_size(frames.saturating_add(1).saturating_mul(TREE_WALK_STACK_PER_FRAME))
+        .spawn(move || {
+            let mut interpreter = Interpreter::new();
+            interpreter.set_compiler_options(compiler);
+            interpreter.set_max_frames(frames);
+            interpreter.interpret_named(&path, &source)
+        });
+    let result = match interpreter {
+        Ok(interpreter) => interpreter.join().expect("The interpreter panicked."),
+        Err(error)      => {
+            eprintln!("Could not reserve the stack of the tree-walking interpreter for --max-frames {frames}: {error}");
+            exit(64);
+        }
+    };
+    exit_with(result);
+}
+
+/// Runs the script with both the VM and the tree-walking interpreter, each in
+/// a process of its own and without warnings. When they agree the run is
+/// passed on as is, otherwise the differences are reported.
+fn run_differential(args: &[String]) {
+    let exe = std::env::current_exe().expect("Could not find the rslox executable.");
+    let args: Vec<&String> = args.iter().filter(|arg| *arg != "--differential").collect();
+    let run = |engine: &[&str]| Command::new(&exe)
+        .args(&args)
+        .arg("-Wnone")
+        .args(engine)
+        .output()
+        .expect("Could not run rslox.");
+    let vm = run(&[]);
+    let tree_walk = run(&["--tree-walk"]);
+    if vm.status.code() == tree_walk.status.code() && vm.stdout == tree_walk.stdout && vm.stderr == tree_walk.stderr {
+        print!("{}", String::from_utf8_lossy(&vm.stdout));
+        eprint!("{}", String::from_utf8_lossy(&vm.stderr));
+        exit(vm.status.code().unwrap_or(70));
+    }
+    eprintln!("The VM and the tree-walking interpreter diverge.");
+    if vm.status.code() != tree_walk.status.code() {
+        eprintln!("exit code:\n  vm:        {:?}\n  tree-walk: {:?}", vm.status.code(), tree_walk.status.code());
+    }
+    report_divergence("stdout", &vm.stdout, &tree_walk.stdout);
+    report_divergence("stderr", &vm.stderr, &tree_walk.stderr);
+    exit(1);
+}
+
+/// Prints the first line that differs between the two outputs.
+fn report_divergence(name: &str, vm: &[u8], tree_walk: &[u8]) {
+    let vm = String::from_utf8_lossy(vm);
+    let tree_walk = String::from_utf8_lossy(tree_walk);
+    let mut vm_lines = vm.lines();
+    let mut tree_walk_lines = tree_walk.lines();
+    for number in 1.. {
+        match (vm_lines.next(), tree_walk_lines.next()) {
+            (None, None)        => return,
+            (a, b) if a == b    => continue,
+            (a, b)              => {
+                let a = a.map_or("<end of output>".to_string(), |line| format!("{line:?}"));
+                let b = b.map_or("<end of output>".to_string(), |line| format!("{line:?}"));
+                eprintln!("{name} line {number}:\n  vm:        {a}\n  tree-walk: {b}");
+                return;
+            }
+        }
+    }
+}
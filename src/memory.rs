@@ -0,0 +1,74 @@
+use crate::value::{Closure, Upvalue, Value};
+
+use std::cell::RefCell;
+use std::collections::HashSet;
+use std::mem;
+use std::rc::Rc;
+
+
+/// Reference counts kept next to every `Rc` allocation.
+const RC_HEADER: usize = 2 * mem::size_of::<usize>();
+
+pub fn string_size(len: usize) -> usize {
+    RC_HEADER + mem::size_of::<String>() + len
+}
+
+pub fn closure_size(upvalue_count: usize) -> usize {
+    RC_HEADER + mem::size_of::<RefCell<Closure>>() + upvalue_count * mem::size_of::<Rc<RefCell<Upvalue>>>()
+}
+
+pub fn upvalue_size() -> usize {
+    RC_HEADER + mem::size_of::<RefCell<Upvalue>>()
+}
+
+/// Sums the size of every heap object reachable from the values it is given,
+/// counting objects shared through several references only once.
+///
+/// Compiled functions and natives are not counted, as they are created by the
+/// compiler and the VM rather than by the script.
+#[derive(Default)]
+pub struct Tracer {
+    seen:   HashSet<usize>,
+    bytes:  usize,
+}
+
+impl Tracer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn bytes(&self) -> usize {
+        self.bytes
+    }
+
+    pub fn value(&mut self, value: &Value) {
+        match value {
+            Value::String(s) if self.seen.insert(Rc::as_ptr(s) as usize) => {
+                self.bytes += string_size(s.capacity());
+            }
+            Value::Closure(closure)   => self.closure(closure),
+            _ => (),
+        }
+    }
+
+    pub fn closure(&mut self, closure: &Rc<RefCell<Closure>>) {
+        if !self.seen.insert(Rc::as_ptr(closure) as usize) {
+            return;
+        }
+        let closure = closure.borrow();
+        self.bytes += closure_size(closure.upvalues.capacity());
+        for upvalue in &closure.upvalues {
+            self.upvalue(upvalue);
+        }
+    }
+
+    pub fn upvalue(&mut self, upvalue: &Rc<RefCell<Upvalue>>) {
+        if !self.seen.insert(Rc::as_ptr(upvalue) as usize) {
+            return;
+        }
+        self.bytes += upvalue_size();
+        if let Upvalue::Closed(value) = &*upvalue.borrow() {
+            self.value(value);
+        }
+    }
+}
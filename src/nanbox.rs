@@ -0,0 +1,317 @@
+use crate::interpreter;
+use crate::value::{Closure, Function, NativeFunction, Slot, Value};
+
+use std::borrow::Cow;
+use std::cell::RefCell;
+use std::fmt;
+use std::marker::PhantomData;
+use std::mem;
+use std::rc::Rc;
+
+
+/// Bits set in every quiet NaN, plus one more to keep clear of the NaN the
+/// hardware produces, which is the only NaN numbers are stored as.
+const QNAN:     u64 = 0x7ffc_0000_0000_0000;
+const SIGN_BIT: u64 = 0x8000_0000_0000_0000;
+
+const NIL:      u64 = QNAN | 1;
+const FALSE:    u64 = QNAN | 2;
+const TRUE:     u64 = QNAN | 3;
+
+/// Heap objects are 8-byte aligned, leaving the low bits of their address for
+/// the kind of object.
+const KIND_MASK:    u64 = 0b111;
+const POINTER_MASK: u64 = !(SIGN_BIT | QNAN | KIND_MASK);
+
+const STRING:   u64 = 0;
+const CLOSURE:  u64 = 1;
+const FUNCTION: u64 = 2;
+const NATIVE:   u64 = 3;
+const AST_CLOSURE:  u64 = 4;
+
+/// A `Value` packed in 8 bytes: numbers are stored as they are, everything else
+/// in the payload of a quiet NaN. Objects keep their `Rc`, whose reference is
+/// owned by the `Packed` holding the pointer. Like those `Rc`s it can't be sent
+/// to or shared with another thread.
+pub struct Packed(u64, PhantomData<Rc<()>>);
+
+impl Packed {
+    fn new(bits: u64) -> Self {
+        Self(bits, PhantomData)
+    }
+
+    fn is_object(&self) -> bool {
+        self.0 & (QNAN | SIGN_BIT) == QNAN | SIGN_BIT
+    }
+
+    fn pointer<T>(&self) -> *const T {
+        (self.0 & POINTER_MASK) as *const T
+    }
+
+    fn object<T>(rc: Rc<T>, kind: u64) -> Self {
+        let pointer = Rc::into_raw(rc) as u64;
+        debug_assert!(pointer & !POINTER_MASK == 0, "object address doesn't fit in a NaN");
+        Self::new(SIGN_BIT | QNAN | pointer | kind)
+    }
+
+    /// Runs `f` on the pointer of an object with the type of its kind.
+    fn with_pointer(&self, f: impl Fn(Pointer)) {
+        if !self.is_object() {
+            return;
+        }
+        match self.0 & KIND_MASK {
+            STRING  => f(Pointer::String(self.pointer())),
+            CLOSURE => f(Pointer::Closure(self.pointer())),
+            FUNCTION=> f(Pointer::Function(self.pointer())),
+            NATIVE  => f(Pointer::Native(self.pointer())),
+            _       => f(Pointer::AstClosure(self.pointer())),
+        }
+    }
+}
+
+enum Pointer {
+    String(*const String),
+    Closure(*const RefCell<Closure>),
+    Function(*const Function),
+    Native(*const NativeFunction),
+    AstClosure(*const interpreter::Closure),
+}
+
+impl From<Value> for Packed {
+    fn from(value: Value) -> Self {
+        match value {
+            Value::Number(n)                => Self::from_number(n),
+            Value::Nil                      => Self::new(NIL),
+            Value::Bool(false)              => Self::new(FALSE),
+            Value::Bool(true)               => Self::new(TRUE),
+            Value::String(s)                => Self::object(s, STRING),
+            Value::Closure(c)               => Self::object(c, CLOSURE),
+            Value::Function(f)              => Self::object(f, FUNCTION),
+            Value::Native(n)                => Self::object(n, NATIVE),
+            Value::AstClosure(c)            => Self::object(c, AST_CLOSURE),
+        }
+    }
+}
+
+impl Clone for Packed {
+    fn clone(&self) -> Self {
+        // SAFETY: the pointer came from `Rc::into_raw` and the reference owned
+        // by `self` keeps it alive.
+        self.with_pointer(|pointer| unsafe {
+            match pointer {
+                Pointer::String(p)      => Rc::increment_strong_count(p),
+                Pointer::Closure(p)     => Rc::increment_strong_count(p),
+                Pointer::Function(p)    => Rc::increment_strong_count(p),
+                Pointer::Native(p)      => Rc::increment_strong_count(p),
+                Pointer::AstClosure(p)  => Rc::increment_strong_count(p),
+            }
+        });
+        Self::new(self.0)
+    }
+}
+
+impl Drop for Packed {
+    fn drop(&mut self) {
+        // SAFETY: gives back the reference taken by `Packed::object` or `clone`.
+        self.with_pointer(|pointer| unsafe {
+            match pointer {
+                Pointer::String(p)      => drop(Rc::from_raw(p)),
+                Pointer::Closure(p)     => drop(Rc::from_raw(p)),
+                Pointer::Function(p)    => drop(Rc::from_raw(p)),
+                Pointer::Native(p)      => drop(Rc::from_raw(p)),
+                Pointer::AstClosure(p)  => drop(Rc::from_raw(p)),
+            }
+        });
+    }
+}
+
+impl Slot for Packed {
+    fn from_value(value: Value) -> Self {
+        Self::from(value)
+    }
+
+    fn from_number(n: f64) -> Self {
+        if n.is_nan() {
+            Self::new(f64::NAN.to_bits())
+        } else {
+            Self::new(n.to_bits())
+        }
+    }
+
+    fn value(&self) -> Value {
+        self.clone().into_value()
+    }
+
+    fn into_value(self) -> Value {
+        if let Some(n) = self.number() {
+            return Value::Number(n);
+        }
+        let bits = self.0;
+        if !self.is_object() {
+            return match bits {
+                FALSE   => Value::Bool(false),
+                TRUE    => Value::Bool(true),
+                _       => Value::Nil,
+            };
+        }
+        // The reference owned by `self` moves into the `Rc`.
+        mem::forget(self);
+        let pointer = bits & POINTER_MASK;
+        // SAFETY: see `Clone`.
+        unsafe {
+            match bits & KIND_MASK {
+                STRING  => Value::String(Rc::from_raw(pointer as *const String)),
+                CLOSURE => Value::Closure(Rc::from_raw(pointer as *const RefCell<Closure>)),
+                FUNCTION=> Value::Function(Rc::from_raw(pointer as *const Function)),
+                NATIVE  => Value::Native(Rc::from_raw(pointer as *const NativeFunction)),
+                _       => Value::AstClosure(Rc::from_raw(pointer as *const interpreter::Closure)),
+            }
+        }
+    }
+
+    fn number(&self) -> Option<f64> {
+        if self.0 & QNAN == QNAN {
+            None
+        } else {
+            Some(f64::from_bits(self.0))
+        }
+    }
+
+    fn is_falsey(&self, strict: bool) -> bool {
+        match self.0 {
+            NIL | FALSE => true,
+            _ if strict => false,
+            TRUE        => false,
+            _ => match self.number() {
+                Some(n) => n == 0.0,
+                None if self.is_object() && self.0 & KIND_MASK == STRING => {
+                    // SAFETY: see `Clone`.
+                    let s: &String = unsafe { &*self.pointer() };
+                    s.is_empty()
+                }
+                None    => false,
+            },
+        }
+    }
+
+    fn values(slots: &[Self]) -> Cow<'_, [Value]> {
+        Cow::Owned(slots.iter().map(Slot::value).collect())
+    }
+}
+
+impl fmt::Display for Packed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.value())
+    }
+}
+
+impl fmt::Debug for Packed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}", self.value())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interpreter::Interpreter;
+    use crate::native::NATIVES;
+
+    /// A value of every kind `Packed` tells apart.
+    fn values() -> Vec<Value> {
+        let (name, arity, native) = NATIVES[0];
+        let mut interpreter = Interpreter::new();
+        interpreter.interpret("fun f() {}");
+        vec![
+            Value::Number(1.5),
+            Value::Number(-2.0),
+            Value::Number(f64::INFINITY),
+            Value::Nil,
+            Value::Bool(false),
+            Value::Bool(true),
+            Value::String(Rc::new("s".to_string())),
+            Value::Closure(Rc::new(RefCell::new(Closure::new(Rc::new(Function::new()))))),
+            Value::Function(Rc::new(Function::new())),
+            Value::Native(Rc::new(NativeFunction { arity, name: Rc::new(name.to_string()), function: native })),
+            interpreter.globals()["f"].clone(),
+        ]
+    }
+
+    /// Whether `a` and `b` are the same number, constant or object.
+    fn same(a: &Value, b: &Value) -> bool {
+        match (a, b) {
+            (Value::Number(a), Value::Number(b))        => a.to_bits() == b.to_bits(),
+            (Value::Nil, Value::Nil)                    => true,
+            (Value::Bool(a), Value::Bool(b))            => a == b,
+            (Value::String(a), Value::String(b))        => Rc::ptr_eq(a, b),
+            (Value::Closure(a), Value::Closure(b))      => Rc::ptr_eq(a, b),
+            (Value::Function(a), Value::Function(b))    => Rc::ptr_eq(a, b),
+            (Value::Native(a), Value::Native(b))        => Rc::ptr_eq(a, b),
+            (Value::AstClosure(a), Value::AstClosure(b)) => Rc::ptr_eq(a, b),
+            _                                           => false,
+        }
+    }
+
+    #[test]
+    fn every_kind_round_trips() {
+        for value in values() {
+            let packed = Packed::from(value.clone());
+            assert_eq!(packed.is_object(), !matches!(value, Value::Number(_) | Value::Nil | Value::Bool(_)), "{value:?}");
+            assert!(same(&packed.value(), &value), "{value:?}");
+            assert!(same(&packed.clone().into_value(), &value), "{value:?}");
+            assert!(same(&packed.into_value(), &value), "{value:?}");
+        }
+    }
+
+    #[test]
+    fn clones_and_drops_keep_the_reference_count() {
+        let string = Rc::new("s".to_string());
+        let packed = Packed::from(Value::String(Rc::clone(&string)));
+        assert_eq!(Rc::strong_count(&string), 2);
+        let clone = packed.clone();
+        assert_eq!(Rc::strong_count(&string), 3);
+        let value = clone.value();
+        assert_eq!(Rc::strong_count(&string), 4);
+        drop(value);
+        drop(clone);
+        assert_eq!(Rc::strong_count(&string), 2);
+        let value = packed.into_value();
+        assert_eq!(Rc::strong_count(&string), 2);
+        drop(value);
+        assert_eq!(Rc::strong_count(&string), 1);
+
+        let function = Rc::new(Function::new());
+        let closure = Rc::new(RefCell::new(Closure::new(Rc::clone(&function))));
+        let packed = [Packed::from(Value::Closure(Rc::clone(&closure))), Packed::from(Value::Function(Rc::clone(&function)))];
+        let clones = packed.clone();
+        assert_eq!((Rc::strong_count(&closure), Rc::strong_count(&function)), (3, 4));
+        drop(clones);
+        drop(packed);
+        assert_eq!((Rc::strong_count(&closure), Rc::strong_count(&function)), (1, 2));
+    }
+
+    #[test]
+    fn nan_and_negative_zero_stay_numbers() {
+        let nans = [
+            f64::NAN,
+            -f64::NAN,
+            f64::from_bits(QNAN),
+            f64::from_bits(QNAN | 1),
+            f64::from_bits(SIGN_BIT | QNAN | 0x1000),
+            f64::from_bits(0x7ff0_0000_0000_0001),
+        ];
+        for nan in nans {
+            let packed = Packed::from_number(nan);
+            assert!(!packed.is_object(), "{:#x}", nan.to_bits());
+            assert!(packed.number().is_some_and(f64::is_nan), "{:#x}", nan.to_bits());
+            assert!(matches!(packed.into_value(), Value::Number(n) if n.is_nan()));
+        }
+
+        let zero = Packed::from_number(-0.0);
+        assert!(!zero.is_object());
+        assert_eq!(zero.number().map(f64::to_bits), Some((-0.0f64).to_bits()));
+        assert!(zero.is_falsey(false));
+        assert!(!zero.is_falsey(true));
+        assert!(matches!(Packed::from(Value::Number(-0.0)).into_value(), Value::Number(n) if n.to_bits() == (-0.0f64).to_bits()));
+    }
+}
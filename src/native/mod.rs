@@ -0,0 +1,12 @@
+use crate::value::Native;
+
+pub mod clock;
+pub mod sqrt;
+
+
+/// Name, arity and implementation of every native function, which both the VM
+/// and the tree-walking interpreter define as globals.
+pub const NATIVES: [(&str, u8, Native); 2] = [
+    ("clock",   0, clock::clock),
+    ("sqrt",    1, sqrt::sqrt),
+];
@@ -0,0 +1,179 @@
+use crate::chunk::OpCode;
+
+use std::collections::HashMap;
+use std::fmt::Write as _;
+use std::io;
+use std::time::{Duration, Instant};
+
+
+#[derive(Debug, Default, Clone)]
+pub struct FunctionStats {
+    pub calls:      u64,
+    pub total:      Duration,
+    pub own:        Duration,
+}
+
+struct ActiveCall {
+    name:       String,
+    started:    Instant,
+    children:   Duration,
+    path_len:   usize,
+    recursive:  bool,
+}
+
+pub struct Profiler {
+    opcodes:            [u64; 256],
+    functions:          HashMap<String, FunctionStats>,
+    lines:              HashMap<usize, u64>,
+    stacks:             HashMap<String, u64>,
+    active:             Vec<ActiveCall>,
+    depths:             HashMap<String, usize>,
+    path:               String,
+    sample_interval:    u64,
+    ticks:              u64,
+    started:            Instant,
+}
+
+impl Default for Profiler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Profiler {
+    pub fn new() -> Self {
+        Self {
+            opcodes:            [0; 256],
+            functions:          HashMap::new(),
+            lines:              HashMap::new(),
+            stacks:             HashMap::new(),
+            active:             Vec::new(),
+            depths:             HashMap::new(),
+            path:               String::new(),
+            sample_interval:    1,
+            ticks:              0,
+            started:            Instant::now(),
+        }
+    }
+
+    /// Records the call stack every `interval` instructions instead of on every one.
+    pub fn set_sample_interval(&mut self, interval: u64) {
+        self.sample_interval = interval.max(1);
+    }
+
+    pub fn instruction(&mut self, instruction: OpCode, line: usize) {
+        self.opcodes[u8::from(instruction) as usize] += 1;
+        *self.lines.entry(line).or_insert(0) += 1;
+        self.ticks += 1;
+        if self.ticks.is_multiple_of(self.sample_interval) {
+            match self.stacks.get_mut(&self.path) {
+                Some(count) => *count += 1,
+                None        => {
+                    self.stacks.insert(self.path.clone(), 1);
+                }
+            }
+        }
+    }
+
+    pub fn enter(&mut self, name: &str) {
+        let name = if name.is_empty() { "script" } else { name };
+        self.functions.entry(name.to_string()).or_default().calls += 1;
+        let path_len = self.path.len();
+        if !self.path.is_empty() {
+            self.path.push(';');
+        }
+        self.path.push_str(name);
+        let depth = self.depths.entry(name.to_string()).or_insert(0);
+        *depth += 1;
+        self.active.push(ActiveCall {
+            name: name.to_string(),
+            started: Instant::now(),
+            children: Duration::ZERO,
+            path_len,
+            recursive: *depth > 1,
+        });
+    }
+
+    pub fn exit(&mut self) {
+        let call = match self.active.pop() {
+            Some(call)  => call,
+            None        => return,
+        };
+        let elapsed = call.started.elapsed();
+        if let Some(depth) = self.depths.get_mut(&call.name) {
+            *depth -= 1;
+        }
+        let stats = self.functions.entry(call.name).or_default();
+        // Only the outermost activation counts towards the inclusive time of a
+        // recursive function, otherwise nested calls are counted repeatedly.
+        if !call.recursive {
+            stats.total += elapsed;
+        }
+        stats.own += elapsed.saturating_sub(call.children);
+        if let Some(parent) = self.active.last_mut() {
+            parent.children += elapsed;
+        }
+        self.path.truncate(call.path_len);
+    }
+
+    /// Closes every call that is still active, e.g. after a runtime error.
+    pub fn unwind(&mut self) {
+        while !self.active.is_empty() {
+            self.exit();
+        }
+    }
+
+    pub fn instructions(&self) -> u64 {
+        self.opcodes.iter().sum()
+    }
+
+    pub fn function(&self, name: &str) -> Option<&FunctionStats> {
+        self.functions.get(name)
+    }
+
+    pub fn report(&self) -> String {
+        let mut out = String::new();
+        let total = self.instructions().max(1) as f64;
+        let _ = writeln!(out, "== profile ==");
+        let _ = writeln!(out, "{} instructions in {:.3} ms", self.instructions(), millis(self.started.elapsed()));
+
+        let mut opcodes: Vec<(OpCode, u64)> = (0..=u8::MAX)
+            .filter(|byte| self.opcodes[*byte as usize] > 0)
+            .map(|byte| (OpCode::from(byte), self.opcodes[byte as usize]))
+            .collect();
+        opcodes.sort_by_key(|(_, count)| std::cmp::Reverse(*count));
+        let _ = writeln!(out, "\n{:<16} {:>12} {:>7}", "opcode", "count", "%");
+        for (opcode, count) in opcodes {
+            let _ = writeln!(out, "{:<16} {count:>12} {:>6.2}%", format!("{opcode:?}"), count as f64 * 100.0 / total);
+        }
+
+        let mut functions: Vec<(&String, &FunctionStats)> = self.functions.iter().collect();
+        functions.sort_by(|a, b| b.1.own.cmp(&a.1.own).then(a.0.cmp(b.0)));
+        let _ = writeln!(out, "\n{:<16} {:>8} {:>12} {:>12}", "function", "calls", "total ms", "self ms");
+        for (name, stats) in functions {
+            let _ = writeln!(out, "{name:<16} {:>8} {:>12.3} {:>12.3}", stats.calls, millis(stats.total), millis(stats.own));
+        }
+
+        let mut lines: Vec<(&usize, &u64)> = self.lines.iter().collect();
+        lines.sort_by(|a, b| b.1.cmp(a.1).then(a.0.cmp(b.0)));
+        let _ = writeln!(out, "\n{:<16} {:>12} {:>7}", "line", "count", "%");
+        for (line, count) in lines.into_iter().take(10) {
+            let _ = writeln!(out, "{line:<16} {count:>12} {:>6.2}%", *count as f64 * 100.0 / total);
+        }
+        out
+    }
+
+    /// Writes the sampled stacks in the collapsed format read by flamegraph tools.
+    pub fn write_collapsed<W: io::Write>(&self, out: &mut W) -> io::Result<()> {
+        let mut stacks: Vec<(&String, &u64)> = self.stacks.iter().collect();
+        stacks.sort();
+        for (stack, count) in stacks {
+            writeln!(out, "{stack} {count}")?;
+        }
+        Ok(())
+    }
+}
+
+fn millis(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
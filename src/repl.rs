@@ -0,0 +1,150 @@
+use rslox::compiler;
+use rslox::debug::Disassembler;
+use rslox::scanner::{ScanError, Scanner, TokenType};
+use rslox::value::Value;
+use rslox::vm::VM;
+
+use rustyline::error::ReadlineError;
+use rustyline::DefaultEditor;
+
+use std::path::PathBuf;
+use std::time::Instant;
+
+
+const PROMPT: &str = "> ";
+const CONTINUATION_PROMPT: &str = "... ";
+const HISTORY_FILE: &str = ".rslox_history";
+
+pub fn run() {
+    let mut editor = match DefaultEditor::new() {
+        Ok(editor)  => editor,
+        Err(error)  => {
+            eprintln!("Could not start the REPL: {error}");
+            return;
+        }
+    };
+    let history = history_path();
+    if let Some(history) = &history {
+        // A missing history file just means this is the first session.
+        let _ = editor.load_history(history);
+    }
+
+    let mut vm = new_vm();
+    let mut source = String::new();
+    loop {
+        let prompt = if source.is_empty() { PROMPT } else { CONTINUATION_PROMPT };
+        match editor.readline(prompt) {
+            Ok(line) if source.is_empty() && line.trim_start().starts_with(':') => {
+                let _ = editor.add_history_entry(line.trim());
+                command(&mut vm, line.trim());
+            }
+            Ok(line) => {
+                source.push_str(&line);
+                source.push('\n');
+                if !is_complete(&source) {
+                    continue;
+                }
+                let entry = source.trim_end();
+                if !entry.is_empty() {
+                    let _ = editor.add_history_entry(entry);
+                    vm.interpret(&source);
+                }
+                source.clear();
+            }
+            Err(ReadlineError::Interrupted) => source.clear(),
+            Err(ReadlineError::Eof)         => break,
+            Err(error)                      => {
+                eprintln!("{error}");
+                break;
+            }
+        }
+    }
+
+    if let Some(history) = &history {
+        if let Err(error) = editor.save_history(history) {
+            eprintln!("Could not save history to '{}': {error}", history.display());
+        }
+    }
+}
+
+fn new_vm() -> VM {
+    let mut vm = VM::new();
+    vm.set_compiler_options(compiler::Options {
+        repl: true,
+        ..Default::default()
+    });
+    vm
+}
+
+const HELP: &str = "\
+:dis <fn>       disassemble the global function <fn>
+:globals        list the global variables
+:load <file>    run a file in this session
+:reset          forget every global and start over
+:time <expr>    evaluate <expr> and print how long it took
+:help           show this message";
+
+fn command(vm: &mut VM, line: &str) {
+    let (name, argument) = match line.split_once(char::is_whitespace) {
+        Some((name, argument))  => (name, argument.trim()),
+        None                    => (line, ""),
+    };
+    match (name, argument) {
+        (":dis", name) if !name.is_empty() => match vm.globals().get(name) {
+            Some(Value::Closure(closure))   => Disassembler::new(&closure.borrow().function.chunk).print_chunk(name, &mut std::io::stdout()).unwrap_or_default(),
+            Some(Value::Function(function)) => Disassembler::new(&function.chunk).print_chunk(name, &mut std::io::stdout()).unwrap_or_default(),
+            Some(Value::Native(_))          => println!("'{name}' is a native function."),
+            Some(_)                         => println!("'{name}' is not a function."),
+            None                            => println!("Undefined variable '{name}'."),
+        },
+        (":globals", "") => {
+            let mut globals: Vec<(&String, &Value)> = vm.globals().iter().collect();
+            globals.sort_by(|a, b| a.0.cmp(b.0));
+            for (name, value) in globals {
+                println!("{name} = {value}");
+            }
+        }
+        (":load", path) if !path.is_empty() => match std::fs::read_to_string(path) {
+            Ok(source)  => {
+                // Files are run as they would be from the command line, without
+                // echoing their expression statements.
+                let options = vm.compiler_options().clone();
+                let mut file_options = options.clone();
+                file_options.repl = false;
+                vm.set_compiler_options(file_options);
+                vm.interpret_named(path, &source);
+                vm.set_compiler_options(options);
+            }
+            Err(error)  => println!("Could not open '{path}': {error}"),
+        },
+        (":reset", "") => *vm = new_vm(),
+        (":time", expression) if !expression.is_empty() => {
+            let started = Instant::now();
+            vm.interpret(&format!("{expression};"));
+            println!("({:.3} ms)", started.elapsed().as_secs_f64() * 1000.0);
+        }
+        (":help", "") => println!("{HELP}"),
+        _ => println!("Unknown command '{line}', try :help."),
+    }
+}
+
+fn history_path() -> Option<PathBuf> {
+    std::env::var_os("HOME").map(|home| PathBuf::from(home).join(HISTORY_FILE))
+}
+
+/// Whether `source` can be compiled as is, or is waiting for more lines to
+/// close a brace, a parenthesis or a string.
+fn is_complete(source: &str) -> bool {
+    let mut scanner = Scanner::new(source);
+    let mut depth = 0;
+    loop {
+        let token = scanner.scan_token();
+        match token.token_type {
+            TokenType::LeftBrace | TokenType::LeftParen     => depth += 1,
+            TokenType::RightBrace | TokenType::RightParen   => depth -= 1,
+            TokenType::Error(ScanError::UnterminatedString) => return false,
+            TokenType::EOF  => return depth <= 0,
+            _ => (),
+        }
+    }
+}
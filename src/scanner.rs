@@ -1,9 +1,15 @@
+use crate::diagnostic::Span;
+
 #[derive(Debug, Default)]
 pub struct Scanner<'a> {
     source: &'a str,
     start: usize,
     current: usize,
     line: usize,
+    line_start: usize,
+    start_column: usize,
+    /// The `//` comments skipped so far, when asked to keep them.
+    comments: Option<Vec<Span>>,
 }
 
 #[derive(Debug, PartialEq, Clone, Copy)]
@@ -46,15 +52,33 @@ pub enum TokenType {
     True,
     Var,
     While,
-    Error,
+    Error(ScanError),
     EOF,
 }
 
+/// Why the scanner returned an error token.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum ScanError {
+    UnexpectedCharacter,
+    UnterminatedString,
+}
+
+impl ScanError {
+    pub fn message(self) -> &'static str {
+        match self {
+            ScanError::UnexpectedCharacter  => "Unexpected character.",
+            ScanError::UnterminatedString   => "Unterminated string.",
+        }
+    }
+}
+
 #[derive(Clone, Debug)]
 pub struct Token<'a> {
     pub token_type: TokenType,
     pub value: &'a str,
     pub line: usize,
+    pub column: usize,
+    pub offset: usize,
 }
 
 impl<'a> Default for Token<'a> {
@@ -63,6 +87,20 @@ impl<'a> Default for Token<'a> {
             token_type: TokenType::EOF,
             value: "",
             line: 0,
+            column: 0,
+            offset: 0,
+        }
+    }
+}
+
+impl<'a> Token<'a> {
+    pub fn span(&self) -> Span {
+        Span {
+            line: self.line,
+            column: self.column,
+            offset: self.offset,
+            // The value of an error token is its message rather than a lexeme.
+            len: if matches!(self.token_type, TokenType::Error(_)) { 1 } else { self.value.len() },
         }
     }
 }
@@ -70,17 +108,32 @@ impl<'a> Default for Token<'a> {
 impl<'a> Scanner<'a> {
     pub fn new(source: &'a str) -> Self {
         Self {
-            source: &source,
+            source,
             start: 0,
             current: 0,
             line: 1,
+            line_start: 0,
+            start_column: 1,
+            comments: None,
         }
     }
 
+    /// Keeps the comments the scanner skips, for `comments`.
+    pub fn keep_comments(&mut self) {
+        self.comments.get_or_insert_with(Vec::new);
+    }
+
+    /// The comments skipped so far, each spanning from its `//` to the end
+    /// of its line.
+    pub fn comments(&self) -> &[Span] {
+        self.comments.as_deref().unwrap_or_default()
+    }
+
     pub fn scan_token(&mut self) -> Token<'a> {
         use TokenType::*;
         self.skip_whitespace();
         self.start = self.current;
+        self.start_column = self.start - self.line_start + 1;
         if self.is_at_end() {
             return self.make_token(EOF);
         }
@@ -125,7 +178,7 @@ impl<'a> Scanner<'a> {
                 } else if is_alpha(ch) {
                     self.identifier()
                 } else {
-                    self.error_token("Unexpected Character")
+                    self.error_token(ScanError::UnexpectedCharacter)
                 }
             }
         }
@@ -159,7 +212,7 @@ impl<'a> Scanner<'a> {
         if self.is_at_end() {
             return '\0';
         }
-        return self.source.as_bytes()[self.current + 1] as char;
+        self.source.as_bytes()[self.current + 1] as char
     }
 
     fn skip_whitespace(&mut self) {
@@ -171,9 +224,18 @@ impl<'a> Scanner<'a> {
                 }
                 '/' => {
                     if self.peek_next() == '/' {
+                        let start = self.current;
                         while self.peek() != '\n' && !self.is_at_end() {
                             self.advance();
                         }
+                        if let Some(comments) = &mut self.comments {
+                            comments.push(Span {
+                                line: self.line,
+                                column: start - self.line_start + 1,
+                                offset: start,
+                                len: self.current - start,
+                            });
+                        }
                     } else {
                         return;
                     }
@@ -181,6 +243,7 @@ impl<'a> Scanner<'a> {
                 '\n' => {
                     self.line += 1;
                     self.advance();
+                    self.line_start = self.current;
                 }
                 _ => {
                     break;
@@ -271,14 +334,15 @@ impl<'a> Scanner<'a> {
         while self.peek() != '"' && !self.is_at_end() {
             if self.peek() == '\n' {
                 self.line += 1;
+                self.line_start = self.current + 1;
             }
             self.advance();
         }
         if self.is_at_end() {
-            return self.error_token("Unterminated String");
+            return self.error_token(ScanError::UnterminatedString);
         }
         self.advance();
-        return self.make_token(TokenType::String);
+        self.make_token(TokenType::String)
     }
 
     fn is_at_end(&self) -> bool {
@@ -290,22 +354,27 @@ impl<'a> Scanner<'a> {
             token_type,
             value: &self.source[self.start..self.current],
             line: self.line,
+            column: self.start_column,
+            offset: self.start,
         }
     }
 
-    fn error_token(&self, message: &'a str) -> Token<'a> {
+    /// A token of type `Error`, whose value is the message of `error`.
+    fn error_token(&self, error: ScanError) -> Token<'a> {
         Token {
-            token_type: TokenType::Error,
-            value: &message,
+            token_type: TokenType::Error(error),
+            value: error.message(),
             line: self.line,
+            column: self.start_column,
+            offset: self.start,
         }
     }
 }
 
 fn is_digit(ch: char) -> bool {
-    '0' <= ch && ch <= '9'
+    ch.is_ascii_digit()
 }
 
 fn is_alpha(ch: char) -> bool {
-    'a' <= ch && ch <= 'z' || 'A' <= ch && ch <= 'Z' || ch == '_'
+    ch.is_ascii_lowercase() || ch.is_ascii_uppercase() || ch == '_'
 }
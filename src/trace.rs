@@ -0,0 +1,153 @@
+use crate::chunk::OpCode;
+use crate::debug::{self, Disassembler};
+use crate::json::Json;
+use crate::value::Function;
+
+use std::fmt::{self, Write as _};
+use std::io::{self, Write};
+use std::ops::RangeInclusive;
+use std::rc::Rc;
+
+
+/// Which code a trace covers. An empty filter covers all of it.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Filter {
+    /// Names of the functions to trace, `<script>` for the top level.
+    pub functions:  Vec<String>,
+    pub lines:      Option<RangeInclusive<usize>>,
+}
+
+impl Filter {
+    /// Parses a line range, `10-20` or a single line.
+    pub fn parse_lines(text: &str) -> Result<RangeInclusive<usize>, String> {
+        let number = |text: &str| text.trim().parse::<usize>().map_err(|_| format!("Expect a line number, found '{text}'."));
+        let (start, end) = match text.split_once('-') {
+            Some((start, end))  => (number(start)?, number(end)?),
+            None                => (number(text)?, number(text)?),
+        };
+        if start > end {
+            return Err(format!("The line range {start}-{end} is empty."));
+        }
+        Ok(start..=end)
+    }
+
+    pub fn matches_function(&self, function: &Function) -> bool {
+        self.functions.is_empty() || self.functions.iter().any(|name| name == debug::function_name(function))
+    }
+
+    pub fn matches_line(&self, line: usize) -> bool {
+        self.lines.as_ref().is_none_or(|lines| lines.contains(&line))
+    }
+}
+
+/// Where the VM reports the code it compiles and the instructions it runs:
+/// listings of the bytecode, a readable trace and a trace in JSON lines.
+/// Each is written only for the code `filter` covers.
+pub struct Trace {
+    pub filter: Filter,
+    code:       Option<Box<dyn Write>>,
+    text:       Option<Box<dyn Write>>,
+    json:       Option<Box<dyn Write>>,
+    /// The first error writing failed with, after which nothing is written.
+    error:      Option<io::Error>,
+}
+
+impl Trace {
+    pub fn new(filter: Filter) -> Self {
+        Self { filter, code: None, text: None, json: None, error: None }
+    }
+
+    /// Writes the bytecode of every compiled function to `out`.
+    pub fn print_code(&mut self, out: Box<dyn Write>) {
+        self.code = Some(out);
+    }
+
+    /// Writes the stack and the instruction before each one runs to `out`.
+    pub fn trace_text(&mut self, out: Box<dyn Write>) {
+        self.text = Some(out);
+    }
+
+    /// Writes a JSON object per instruction run to `out`, with the index of
+    /// its frame, its function, ip, line and opcode, and the stack.
+    pub fn trace_json(&mut self, out: Box<dyn Write>) {
+        self.json = Some(out);
+    }
+
+    /// Lists `function` and the functions declared in it.
+    pub fn compiled(&mut self, function: &Rc<Function>) {
+        if self.code.is_none() || self.error.is_some() {
+            return;
+        }
+        let mut listing = String::new();
+        for function in debug::functions(function) {
+            if self.filter.matches_function(&function) {
+                let _ = self.listing(&function, &mut listing);
+            }
+        }
+        let written = self.code.as_mut().unwrap().write_all(listing.as_bytes());
+        self.record(written);
+    }
+
+    fn listing(&self, function: &Function, out: &mut String) -> fmt::Result {
+        let disassembler = Disassembler::new(&function.chunk);
+        if self.filter.lines.is_none() {
+            return disassembler.disassemble_chunk(debug::function_name(function), out);
+        }
+        let mut instructions = disassembler.instructions().filter(|instruction| self.filter.matches_line(instruction.line)).peekable();
+        if instructions.peek().is_some() {
+            writeln!(out, "== {} ==", debug::function_name(function))?;
+        }
+        for instruction in instructions {
+            disassembler.disassemble_instruction(instruction.offset, out)?;
+        }
+        Ok(())
+    }
+
+    /// Traces the instruction at `ip` of `function`, which is about to run
+    /// in the frame at index `frame` with `stack`.
+    pub fn instruction(&mut self, frame: usize, function: &Function, ip: usize, stack: &[impl fmt::Display]) {
+        if self.error.is_some() || !self.filter.matches_function(function) || !self.filter.matches_line(function.chunk.lines[ip]) {
+            return;
+        }
+        if let Some(out) = self.text.as_mut() {
+            let mut text = String::from("          ");
+            for value in stack {
+                let _ = write!(text, "[ {value} ]");
+            }
+            text.push('\n');
+            let _ = Disassembler::new(&function.chunk).disassemble_instruction(ip, &mut text);
+            let written = out.write_all(text.as_bytes());
+            self.record(written);
+        }
+        if let Some(out) = self.json.as_mut() {
+            let instruction = Json::object([
+                ("frame", Json::from(frame)),
+                ("function", Json::from(debug::function_name(function))),
+                ("ip", Json::from(ip)),
+                ("line", Json::from(function.chunk.lines[ip])),
+                ("opcode", Json::from(format!("{:?}", OpCode::from(function.chunk.code[ip])))),
+                ("stack", Json::Array(stack.iter().map(|value| Json::from(value.to_string())).collect())),
+            ]);
+            let written = writeln!(out, "{instruction}");
+            self.record(written);
+        }
+    }
+
+    /// Flushes what was written, and returns the first error writing failed with.
+    pub fn finish(&mut self) -> io::Result<()> {
+        for out in [&mut self.code, &mut self.text, &mut self.json].into_iter().flatten() {
+            let flushed = out.flush();
+            self.error = self.error.take().or(flushed.err());
+        }
+        match self.error.take() {
+            Some(error) => Err(error),
+            None        => Ok(()),
+        }
+    }
+
+    fn record(&mut self, written: io::Result<()>) {
+        if let Err(error) = written {
+            self.error.get_or_insert(error);
+        }
+    }
+}
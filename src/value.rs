@@ -1,5 +1,7 @@
 use crate::chunk::Chunk;
+use crate::interpreter;
 
+use std::borrow::Cow;
 use std::cell::RefCell;
 use std::fmt;
 use std::ops;
@@ -12,10 +14,12 @@ pub struct Closure {
     pub upvalues: Vec<Rc<RefCell<Upvalue>>>,
 }
 
-#[derive(Debug, Clone, Default)]
-pub struct Upvalue {
-    pub location: usize,
-    pub closed: Option<Value>,
+/// A variable captured by a closure. It refers to the stack slot of the
+/// variable while that is in scope, and holds the value once the slot is gone.
+#[derive(Debug, Clone)]
+pub enum Upvalue {
+    Open(usize),
+    Closed(Value),
 }
 
 #[derive(Debug, Default, Clone)]
@@ -24,6 +28,8 @@ pub struct Function {
     pub name:   String,
     pub chunk:  Chunk,
     pub upvalue_count: usize,
+    /// Line of its `fun`, 0 for a script.
+    pub line:   usize,
 }
 
 pub type Native = fn(u8, &[Value]) -> Result<Value, String>;
@@ -45,7 +51,62 @@ pub enum Value {
     String(Rc<String>),
     Function(Rc<Function>),
     Native(Rc<NativeFunction>),
-    Upvalue(Rc<RefCell<Upvalue>>),
+    /// A function of the tree-walking interpreter.
+    AstClosure(Rc<interpreter::Closure>),
+}
+
+/// What the VM keeps on its stack: `Value` itself, or `nanbox::Packed` with the
+/// `nan_boxing` feature.
+pub trait Slot: Clone + fmt::Display + fmt::Debug {
+    fn from_value(value: Value) -> Self;
+    fn from_number(n: f64) -> Self;
+    fn value(&self) -> Value;
+    fn into_value(self) -> Value;
+    /// The number held, without touching the heap.
+    fn number(&self) -> Option<f64>;
+    /// `nil` and `false` are falsey, and unless `strict` so are `0` and `""`.
+    fn is_falsey(&self, strict: bool) -> bool;
+    /// `slots` as values, e.g. to pass them to a native function.
+    fn values(slots: &[Self]) -> Cow<'_, [Value]>;
+}
+
+impl Slot for Value {
+    fn from_value(value: Value) -> Self {
+        value
+    }
+
+    fn from_number(n: f64) -> Self {
+        Self::Number(n)
+    }
+
+    fn value(&self) -> Value {
+        self.clone()
+    }
+
+    fn into_value(self) -> Value {
+        self
+    }
+
+    fn number(&self) -> Option<f64> {
+        match self {
+            Self::Number(n) => Some(*n),
+            _               => None,
+        }
+    }
+
+    fn is_falsey(&self, strict: bool) -> bool {
+        match self {
+            Self::Nil           => true,
+            Self::Bool(b)       => !b,
+            Self::Number(n)     => !strict && *n == 0.0,
+            Self::String(s)     => !strict && s.is_empty(),
+            _                   => false,
+        }
+    }
+
+    fn values(slots: &[Self]) -> Cow<'_, [Value]> {
+        Cow::Borrowed(slots)
+    }
 }
 
 impl Closure {
@@ -64,15 +125,7 @@ impl Function {
             name: String::new(),
             chunk: Chunk::default(),
             upvalue_count: 0,
-        }
-    }
-}
-
-impl  Upvalue {
-    pub fn new(slot: usize) -> Self {
-        Self {
-            location: slot,
-            closed: None,
+            line: 0,
         }
     }
 }
@@ -84,21 +137,18 @@ impl fmt::Display for Value {
             Self::Nil           => write!(f, "nil"),
             Self::Number(n)     => write!(f, "{n}"),
             Self::String(s)     => write!(f, "{s}"),
-            Self::Function(n)   => if n.name.len() == 0 {
+            Self::Function(n)   => if n.name.is_empty() {
                 write!(f, "<script>")
             } else {
                 write!(f, "<fn {}>", n.name)
             }
-            Self::Closure(c)   => if c.borrow().function.name.len() == 0 {
+            Self::Closure(c)   => if c.borrow().function.name.is_empty() {
                 write!(f, "<script>")
             } else {
                 write!(f, "<fn {}>", c.borrow().function.name)
             }
-            Self::Native(n)     => write!(f, "<fn {}>", n.name),
-            Self::Upvalue(u) => match &u.borrow().closed {
-                Some(value) => write!(f, "{}", value),
-                None => write!(f, "<closed>"),
-            },
+            Self::Native(_)     => write!(f, "<native fn>"),
+            Self::AstClosure(c) => write!(f, "<fn {}>", c.declaration.name.name),
         }
     }
 }
@@ -133,13 +183,13 @@ impl From<bool> for Value {
 
 impl From<&Rc<RefCell<Closure>>> for Value {
     fn from(value: &Rc<RefCell<Closure>>) -> Self {
-        Self::Closure(Rc::clone(&value))
+        Self::Closure(Rc::clone(value))
     }
 }
 
 impl From<&Rc<Function>> for Value {
     fn from(value: &Rc<Function>) -> Self {
-        Self::Function(Rc::clone(&value))
+        Self::Function(Rc::clone(value))
     }
 }
 
@@ -160,9 +210,3 @@ impl From<String> for Value {
         Self::String(Rc::new(value))
     }
 }
-
-impl From<Rc<RefCell<Upvalue>>> for Value {
-    fn from(value: Rc<RefCell<Upvalue>>) -> Self {
-        Self::Upvalue(value)
-    }
-}
\ No newline at end of file
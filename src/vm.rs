@@ -1,32 +1,94 @@
-use crate::chunk::OpCode;
-use crate::compiler::Parser;
-use crate::value::{self, Function, Native, NativeFunction, Upvalue, Value};
-use crate::native::clock::clock;
-use crate::native::sqrt::sqrt;
+use crate::compiler::{self, Compiler};
+use crate::coverage::Coverage;
+use crate::memory::{self, Tracer};
+use crate::value::{self, Function, Native, NativeFunction, Slot, Upvalue, Value};
+use crate::native::NATIVES;
+use crate::profiler::Profiler;
+use crate::trace::Trace;
 
 use std::cell::{Ref, RefCell};
 use std::collections::HashMap;
 use std::rc::Rc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 
+/// How values are kept on the stack, see `value::Slot`.
+#[cfg(not(feature = "nan_boxing"))]
+type StackValue = Value;
+#[cfg(feature = "nan_boxing")]
+type StackValue = crate::nanbox::Packed;
+
 pub struct CallFrame {
     first_slot: usize,
     ip:         usize,
     function:   Rc<Function>,
     closure:    Rc<RefCell<value::Closure>>,
+    /// Frames of callers this one replaced through tail calls.
+    elided:     usize,
 }
 
 pub struct VM {
     frames:         Vec<CallFrame>,
-    stack:          Vec<Value>,
+    stack:          Vec<StackValue>,
     open_upvalues:  Vec<Rc<RefCell<Upvalue>>>,
     globals:        HashMap<String, Value>,
+    profiler:       Option<Box<Profiler>>,
+    coverage:       Option<Box<Coverage>>,
+    trace:          Option<Box<Trace>>,
+    limits:         Limits,
+    interrupt:      Arc<AtomicBool>,
+    executed:       u64,
+    deadline:       Option<Instant>,
+    allocated:      usize,
+    options:        compiler::Options,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum InterpretResult {
     Ok,
     CompileError,
     RuntimeError,
+    /// Execution was stopped by an instruction limit, a deadline or the interrupt flag.
+    Interrupted,
+}
+
+/// Bounds on a single call to `VM::interpret`. `None` means unlimited.
+#[derive(Debug, Clone)]
+pub struct Limits {
+    pub instructions:   Option<u64>,
+    pub timeout:        Option<Duration>,
+    /// Maximum depth of nested calls.
+    pub frames:         usize,
+    /// Maximum number of values on the stack.
+    pub stack:          usize,
+    /// Maximum bytes of strings, closures and upvalues the script may keep alive.
+    pub heap:           Option<usize>,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            instructions:   None,
+            timeout:        None,
+            frames:         FRAMES_MAX,
+            stack:          STACK_MAX,
+            heap:           None,
+        }
+    }
+}
+
+pub const FRAMES_MAX: usize = 10_000;
+pub const STACK_MAX: usize = FRAMES_MAX * 64;
+
+/// How many instructions run between checks of the deadline and the interrupt flag.
+const LIMIT_CHECK_INTERVAL: u64 = 1024;
+
+impl Default for VM {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl VM {
@@ -36,25 +98,140 @@ impl VM {
             stack:          Vec::new(), 
             open_upvalues:  Vec::new(),
             globals:        HashMap::new(),
+            profiler:       None,
+            coverage:       None,
+            trace:          None,
+            limits:         Limits::default(),
+            interrupt:      Arc::new(AtomicBool::new(false)),
+            executed:       0,
+            deadline:       None,
+            allocated:      0,
+            options:        compiler::Options::default(),
         };
-        vm.define_native(0, "clock", clock);
-        vm.define_native(1, "sqrt", sqrt);
+        for (name, arity, native) in NATIVES {
+            vm.define_native(arity, name, native);
+        }
         vm
     }
 
     pub fn interpret(&mut self, source: &str) -> InterpretResult {
-        let mut compiler = Parser::new(source);
+        self.interpret_named("script", source)
+    }
+
+    /// Like `interpret`, with `name` identifying the source in coverage reports.
+    pub fn interpret_named(&mut self, name: &str, source: &str) -> InterpretResult {
+        self.interrupt.store(false, Ordering::Relaxed);
+        let mut compiler = Compiler::with_options(name, source, self.options.clone());
+        compiler.declare_globals(self.globals.keys());
         let result = compiler.compile();
         if result.is_none() {
             return InterpretResult::CompileError;
         }
         let function = result.unwrap();
+        self.executed = 0;
+        self.deadline = self.limits.timeout.map(|timeout| Instant::now() + timeout);
+        if let Some(coverage) = self.coverage.as_deref_mut() {
+            coverage.register(name, source, &function);
+        }
+        if let Some(trace) = self.trace.as_deref_mut() {
+            trace.compiled(&function);
+        }
         self.push(Value::from(&function));
         let closure = Rc::new(RefCell::new(value::Closure::new(function)));
         self.pop();
         self.push(Value::from(&closure));
         self.call(closure, 0);
-        self.run()
+        let result = self.run();
+        self.interrupt.store(false, Ordering::Relaxed);
+        result
+    }
+
+    pub fn set_compiler_options(&mut self, options: compiler::Options) {
+        self.options = options;
+    }
+
+    pub fn compiler_options(&self) -> &compiler::Options {
+        &self.options
+    }
+
+    /// Switches to reference Lox semantics, see `compiler::Options::strict`.
+    pub fn set_strict(&mut self, strict: bool) {
+        self.options.strict = strict;
+    }
+
+    pub fn strict(&self) -> bool {
+        self.options.strict
+    }
+
+    pub fn globals(&self) -> &HashMap<String, Value> {
+        &self.globals
+    }
+
+    pub fn set_limits(&mut self, limits: Limits) {
+        self.limits = limits;
+    }
+
+    pub fn limits(&self) -> &Limits {
+        &self.limits
+    }
+
+    /// A flag another thread can set to stop the running script. `interpret`
+    /// clears it when it starts and when it returns, so setting it while no
+    /// script runs, or while one finishes, stops nothing.
+    pub fn interrupt_handle(&self) -> Arc<AtomicBool> {
+        Arc::clone(&self.interrupt)
+    }
+
+    /// Instructions executed by the last call to `interpret`.
+    pub fn instructions_executed(&self) -> u64 {
+        self.executed
+    }
+
+    /// Bytes of script values currently reachable from the stack and globals.
+    pub fn live_bytes(&self) -> usize {
+        let mut tracer = Tracer::new();
+        for value in &self.stack {
+            tracer.value(&value.value());
+        }
+        for value in self.globals.values() {
+            tracer.value(value);
+        }
+        for frame in &self.frames {
+            tracer.closure(&frame.closure);
+        }
+        for upvalue in &self.open_upvalues {
+            tracer.upvalue(upvalue);
+        }
+        tracer.bytes()
+    }
+
+    pub fn enable_profiler(&mut self) {
+        self.profiler = Some(Box::default());
+    }
+
+    pub fn profiler(&self) -> Option<&Profiler> {
+        self.profiler.as_deref()
+    }
+
+    pub fn profiler_mut(&mut self) -> Option<&mut Profiler> {
+        self.profiler.as_deref_mut()
+    }
+
+    pub fn enable_coverage(&mut self) {
+        self.coverage = Some(Box::default());
+    }
+
+    pub fn coverage(&self) -> Option<&Coverage> {
+        self.coverage.as_deref()
+    }
+
+    /// Reports the code compiled and the instructions run from now on to `trace`.
+    pub fn enable_trace(&mut self, trace: Trace) {
+        self.trace = Some(Box::new(trace));
+    }
+
+    pub fn trace_mut(&mut self) -> Option<&mut Trace> {
+        self.trace.as_deref_mut()
     }
 
     fn frame(&self) -> &CallFrame {
@@ -65,107 +242,168 @@ impl VM {
         self.frames.last_mut().unwrap()
     }
 
-    fn closure(&self) -> Ref<value::Closure> {
+    fn closure(&self) -> Ref<'_, value::Closure> {
         self.frame().closure.borrow()
     }
 
     fn push(&mut self, value: Value) {
-        self.stack.push(value);
+        self.stack.push(StackValue::from_value(value));
     }
 
     fn pop(&mut self) -> Value {
-        self.stack.pop().unwrap()
+        self.stack.pop().unwrap().into_value()
     }
 
-    fn peek(&self, distance: usize) -> &Value {
+    fn peek(&self, distance: usize) -> &StackValue {
         &self.stack[self.stack.len() - 1 - distance] 
     }
 
     fn reset_stack(&mut self) {
+        if let Some(profiler) = self.profiler.as_deref_mut() {
+            profiler.unwind();
+        }
         self.stack.clear();
         self.open_upvalues.clear();
         self.frames.clear();
     }
 
-    fn read_byte(&mut self) -> OpCode {
-        self.frame_mut().ip += 1;
-        self.closure().function.chunk.code[self.frame().ip - 1].into()
-    }
-
-    fn read_constant(&mut self) -> Value {
-        let constant = self.read_byte() as usize;
-        self.closure().function.chunk.constants[constant].clone()
-    }
-
-    fn read_short(&mut self) -> u16 {
-        self.frame_mut().ip += 2;
-        ((self.closure().function.chunk.code[self.frame().ip - 2] as u16) << 8) | 
-        ((self.closure().function.chunk.code[self.frame().ip - 1] as u16))
-    }
-
     fn run(&mut self) -> InterpretResult {
         use crate::chunk::OpCode::*;
         use InterpretResult::*;
+        // The running function and its ip live in locals rather than being
+        // read through `frames` for every byte. The ip is written back to the
+        // frame before anything that may report an error or push a frame, and
+        // the locals are reloaded once the frame on top changes.
+        let mut function = Rc::clone(&self.frame().function);
+        let mut ip = self.frame().ip;
+        let mut first_slot = self.frame().first_slot;
+        let strict = self.options.strict;
+
+        macro_rules! save {
+            () => {
+                self.frame_mut().ip = ip;
+            };
+        }
+        macro_rules! load {
+            () => {
+                let frame = self.frames.last().unwrap();
+                function = Rc::clone(&frame.function);
+                ip = frame.ip;
+                first_slot = frame.first_slot;
+            };
+        }
+        macro_rules! read_byte {
+            () => {{
+                ip += 1;
+                function.chunk.code[ip - 1]
+            }};
+        }
+        macro_rules! read_short {
+            () => {{
+                ip += 2;
+                ((function.chunk.code[ip - 2] as u16) << 8) | function.chunk.code[ip - 1] as u16
+            }};
+        }
+        macro_rules! read_constant {
+            () => {
+                function.chunk.constants[read_byte!() as usize].clone()
+            };
+        }
+        macro_rules! error {
+            ($message: expr) => {{
+                save!();
+                self.runtime_error($message);
+                return RuntimeError;
+            }};
+        }
+
         loop {
-            #[cfg(feature = "debug_trace_execution")]
-            {
-                print!("          ");
-                for value in self.stack.iter() {
-                    print!("[ {value} ]");
+            if let Some(trace) = self.trace.as_deref_mut() {
+                trace.instruction(self.frames.len() - 1, &function, ip, &self.stack);
+            }
+            let instruction = function.chunk.code[ip].into();
+            if let Some(profiler) = self.profiler.as_deref_mut() {
+                profiler.instruction(instruction, function.chunk.lines[ip]);
+            }
+            if let Some(coverage) = self.coverage.as_deref_mut() {
+                coverage.instruction(&function, ip);
+            }
+            ip += 1;
+            self.executed += 1;
+            if self.executed.is_multiple_of(LIMIT_CHECK_INTERVAL) || self.limits.instructions.is_some() {
+                if let Some(message) = self.exceeded_limit() {
+                    save!();
+                    self.runtime_error(message);
+                    return Interrupted;
                 }
-                println!();
-                let closure = self.closure();
-                let disassembler = crate::debug::Disassembler::new(&closure.function.chunk);
-                disassembler.disassemble_instruction(self.frame().ip);
             }
-            let instruction = self.frame().function.chunk.code[self.frame().ip].into();
-            self.frame_mut().ip += 1;
 
 
             macro_rules! bin_op {
                 ($op: tt) => {
-                    if let (Value::Number(b), Value::Number(a)) = (self.peek(0).clone(), self.peek(1).clone()) {
-                        self.pop();
-                        self.pop();
-                        self.push(Value::from(a $op b));
+                    if let (Some(b), Some(a)) = (self.peek(0).number(), self.peek(1).number()) {
+                        self.stack.pop();
+                        *self.stack.last_mut().unwrap() = StackValue::from_number(a $op b);
                     } else {
-                        self.runtime_error("Operands must be numbers");
-                        return RuntimeError;
+                        error!("Operands must be numbers.");
                     }
                 };
             }
             macro_rules! cmp_op {
                 ($op: tt) => {
-                    if let (Value::Number(b), Value::Number(a)) = (self.peek(0).clone(), self.peek(1).clone()) {
+                    if let (Some(b), Some(a)) = (self.peek(0).number(), self.peek(1).number()) {
                         self.pop();
                         self.pop();
                         self.push(Value::from(a $op b));
-                    } else if let (Value::String(b), Value::String(a)) = (self.peek(0).clone(), self.peek(1).clone()) {
+                    } else if let (Value::String(b), Value::String(a)) = (self.peek(0).value(), self.peek(1).value()) {
                         self.pop();
                         self.pop();
                         self.push(Value::from(a $op b));
                     } else {
-                        self.runtime_error("Operands must be numbers");
-                        return RuntimeError;
+                        error!("Operands must be numbers.");
                     }
                 };
             }
 
-            match instruction {
-                
-                Subtract    => bin_op!(-),
-                Multiply    => bin_op!(*),
-                Divide      => bin_op!(/),
-                Add         =>  {
-                    if let Value::String(a) = self.peek(1).clone() {
-                        let b = self.peek(0).clone();
-                        let b = b.to_string();
+            macro_rules! add {
+                () => {
+                    if let (Some(b), Some(a)) = (self.peek(0).number(), self.peek(1).number()) {
+                        self.stack.pop();
+                        *self.stack.last_mut().unwrap() = StackValue::from_number(a + b);
+                    } else if let Value::String(a) = self.peek(1).value() {
+                        let b = match self.peek(0).value() {
+                            Value::String(b)    => b.to_string(),
+                            _ if strict         => error!("Operands must be two numbers or two strings."),
+                            b                   => b.to_string(),
+                        };
+                        save!();
+                        if !self.allocate(memory::string_size(a.len() + b.len())) {
+                            return RuntimeError;
+                        }
                         self.pop();
                         self.pop();
                         self.push(Value::from(String::with_capacity(a.len() + b.len()) + &a + &b));
+                    } else if strict {
+                        error!("Operands must be two numbers or two strings.");
                     } else {
-                        bin_op!(+)
+                        error!("Operands must be numbers.");
                     }
+                };
+            }
+
+            match instruction {
+                
+                Subtract    => bin_op!(-),
+                Multiply    => bin_op!(*),
+                Divide      => bin_op!(/),
+                Add         => add!(),
+                AddLocalConstant => {
+                    let slot = read_byte!() as usize;
+                    let value = self.stack[first_slot + slot].clone();
+                    self.stack.push(value);
+                    let constant = read_constant!();
+                    self.push(constant);
+                    add!()
                 }
                 
                 True        => self.push(Value::from(true)),
@@ -181,99 +419,119 @@ impl VM {
                 }
 
                 Constant => {
-                    let constant = self.read_constant();
+                    let constant = read_constant!();
                     self.push(constant);
                 }
                 Negate => {
-                    if let Value::Number(_) = self.peek(0) {
+                    if self.peek(0).number().is_some() {
                         let value = self.pop();
                         self.push(-value);
                     } else {
-                        self.runtime_error("Operand must be a number");
-                        return RuntimeError
+                        error!("Operand must be a number.");
                     }
                 }
                 Not => {
                     let value = self.pop();
-                    let value = self.is_falsey(value);
-                    self.push(Value::from(value));
+                    self.push(Value::from(value.is_falsey(strict)));
                 },
 
                 DefineGlobal => {
-                    if let Value::String(name) = self.read_constant() {
+                    if let Value::String(name) = read_constant!() {
                         let value = self.pop();
                         self.globals.insert(name.to_string(), value);
                     }
                 }
                 GetGlobal => {
-                    if let Value::String(name) = self.read_constant() {
-                        let value = self.globals.get(&name.to_string());
+                    if let Value::String(name) = read_constant!() {
+                        let value = self.globals.get(name.as_str());
                         match value {
                             Some(v) => self.push(v.clone()),
-                            None => {
-                                self.runtime_error(&format!("Undefined variable '{name}'"));
-                                return RuntimeError;
-                            }
+                            None    => error!(&format!("Undefined variable '{name}'.")),
                         }
                     }
                 }
                 SetGlobal => {
-                    if let Value::String(name) = self.read_constant() {
-                        let val = self.peek(0).clone();
-                        let value = self.globals.get_mut(&name.to_string());
+                    if let Value::String(name) = read_constant!() {
+                        let val = self.peek(0).value();
+                        let value = self.globals.get_mut(name.as_str());
                         match value {
                             Some(v) => *v = val,
-                            None    => {
-                                self.runtime_error(&format!("Undefined variable '{name}'"));
-                                return RuntimeError;
-                            }
+                            None    => error!(&format!("Undefined variable '{name}'.")),
                         }
                     }
                 }
 
                 GetLocal        => {
-                    let slot = self.read_byte();
-                    self.push(self.stack[self.frame().first_slot + slot as usize].clone())
+                    let slot = read_byte!() as usize;
+                    let value = self.stack[first_slot + slot].clone();
+                    self.stack.push(value);
                 }
                 SetLocal        => {
-                    let mut slot = self.read_byte() as usize;
-                    slot += self.frame_mut().first_slot;
-                    self.stack[slot] = self.peek(0).clone();
+                    let slot = read_byte!() as usize;
+                    self.stack[first_slot + slot] = self.peek(0).clone();
                 }
 
                 Jump            => {
-                    let offset = self.read_short();
-                    self.frame_mut().ip += offset as usize;
+                    let offset = read_short!();
+                    ip += offset as usize;
                 }
                 JumpIfFalse     => {
-                    let offset = self.read_short();
-                    if self.is_falsey(self.peek(0).clone()) {
-                        self.frame_mut().ip += offset as usize;
+                    let offset = read_short!();
+                    let jumped = self.peek(0).is_falsey(strict);
+                    if let Some(coverage) = self.coverage.as_deref_mut() {
+                        coverage.branch(&function, ip - 3, jumped);
+                    }
+                    if jumped {
+                        ip += offset as usize;
+                    }
+                }
+                JumpIfTrue      => {
+                    let offset = read_short!();
+                    let jumped = !self.peek(0).is_falsey(strict);
+                    if let Some(coverage) = self.coverage.as_deref_mut() {
+                        coverage.branch(&function, ip - 3, jumped);
+                    }
+                    if jumped {
+                        ip += offset as usize;
                     }
                 }
                 Loop            => {
-                    let offset = self.read_short();
-                    self.frame_mut().ip -= offset as usize
-                }, 
+                    let offset = read_short!();
+                    ip -= offset as usize;
+                }
                 Call            => {
-                    let arg_count = self.read_byte() as u8;
-                    if !self.call_value(self.peek(arg_count as usize).clone(), arg_count) {
-                        return InterpretResult::RuntimeError;
+                    let arg_count = read_byte!();
+                    save!();
+                    if !self.call_value(self.peek(arg_count as usize).value(), arg_count) {
+                        return RuntimeError;
                     }
+                    load!();
+                }
+                TailCall        => {
+                    let arg_count = read_byte!();
+                    save!();
+                    if !self.tail_call(self.peek(arg_count as usize).value(), arg_count) {
+                        return RuntimeError;
+                    }
+                    load!();
                 }
                 Closure => {
-                    let function = self.read_constant();
-                    if let Value::Function(function) = function {
-                        let upvalue_count = function.upvalue_count;
-                        let closure = value::Closure::new(function);
+                    let function_value = read_constant!();
+                    if let Value::Function(function_value) = function_value {
+                        let upvalue_count = function_value.upvalue_count;
+                        save!();
+                        if !self.allocate(memory::closure_size(upvalue_count) + upvalue_count * memory::upvalue_size()) {
+                            return RuntimeError;
+                        }
+                        let closure = value::Closure::new(function_value);
                         let closure = RefCell::new(closure);
                         let closure = Rc::new(closure);
                         self.push(Value::from(&closure));
                         for _ in 0..upvalue_count {
-                            let is_local = self.read_byte() as u8;
-                            let index = self.read_byte() as usize;
+                            let is_local = read_byte!();
+                            let index = read_byte!() as usize;
                             if is_local != 0 {
-                                closure.borrow_mut().upvalues.push(self.capture_upvalue(self.frame().first_slot + index));
+                                closure.borrow_mut().upvalues.push(self.capture_upvalue(first_slot + index));
                             } else {
                                 closure.borrow_mut().upvalues.push(self.closure().upvalues[index].clone());
                             }
@@ -281,26 +539,21 @@ impl VM {
                     };
                 }
                 GetUpvalue => {
-                    let slot = self.read_byte() as usize;
-                    let upvalue = self.frame().closure.borrow().upvalues[slot].clone();
-                    if upvalue.borrow().closed.is_some() {
-                        self.push(Value::from(upvalue));
-                    } else {
-                        if let Some(closed) = upvalue.borrow().closed.clone() {
-                            self.push(closed);
-                        } else {
-                            self.push(self.stack[upvalue.borrow().location].clone());
-                        }
-                    }
+                    let slot = read_byte!() as usize;
+                    let upvalue = Rc::clone(&self.closure().upvalues[slot]);
+                    let value = match &*upvalue.borrow() {
+                        Upvalue::Open(location) => self.stack[*location].clone(),
+                        Upvalue::Closed(value)  => StackValue::from_value(value.clone()),
+                    };
+                    self.stack.push(value);
                 }
                 SetUpvalue => {
-                    let slot = self.read_byte() as usize;
-                    let location = self.closure().upvalues[slot].borrow().location;
-                    let value = self.peek(0).clone();
-                    if self.closure().upvalues[slot].borrow().closed.is_some() {
-                        self.closure().upvalues[slot].borrow_mut().closed = Some(value);                        
-                    } else {
-                        self.stack[location] = value;
+                    let slot = read_byte!() as usize;
+                    let upvalue = Rc::clone(&self.closure().upvalues[slot]);
+                    let mut upvalue = upvalue.borrow_mut();
+                    match &mut *upvalue {
+                        Upvalue::Open(location) => self.stack[*location] = self.peek(0).clone(),
+                        Upvalue::Closed(value)  => *value = self.peek(0).value(),
                     }
                 }
                 CloseUpvalue => {
@@ -309,15 +562,18 @@ impl VM {
                 }
                 Return => {
                     let result = self.pop();
-                    let slot = self.frame().first_slot;
-                    self.close_upvalues(slot);
+                    self.close_upvalues(first_slot);
                     self.frames.pop();
+                    if let Some(profiler) = self.profiler.as_deref_mut() {
+                        profiler.exit();
+                    }
                     if self.frames.is_empty() {
                         self.pop();
-                        return InterpretResult::Ok;
+                        return Ok;
                     }
-                    self.stack.truncate(slot);
+                    self.stack.truncate(first_slot);
                     self.push(result);
+                    load!();
                 },
                 Pop => {
                     self.pop();
@@ -327,32 +583,65 @@ impl VM {
         }
     }
 
+    /// Accounts for `bytes` the script is about to allocate. When the running
+    /// total passes the heap limit the live heap is measured again, and the
+    /// allocation fails if it still doesn't fit.
+    fn allocate(&mut self, bytes: usize) -> bool {
+        let limit = match self.limits.heap {
+            Some(limit) => limit,
+            None        => return true,
+        };
+        self.allocated += bytes;
+        if self.allocated > limit {
+            self.allocated = self.live_bytes() + bytes;
+            if self.allocated > limit {
+                self.allocated -= bytes;
+                self.runtime_error("Out of memory.");
+                return false;
+            }
+        }
+        true
+    }
+
+    fn exceeded_limit(&self) -> Option<&'static str> {
+        if self.limits.instructions.is_some_and(|max| self.executed > max) {
+            return Some("Instruction limit exceeded.");
+        }
+        if !self.executed.is_multiple_of(LIMIT_CHECK_INTERVAL) {
+            return None;
+        }
+        if self.interrupt.swap(false, Ordering::Relaxed) {
+            return Some("Interrupted.");
+        }
+        if self.deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+            return Some("Deadline exceeded.");
+        }
+        None
+    }
+
+    /// The upvalue for the stack slot `local`, shared by every closure that
+    /// captures it. Open upvalues are kept sorted by slot, so that finding one
+    /// is a binary search and closing them only pops from the end.
     fn capture_upvalue(&mut self, local: usize) -> Rc<RefCell<Upvalue>> {
-        for upvalue in &self.open_upvalues {
-            if upvalue.borrow().location == local {
-                return Rc::clone(&upvalue);
+        match self.open_upvalues.binary_search_by_key(&local, open_slot) {
+            Ok(index)   => Rc::clone(&self.open_upvalues[index]),
+            Err(index)  => {
+                let upvalue = Rc::new(RefCell::new(Upvalue::Open(local)));
+                self.open_upvalues.insert(index, Rc::clone(&upvalue));
+                upvalue
             }
         }
-        let created_upvalue = Upvalue::new(local);
-        let created_upvalue = Rc::new(RefCell::new(created_upvalue));
-        self.open_upvalues.push(Rc::clone(&created_upvalue));
-        created_upvalue
     }
-    
+
+    /// Moves the values of the upvalues at slot `last` and above off the stack.
     fn close_upvalues(&mut self, last: usize) {
-        let mut i = 0;
-        while i < self.open_upvalues.len() {
-            let upvalue = &mut self.open_upvalues[i];
-            if upvalue.borrow().location >= last {
-                let value = Some(self.stack[upvalue.borrow().location].clone());
-                upvalue.borrow_mut().closed = value;
-                let last_upvalue = self.open_upvalues.pop();
-                if i != self.open_upvalues.len() {
-                    self.open_upvalues[i] = last_upvalue.unwrap();
-                }
-            } else {
-                i += 1;
+        while let Some(upvalue) = self.open_upvalues.last() {
+            let slot = open_slot(upvalue);
+            if slot < last {
+                break;
             }
+            *upvalue.borrow_mut() = Upvalue::Closed(self.stack[slot].value());
+            self.open_upvalues.pop();
         }
     }
 
@@ -360,7 +649,18 @@ impl VM {
         match callee {
             Value::Closure(closure)   => self.call(closure, arg_count),
             Value::Native(native)       => {
-                let res = (native.function)(arg_count, &self.stack[self.stack.len() - arg_count as usize..self.stack.len()]);
+                if arg_count != native.arity {
+                    self.runtime_error(&format!("Expected {} arguments but got {arg_count}.", native.arity));
+                    return false;
+                }
+                if let Some(profiler) = self.profiler.as_deref_mut() {
+                    profiler.enter(&native.name);
+                }
+                let args = StackValue::values(&self.stack[self.stack.len() - arg_count as usize..]);
+                let res = (native.function)(arg_count, &args);
+                if let Some(profiler) = self.profiler.as_deref_mut() {
+                    profiler.exit();
+                }
                 let len = self.stack.len() - arg_count as usize - 1;
                 self.stack.truncate(len);
                 match res {
@@ -391,42 +691,112 @@ impl VM {
     }
 
     fn call(&mut self, closure: Rc<RefCell<value::Closure>>, arg_count: u8) -> bool {
-        if arg_count as usize != closure.borrow().function.arity {
-            self.runtime_error(&format!("Expected {} arguments but got {}", closure.borrow().function.arity, arg_count));
+        if !self.check_arity(&closure, arg_count) {
+            return false;
+        }
+        if self.frames.len() >= self.limits.frames || self.stack.len() > self.limits.stack {
+            self.runtime_error("Stack overflow.");
             return false;
         }
         let function = Rc::clone(&closure.borrow().function);
+        if let Some(profiler) = self.profiler.as_deref_mut() {
+            profiler.enter(&function.name);
+        }
+        if let Some(coverage) = self.coverage.as_deref_mut() {
+            coverage.call(&function);
+        }
         self.frames.push(CallFrame {
             function,
             ip: 0,
             first_slot: self.stack.len() - arg_count as usize - 1,
             closure,
+            elided: 0,
         });
         true
     }
 
-    fn is_falsey(&self, value: Value) -> bool {
-        match value {
-            Value::Nil          => true,
-            Value::Bool(bool)   => !bool,
-            Value::Number(n)    =>  n == 0.0,
-            Value::String(s)    =>  s.len() == 0,
-            _  =>  false,
+    /// Calls `callee` in place of the current frame, which has nothing left to
+    /// do but return the result. Natives are called as usual.
+    fn tail_call(&mut self, callee: Value, arg_count: u8) -> bool {
+        let closure = match callee {
+            Value::Closure(closure) => closure,
+            callee                  => return self.call_value(callee, arg_count),
+        };
+        if !self.check_arity(&closure, arg_count) {
+            return false;
+        }
+        let first_slot = self.frame().first_slot;
+        self.close_upvalues(first_slot);
+        let callee_slot = self.stack.len() - arg_count as usize - 1;
+        self.stack.drain(first_slot..callee_slot);
+        let function = Rc::clone(&closure.borrow().function);
+        if let Some(profiler) = self.profiler.as_deref_mut() {
+            profiler.exit();
+            profiler.enter(&function.name);
+        }
+        if let Some(coverage) = self.coverage.as_deref_mut() {
+            coverage.call(&function);
         }
+        let frame = self.frame_mut();
+        frame.function = function;
+        frame.closure = closure;
+        frame.ip = 0;
+        frame.elided += 1;
+        true
+    }
+
+    fn check_arity(&mut self, closure: &Rc<RefCell<value::Closure>>, arg_count: u8) -> bool {
+        let arity = closure.borrow().function.arity;
+        if arg_count as usize != arity {
+            self.runtime_error(&format!("Expected {arity} arguments but got {arg_count}."));
+            return false;
+        }
+        true
     }
 
     fn runtime_error(&mut self, message: &str) {
         eprintln!("{message}");
-        for frame in self.frames.iter().rev() {
-            let function = &frame.closure.borrow().function;
-            eprint!("[line {}] in ", function.chunk.lines[frame.ip - 1]);
-            if function.name.is_empty() {
-                eprintln!("script");
+        if let Some(frame) = self.frames.last() {
+            let chunk = &frame.function.chunk;
+            eprint!("{}", chunk.source.snippet(chunk.spans[frame.ip - 1]));
+        }
+        // Runaway recursion leaves thousands of identical frames, which are
+        // collapsed into a single line with a repeat count.
+        let mut frames = self.frames.iter().rev().peekable();
+        while let Some(frame) = frames.next() {
+            let line = frame.function.chunk.lines[frame.ip - 1];
+            let mut repeated = 1;
+            let mut elided = frame.elided;
+            while let Some(next) = frames.peek() {
+                if !Rc::ptr_eq(&next.function, &frame.function) || next.function.chunk.lines[next.ip - 1] != line {
+                    break;
+                }
+                repeated += 1;
+                elided += next.elided;
+                frames.next();
+            }
+            eprint!("[line {line}] in ");
+            if frame.function.name.is_empty() {
+                eprint!("script");
             } else {
-                eprintln!("{}", function.name);
+                eprint!("{}", frame.function.name);
+            }
+            if repeated > 1 {
+                eprint!(" (x {repeated})");
             }
+            if elided > 0 {
+                eprint!(" ({elided} tail {} elided)", if elided == 1 { "call" } else { "calls" });
+            }
+            eprintln!();
         }
         self.reset_stack();
     }
 
 }
+
+fn open_slot(upvalue: &Rc<RefCell<Upvalue>>) -> usize {
+    match *upvalue.borrow() {
+        Upvalue::Open(slot) => slot,
+        Upvalue::Closed(_)  => unreachable!("closed upvalue in the open list"),
+    }
+}
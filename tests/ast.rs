@@ -0,0 +1,76 @@
+use common::{run_with, stdout};
+
+mod common;
+
+
+/// The tree `--dump-ast` prints for `source`, failing the test on anything
+/// written to stderr or a non-zero exit code.
+fn dump(format: &str, source: &str) -> String {
+    stdout(run_with(&[format], source))
+}
+
+#[test]
+fn sexpr_shows_resolved_variables() {
+    let output = dump("--dump-ast", "
+        var g = 1;
+        fun outer(a) {
+          var b = a;
+          fun inner() { return a + b + g; }
+          return inner;
+        }
+        print outer(2)();
+    ");
+    assert_eq!(output, "\
+(var g 1)
+(fun outer (a)
+  (var b (local a 1))
+  (fun inner ()
+    (return (+ (+ (upvalue a 0) (upvalue b 1)) (global g))))
+  (return (local inner 3)))
+(print (call (call (global outer) 2)))
+");
+}
+
+#[test]
+fn sexpr_of_control_flow() {
+    let output = dump("--dump-ast", "
+        for (var i = 0; i < 3; i = i + 1) {
+          if (i == 1 and !false) print \"one\"; else print -i;
+        }
+        while (nil) {}
+    ");
+    assert_eq!(output, "\
+(for (var i 0) (< (local i 1) 3) (= (local i 1) (+ (local i 1) 1))
+  (block
+    (if (and (== (local i 1) 1) (! false))
+      (print \"one\")
+      (print (- (local i 1))))))
+(while nil
+  (block))
+");
+}
+
+#[test]
+fn json_has_node_positions_and_captures() {
+    let output = dump("--dump-ast=json", "fun f() {\n  var x = 1;\n  fun g() { return x; }\n  return g;\n}\n");
+    assert_eq!(output, concat!(
+        r#"{"type":"Script","statements":[{"type":"Function","line":1,"column":1,"name":"f","params":[],"#,
+        r#""upvalues":[],"captured":false,"body":[{"type":"Var","line":2,"column":3,"name":"x","#,
+        r#""initializer":{"type":"Literal","line":2,"column":11,"value":1},"captured":true},"#,
+        r#"{"type":"Function","line":3,"column":3,"name":"g","params":[],"upvalues":[{"index":1,"local":true}],"#,
+        r#""captured":false,"body":[{"type":"Return","line":3,"column":13,"value":{"type":"Variable","line":3,"#,
+        r#""column":20,"name":"x","binding":{"kind":"upvalue","index":0}}}]},{"type":"Return","line":4,"column":3,"#,
+        r#""value":{"type":"Variable","line":4,"column":10,"name":"g","binding":{"kind":"local","slot":2}}}]}]}"#,
+        "\n",
+    ));
+}
+
+#[test]
+fn errors_are_reported_instead_of_a_tree() {
+    let output = run_with(&["--dump-ast"], "{ var a = a; }\nprint;\n");
+    assert_eq!(output.status.code(), Some(65));
+    assert!(output.stdout.is_empty());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("Can't read local variable in its own initializer."), "{stderr}");
+    assert!(stderr.contains("Error at ';': Expect expression."), "{stderr}");
+}
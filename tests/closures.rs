@@ -0,0 +1,153 @@
+use common::run;
+
+mod common;
+
+
+#[test]
+fn closed_upvalue_is_its_value() {
+    let output = run("
+        fun counter() {
+          var count = 0;
+          fun increment() {
+            count = count + 1;
+            return count;
+          }
+          return increment;
+        }
+        var c = counter();
+        c();
+        print c() * 10;
+        print c() == 3;
+    ");
+    assert_eq!(output, "20\ntrue\n");
+}
+
+#[test]
+fn closures_share_a_captured_variable() {
+    let output = run("
+        var get;
+        var set;
+        fun make() {
+          var shared = \"before\";
+          fun g() { return shared; }
+          fun s(value) { shared = value; }
+          get = g;
+          set = s;
+        }
+        make();
+        print get();
+        set(\"after\");
+        print get();
+    ");
+    assert_eq!(output, "before\nafter\n");
+}
+
+#[test]
+fn open_upvalue_writes_reach_the_enclosing_function() {
+    let output = run("
+        fun outer() {
+          var x = 1;
+          fun bump() { x = x + 1; }
+          bump();
+          bump();
+          print x;
+          return x;
+        }
+        print outer() + 1;
+    ");
+    assert_eq!(output, "3\n4\n");
+}
+
+#[test]
+fn each_iteration_captures_its_own_variable() {
+    let output = run("
+        var first;
+        var second;
+        for (var i = 1; i <= 2; i = i + 1) {
+          var j = i * 10;
+          fun f() { return j; }
+          if (i == 1) first = f; else second = f;
+        }
+        print first();
+        print second();
+    ");
+    assert_eq!(output, "10\n20\n");
+}
+
+#[test]
+fn upvalues_of_upvalues() {
+    let output = run("
+        fun outer() {
+          var a = \"a\";
+          fun middle() {
+            var b = \"b\";
+            fun inner() { return a + b; }
+            return inner;
+          }
+          return middle;
+        }
+        print outer()()();
+    ");
+    assert_eq!(output, "ab\n");
+}
+
+#[test]
+fn captures_in_any_order_of_slots() {
+    let output = run("
+        fun make() {
+          var a = 1;
+          var b = 2;
+          var c = 3;
+          fun f() { return c * 100 + a * 10 + b; }
+          fun g() { return b * 100 + c * 10 + a; }
+          a = 4;
+          return f() + g();
+        }
+        print make();
+    ");
+    assert_eq!(output, "576\n");
+}
+
+#[test]
+fn captured_closure_prints_as_a_function() {
+    let output = run("
+        fun outer() {
+          fun callback() {}
+          fun show() { print callback; }
+          return show;
+        }
+        outer()();
+    ");
+    assert_eq!(output, "<fn callback>\n");
+}
+
+#[test]
+fn upvalues_close_before_a_tail_call() {
+    let output = run("
+        fun id(f) { return f; }
+        fun make(n) {
+          var captured = n;
+          fun get() { return captured; }
+          return id(get);
+        }
+        var a = make(1);
+        var b = make(2);
+        print a() + b();
+    ");
+    assert_eq!(output, "3\n");
+}
+
+#[test]
+fn block_scoped_captures_close_at_the_end_of_the_block() {
+    let output = run("
+        var f;
+        {
+          var local = \"kept\";
+          fun g() { return local; }
+          f = g;
+        }
+        var other = \"other\";
+        print f();
+    ");
+    assert_eq!(output, "kept\n");
+}
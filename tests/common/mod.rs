@@ -0,0 +1,92 @@
+//! Helpers shared by the tests that run the `rslox` binary.
+
+#![allow(dead_code)]
+
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+
+/// A file in the temporary directory, removed when it goes out of scope.
+pub struct TempFile {
+    path: PathBuf,
+}
+
+impl TempFile {
+    /// A new file holding `contents`, named with `extension`.
+    pub fn new(extension: &str, contents: &str) -> Self {
+        static FILES: AtomicUsize = AtomicUsize::new(0);
+        let path = std::env::temp_dir().join(format!(
+            "rslox-test-{}-{}.{extension}",
+            std::process::id(),
+            FILES.fetch_add(1, Ordering::Relaxed),
+        ));
+        std::fs::write(&path, contents).unwrap();
+        Self { path }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn read(&self) -> String {
+        std::fs::read_to_string(&self.path).unwrap()
+    }
+
+    pub fn write(&self, contents: &str) {
+        std::fs::write(&self.path, contents).unwrap();
+    }
+}
+
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+/// A command running the `rslox` binary.
+pub fn rslox() -> Command {
+    Command::new(env!("CARGO_BIN_EXE_rslox"))
+}
+
+/// Runs `rslox` with `args` on a script holding `source`.
+pub fn run_with(args: &[&str], source: &str) -> Output {
+    let script = TempFile::new("lox", source);
+    rslox().args(args).arg(script.path()).output().unwrap()
+}
+
+/// The exit code and stderr of running `source` with `args`.
+pub fn failure(args: &[&str], source: &str) -> (Option<i32>, String) {
+    let output = run_with(args, source);
+    (output.status.code(), String::from_utf8(output.stderr).unwrap())
+}
+
+/// The stdout of `output`, failing the test on anything written to stderr or
+/// a non-zero exit code.
+pub fn stdout(output: Output) -> String {
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(output.status.success() && stderr.is_empty(), "{}\n{stderr}", output.status);
+    String::from_utf8(output.stdout).unwrap()
+}
+
+/// Runs `source` and returns what it printed, see `stdout`.
+pub fn run(source: &str) -> String {
+    stdout(run_with(&[], source))
+}
+
+/// Adds the `.lox` scripts under `directory` to `found`, in the order of
+/// their paths.
+pub fn scripts(directory: &Path, found: &mut Vec<PathBuf>) {
+    let mut entries: Vec<PathBuf> = std::fs::read_dir(directory)
+        .unwrap_or_else(|error| panic!("Could not read '{}': {error}", directory.display()))
+        .map(|entry| entry.unwrap().path())
+        .collect();
+    entries.sort();
+    for path in entries {
+        if path.is_dir() {
+            scripts(&path, found);
+        } else if path.extension().is_some_and(|extension| extension == "lox") {
+            found.push(path);
+        }
+    }
+}
@@ -0,0 +1,138 @@
+//! Runs Lox scripts annotated the way the test suite of the reference
+//! implementation is, and checks what `rslox --strict` does with them:
+//!
+//! - `// expect: <output>` for every line the script prints,
+//! - `// expect runtime error: <message>` for a runtime error reported on the
+//!   line of the comment, which makes rslox exit with 70,
+//! - `// Error at 'x': <message>` or `// [line N] Error at 'x': <message>` for
+//!   compile errors on the line of the comment or on line N, which make rslox
+//!   exit with 65. `[c line N]` is accepted as well and `[java line N]` ignored.
+//!
+//! The scripts in `tests/lox` always run. Set `RSLOX_LOX_TESTS` to a directory
+//! to run those in it as well, e.g. the `test` directory of the reference
+//! implementation or one of its subdirectories.
+
+use std::fmt::Write as _;
+use std::path::Path;
+
+mod common;
+
+
+#[derive(Default)]
+struct Expected {
+    output:         Vec<String>,
+    compile_errors: Vec<String>,
+    runtime_error:  Option<(String, usize)>,
+}
+
+impl Expected {
+    fn parse(source: &str) -> Self {
+        let mut expected = Self::default();
+        for (index, line) in source.lines().enumerate() {
+            let number = index + 1;
+            let comment = match line.find("//") {
+                Some(start) => line[start + 2..].trim(),
+                None        => continue,
+            };
+            if let Some(output) = comment.strip_prefix("expect: ") {
+                expected.output.push(output.to_string());
+            } else if let Some(message) = comment.strip_prefix("expect runtime error: ") {
+                expected.runtime_error = Some((message.to_string(), number));
+            } else if comment.starts_with("Error") {
+                expected.compile_errors.push(format!("[line {number}] {comment}"));
+            } else if let Some(error) = comment.strip_prefix("[line ").or_else(|| comment.strip_prefix("[c line ")) {
+                if error.contains("] Error") {
+                    expected.compile_errors.push(format!("[line {error}"));
+                }
+            }
+        }
+        expected
+    }
+
+    fn exit_code(&self) -> i32 {
+        if !self.compile_errors.is_empty() {
+            65
+        } else if self.runtime_error.is_some() {
+            70
+        } else {
+            0
+        }
+    }
+}
+
+/// Runs the script at `path`, describing every way its run differs from what
+/// its annotations expect.
+fn check(path: &Path) -> Result<(), String> {
+    let source = std::fs::read_to_string(path).map_err(|error| error.to_string())?;
+    let expected = Expected::parse(&source);
+    let run = common::rslox()
+        .args(["--strict", "-Wnone"])
+        .arg(path)
+        .output()
+        .map_err(|error| error.to_string())?;
+    let stdout = String::from_utf8_lossy(&run.stdout);
+    let stderr = String::from_utf8_lossy(&run.stderr);
+    let mut failures = String::new();
+
+    let output: Vec<&str> = stdout.lines().collect();
+    if output != expected.output {
+        let _ = writeln!(failures, "expected output {:?}\n     got output {:?}", expected.output, output);
+    }
+
+    if !expected.compile_errors.is_empty() {
+        let errors: Vec<&str> = stderr.lines().filter(|line| line.starts_with("[line ") && line.contains("] Error")).collect();
+        if errors != expected.compile_errors {
+            let _ = writeln!(failures, "expected compile errors {:?}\n     got compile errors {:?}", expected.compile_errors, errors);
+        }
+    } else if let Some((message, line)) = &expected.runtime_error {
+        let first = stderr.lines().next().unwrap_or_default();
+        if first != message {
+            let _ = writeln!(failures, "expected runtime error {message:?}, got {first:?}");
+        }
+        let trace = format!("[line {line}]");
+        let reported = stderr.lines().find(|l| l.starts_with("[line "));
+        if reported.is_none_or(|l| !l.starts_with(&trace)) {
+            let _ = writeln!(failures, "expected the runtime error on line {line}");
+        }
+    } else if !stderr.is_empty() {
+        let _ = writeln!(failures, "unexpected errors:\n{stderr}");
+    }
+
+    let code = run.status.code().unwrap_or(-1);
+    if code != expected.exit_code() {
+        let _ = writeln!(failures, "expected exit code {}, got {code}", expected.exit_code());
+    }
+
+    if failures.is_empty() {
+        Ok(())
+    } else {
+        Err(failures)
+    }
+}
+
+/// Runs every script under `directory`, printing the failures and a summary.
+fn run_suite(directory: &Path) {
+    let mut paths = Vec::new();
+    common::scripts(directory, &mut paths);
+    let mut failed = 0;
+    for path in &paths {
+        if let Err(failures) = check(path) {
+            failed += 1;
+            println!("FAIL {}\n{failures}", path.strip_prefix(directory).unwrap_or(path).display());
+        }
+    }
+    println!("{}: {} passed, {failed} failed", directory.display(), paths.len() - failed);
+    assert!(failed == 0, "{failed} of {} scripts failed", paths.len());
+}
+
+#[test]
+fn conformance() {
+    run_suite(&Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/lox"));
+}
+
+#[test]
+fn external_suite() {
+    if let Some(directory) = std::env::var_os("RSLOX_LOX_TESTS") {
+        run_suite(Path::new(&directory));
+    }
+}
@@ -0,0 +1,70 @@
+mod common;
+
+
+const SCRIPT: &str = "\
+fun count(n) {
+  while (n > 0) n = n - 1;
+  return n;
+}
+count(3);
+count(0);
+fun down(n) {
+  if (n > 0) return down(n - 1);
+  return n;
+}
+print down(2);
+fun never() {
+  print 1;
+}
+";
+
+/// The lcov report of running `SCRIPT`.
+fn lcov() -> String {
+    let lcov = common::TempFile::new("info", "");
+    let script = common::TempFile::new("lox", SCRIPT);
+    let output = common::rslox().arg("--coverage").arg(lcov.path()).arg(script.path()).output().unwrap();
+    assert_eq!(common::stdout(output), "0\n");
+    lcov.read()
+}
+
+#[test]
+fn functions_are_reported_at_their_declaration_with_their_calls() {
+    let lcov = lcov();
+    let functions: Vec<_> = lcov.lines().filter(|line| line.starts_with("FN")).collect();
+    assert_eq!(functions, [
+        "FN:1,count",
+        "FN:7,down",
+        "FN:12,never",
+        "FNDA:2,count",
+        "FNDA:3,down",
+        "FNDA:0,never",
+        "FNF:3",
+        "FNH:2",
+    ]);
+}
+
+#[test]
+fn lines_and_branches_are_counted() {
+    let lcov = lcov();
+    assert!(lcov.starts_with("TN:\nSF:"), "{lcov}");
+    assert!(lcov.ends_with("end_of_record\n"), "{lcov}");
+    let lines: Vec<_> = lcov.lines().filter(|line| line.starts_with("BR") || line.starts_with("DA:2,") || line.starts_with("DA:13,")).collect();
+    assert_eq!(lines, [
+        "BRDA:2,0,0,3",
+        "BRDA:2,0,1,2",
+        "BRDA:8,1,0,2",
+        "BRDA:8,1,1,1",
+        "BRF:4",
+        "BRH:4",
+        "DA:2,5",
+        "DA:13,0",
+    ]);
+}
+
+#[test]
+fn lines_stop_at_the_end_of_the_source() {
+    let lcov = lcov();
+    let last = lcov.lines().rfind(|line| line.starts_with("DA:"));
+    assert_eq!(last, Some("DA:14,1"), "{lcov}");
+    assert!(lcov.contains("\nLF:11\nLH:10\n"), "{lcov}");
+}
@@ -0,0 +1,73 @@
+use rslox::diagnostic::{Diagnostic, Severity, Source, Span};
+
+
+fn span(line: usize, column: usize, offset: usize, len: usize) -> Span {
+    Span { line, column, offset, len }
+}
+
+#[test]
+fn the_span_is_underlined_below_its_line() {
+    let source = Source::new("script.lox", "var a = 1;\nprint a + nil;\n");
+    assert_eq!(source.snippet(span(2, 7, 17, 7)), " --> script.lox:2:7
+  |
+2 | print a + nil;
+  |       ^~~~~~~
+");
+    assert_eq!(source.snippet(span(1, 5, 4, 1)), " --> script.lox:1:5
+  |
+1 | var a = 1;
+  |     ^
+");
+}
+
+#[test]
+fn the_gutter_fits_the_line_number() {
+    let text = format!("{}print;\n", "\n".repeat(11));
+    let source = Source::new("script.lox", &text);
+    assert_eq!(source.snippet(span(12, 6, 16, 1)), "  --> script.lox:12:6
+   |
+12 | print;
+   |      ^
+");
+}
+
+#[test]
+fn columns_count_characters_and_keep_tabs() {
+    let source = Source::new("script.lox", "\tprint \"é\" + ;\n");
+    assert_eq!(source.snippet(span(1, 14, 14, 1)), " --> script.lox:1:14\n  |\n1 | \tprint \"é\" + ;\n  | \t            ^\n");
+}
+
+#[test]
+fn spans_running_past_their_line_are_cut_at_its_end() {
+    let source = Source::new("script.lox", "print \"open\nstring\";\n");
+    assert_eq!(source.snippet(span(1, 7, 6, 15)), " --> script.lox:1:7
+  |
+1 | print \"open
+  |       ^~~~~
+");
+}
+
+#[test]
+fn the_end_of_the_source_is_shown_after_its_last_character() {
+    for text in ["print 1 +", "print 1 +\n", "print 1 +\n\n\n", "print 1 +\r\n"] {
+        let source = Source::new("script.lox", text);
+        assert_eq!(source.snippet(span(2, 1, text.len(), 0)), " --> script.lox:1:10
+  |
+1 | print 1 +
+  |          ^
+", "{text:?}");
+    }
+}
+
+#[test]
+fn diagnostics_start_with_their_header() {
+    let source = Source::new("script.lox", "print 1 +\n");
+    let diagnostic = Diagnostic {
+        severity:   Severity::Error,
+        message:    "Expect expression.".to_string(),
+        line:       2,
+        span:       span(2, 1, 10, 0),
+        location:   " at end".to_string(),
+    };
+    assert_eq!(diagnostic.render(&source), format!("[line 2] Error at end: Expect expression.\n{}", source.snippet(diagnostic.span)));
+}
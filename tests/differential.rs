@@ -0,0 +1,534 @@
+//! Runs scripts with `rslox --differential`, which fails when the VM and the
+//! tree-walking interpreter disagree on what they print or report.
+//!
+//! `fuzz` generates random programs: mostly well-typed, so they run for a
+//! while, with the odd type error, undefined variable or wrong number of
+//! arguments. Set `RSLOX_FUZZ_ITERATIONS` to run more of them and
+//! `RSLOX_FUZZ_SEED` to start from another seed. Programs the engines disagree
+//! on are kept in the temporary directory.
+
+use std::path::Path;
+
+mod common;
+
+
+/// xorshift64*, enough to make programs reproducible from a seed.
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545_f491_4f6c_dd1d)
+    }
+
+    fn below(&mut self, n: usize) -> usize {
+        (self.next() % n as u64) as usize
+    }
+
+    /// True once in `n` times.
+    fn one_in(&mut self, n: usize) -> bool {
+        self.below(n) == 0
+    }
+
+    fn pick<'a, T>(&mut self, items: &'a [T]) -> &'a T {
+        &items[self.below(items.len())]
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Type {
+    Number,
+    String,
+    Bool,
+}
+
+#[derive(Clone)]
+enum Kind {
+    Value(Type),
+    /// A function taking that many numbers and returning a number.
+    Function(usize),
+    /// A function taking a number and returning a `Function(1)`.
+    Maker,
+}
+
+struct Variable {
+    name:       String,
+    kind:       Kind,
+    /// Loop counters are never assigned by the loop body.
+    assignable: bool,
+}
+
+struct Generator {
+    rng:        Rng,
+    out:        String,
+    indent:     usize,
+    /// Variables in scope, innermost last, with the number of them in each scope.
+    variables:  Vec<Variable>,
+    scopes:     Vec<usize>,
+    names:      usize,
+    /// Rough count of the constants of each function being generated, to stay
+    /// below the 256 a chunk can hold.
+    constants:  Vec<usize>,
+    /// Calls left to the function being generated. Functions only call those
+    /// declared before them, and only once, so every program ends quickly.
+    calls:      Option<usize>,
+}
+
+impl Generator {
+    fn new(seed: u64) -> Self {
+        Self {
+            rng:        Rng(seed | 1),
+            out:        String::new(),
+            indent:     0,
+            variables:  Vec::new(),
+            scopes:     Vec::new(),
+            names:      0,
+            constants:  vec![0],
+            calls:      None,
+        }
+    }
+
+    fn program(mut self) -> String {
+        let items = 4 + self.rng.below(10);
+        for _ in 0..items {
+            if *self.constants.last().unwrap() > 150 {
+                break;
+            }
+            self.declaration(2);
+        }
+        self.out
+    }
+
+    fn line(&mut self, text: &str) {
+        self.out.push_str(&"  ".repeat(self.indent));
+        self.out.push_str(text);
+        self.out.push('\n');
+    }
+
+    fn fresh(&mut self, prefix: &str) -> String {
+        self.names += 1;
+        format!("{prefix}{}", self.names)
+    }
+
+    fn constant(&mut self) {
+        *self.constants.last_mut().unwrap() += 1;
+    }
+
+    fn declare(&mut self, name: &str, kind: Kind, assignable: bool) {
+        if self.scopes.is_empty() {
+            // Defining a global takes the constant of its name.
+            self.constant();
+        }
+        self.variables.push(Variable { name: name.to_string(), kind, assignable });
+        if let Some(count) = self.scopes.last_mut() {
+            *count += 1;
+        }
+    }
+
+    fn begin_scope(&mut self) {
+        self.scopes.push(0);
+    }
+
+    fn end_scope(&mut self) {
+        let count = self.scopes.pop().unwrap();
+        self.variables.truncate(self.variables.len() - count);
+    }
+
+    fn block(&mut self, depth: usize, body: impl FnOnce(&mut Self)) {
+        self.line("{");
+        self.indent += 1;
+        self.begin_scope();
+        body(self);
+        for _ in 0..1 + self.rng.below(3) {
+            self.statement(depth);
+        }
+        self.end_scope();
+        self.indent -= 1;
+        self.line("}");
+    }
+
+    fn declaration(&mut self, depth: usize) {
+        match self.rng.below(10) {
+            0 | 1 if depth > 0    => self.function(depth),
+            2 if depth > 0        => self.maker(),
+            3 | 4               => {
+                let name = self.fresh("v");
+                let ty = *self.rng.pick(&[Type::Number, Type::Number, Type::String, Type::Bool]);
+                let value = self.expression(ty, 2);
+                self.line(&format!("var {name} = {value};"));
+                self.declare(&name, Kind::Value(ty), true);
+            }
+            _                   => self.statement(depth),
+        }
+    }
+
+    fn statement(&mut self, depth: usize) {
+        match self.rng.below(if depth > 0 { 8 } else { 4 }) {
+            0 | 1   => {
+                let ty = *self.rng.pick(&[Type::Number, Type::String, Type::Bool]);
+                let value = self.expression(ty, 3);
+                self.line(&format!("print {value};"));
+            }
+            2       => self.assignment(),
+            3       => {
+                let value = self.expression(Type::Number, 2);
+                self.line(&format!("{value};"));
+            }
+            4       => self.block(depth - 1, |generator| generator.declaration(depth - 1)),
+            5       => {
+                let condition = self.condition();
+                self.line(&format!("if ({condition})"));
+                self.block(depth - 1, |_| ());
+                if self.rng.one_in(2) {
+                    self.line("else");
+                    self.block(depth - 1, |_| ());
+                }
+            }
+            6 if self.calls.is_none() => {
+                let counter = self.fresh("i");
+                let count = self.rng.below(4);
+                self.constant();
+                self.constant();
+                self.constant();
+                self.line(&format!("for (var {counter} = 0; {counter} < {count}; {counter} = {counter} + 1)"));
+                self.begin_scope();
+                self.declare(&counter, Kind::Value(Type::Number), false);
+                self.block(depth - 1, |_| ());
+                self.end_scope();
+            }
+            7 if self.calls.is_none() => {
+                let counter = self.fresh("w");
+                let count = self.rng.below(4);
+                self.constant();
+                self.constant();
+                self.constant();
+                self.line("{");
+                self.indent += 1;
+                self.begin_scope();
+                self.line(&format!("var {counter} = 0;"));
+                self.declare(&counter, Kind::Value(Type::Number), false);
+                self.line(&format!("while ({counter} < {count})"));
+                self.block(depth - 1, |generator| {
+                    generator.line(&format!("{counter} = {counter} + 1;"));
+                });
+                self.end_scope();
+                self.indent -= 1;
+                self.line("}");
+            }
+            _       => self.assignment(),
+        }
+    }
+
+    fn assignment(&mut self) {
+        let candidates: Vec<(String, Type)> = self.variables.iter()
+            .filter(|variable| variable.assignable)
+            .filter_map(|variable| match variable.kind {
+                Kind::Value(ty) => Some((variable.name.clone(), ty)),
+                _               => None,
+            })
+            .collect();
+        if candidates.is_empty() {
+            let value = self.expression(Type::Number, 2);
+            return self.line(&format!("print {value};"));
+        }
+        let (name, ty) = self.rng.pick(&candidates).clone();
+        let value = self.expression(ty, 2);
+        self.variable_constant(&name);
+        self.line(&format!("{name} = {value};"));
+    }
+
+    /// Counts the constant of `name` if it is a global.
+    fn variable_constant(&mut self, name: &str) {
+        let locals: usize = self.scopes.iter().sum();
+        let global = self.variables.iter().rposition(|variable| variable.name == name)
+            .is_none_or(|index| index < self.variables.len() - locals);
+        if global {
+            self.constant();
+        }
+    }
+
+    fn function(&mut self, depth: usize) {
+        let name = self.fresh("f");
+        let arity = self.rng.below(3);
+        let params: Vec<String> = (0..arity).map(|_| self.fresh("p")).collect();
+        self.line(&format!("fun {name}({}) {{", params.join(", ")));
+        self.function_body(&params, |generator| {
+            for _ in 0..1 + generator.rng.below(3) {
+                generator.statement(depth - 1);
+            }
+            let value = generator.expression(Type::Number, 2);
+            generator.line(&format!("return {value};"));
+        });
+        // Declared after the body so that it doesn't call itself.
+        self.declare(&name, Kind::Function(arity), false);
+    }
+
+    fn maker(&mut self) {
+        let name = self.fresh("m");
+        let param = self.fresh("p");
+        let captured = self.fresh("c");
+        let inner = self.fresh("g");
+        let argument = self.fresh("q");
+        self.line(&format!("fun {name}({param}) {{"));
+        self.function_body(std::slice::from_ref(&param), |generator| {
+            let value = generator.expression(Type::Number, 1);
+            generator.line(&format!("var {captured} = {value};"));
+            generator.declare(&captured, Kind::Value(Type::Number), true);
+            generator.line(&format!("fun {inner}({argument}) {{"));
+            generator.function_body(std::slice::from_ref(&argument), |generator| {
+                generator.line(&format!("{captured} = {captured} + {argument};"));
+                let value = generator.expression(Type::Number, 1);
+                generator.line(&format!("return {captured} * {value};"));
+            });
+            generator.line(&format!("return {inner};"));
+        });
+        self.declare(&name, Kind::Maker, false);
+    }
+
+    fn function_body(&mut self, params: &[String], body: impl FnOnce(&mut Self)) {
+        self.indent += 1;
+        self.begin_scope();
+        self.constants.push(0);
+        let calls = self.calls.replace(1);
+        for param in params {
+            self.declare(param, Kind::Value(Type::Number), true);
+        }
+        body(self);
+        self.calls = calls;
+        self.constants.pop();
+        self.end_scope();
+        self.indent -= 1;
+        self.line("}");
+        // The closure of the function.
+        self.constant();
+    }
+
+    fn condition(&mut self) -> String {
+        let ty = *self.rng.pick(&[Type::Bool, Type::Bool, Type::Number, Type::String]);
+        self.expression(ty, 2)
+    }
+
+    fn literal(&mut self, ty: Type) -> String {
+        match ty {
+            Type::Number    => {
+                self.constant();
+                self.rng.pick(&["0", "1", "2", "3", "10", "0.5", "1.25", "100"]).to_string()
+            }
+            Type::String    => {
+                self.constant();
+                self.rng.pick(&["\"\"", "\"a\"", "\"b\"", "\"lox\"", "\"x y\""]).to_string()
+            }
+            Type::Bool      => self.rng.pick(&["true", "false", "nil"]).to_string(),
+        }
+    }
+
+    /// An expression that evaluates to `ty`, except for the odd mistake.
+    fn expression(&mut self, ty: Type, depth: usize) -> String {
+        if self.rng.one_in(400) {
+            // Wrong on purpose: a type error, an undefined variable or a call
+            // of something that isn't a function.
+            return match self.rng.below(3) {
+                0 => {
+                    let a = self.expression(Type::String, 0);
+                    let b = self.expression(Type::Number, 0);
+                    format!("({a} - {b})")
+                }
+                1 => {
+                    self.constant();
+                    "undefined".to_string()
+                }
+                _ => {
+                    let callee = self.expression(Type::Number, 0);
+                    format!("({callee})()")
+                }
+            };
+        }
+        if depth == 0 {
+            return match self.variable(ty) {
+                Some(name) if self.rng.one_in(2) => name,
+                _ => self.literal(ty),
+            };
+        }
+        let depth = depth - 1;
+        match (ty, self.rng.below(6)) {
+            (_, 0)                  => self.expression(ty, 0),
+            (_, 1)                  => {
+                let a = self.expression(ty, depth);
+                let b = self.expression(ty, depth);
+                let operator = self.rng.pick(&["and", "or"]);
+                format!("({a} {operator} {b})")
+            }
+            (Type::Number, 2)       => self.call().unwrap_or_else(|| self.expression(ty, depth)),
+            (Type::Number, 3)       => {
+                let a = self.expression(Type::Number, depth);
+                self.constant();
+                format!("sqrt({a})")
+            }
+            (Type::Number, _)       => {
+                let a = self.expression(Type::Number, depth);
+                let b = self.expression(Type::Number, depth);
+                let operator = self.rng.pick(&["+", "-", "*", "/", "+"]);
+                if self.rng.one_in(4) {
+                    format!("-({a} {operator} {b})")
+                } else {
+                    format!("({a} {operator} {b})")
+                }
+            }
+            (Type::String, _)       => {
+                let a = self.expression(Type::String, depth);
+                let b = self.expression(Type::String, depth);
+                format!("({a} + {b})")
+            }
+            (Type::Bool, 2)         => {
+                let a = self.condition();
+                format!("!{a}")
+            }
+            (Type::Bool, _)         => {
+                let operand = *self.rng.pick(&[Type::Number, Type::Number, Type::String, Type::Bool]);
+                let a = self.expression(operand, depth);
+                let b = self.expression(operand, depth);
+                let operator = match operand {
+                    Type::Bool  => self.rng.pick(&["==", "!="]),
+                    _           => self.rng.pick(&["==", "!=", "<", "<=", ">", ">="]),
+                };
+                format!("({a} {operator} {b})")
+            }
+        }
+    }
+
+    fn variable(&mut self, ty: Type) -> Option<String> {
+        let candidates: Vec<String> = self.variables.iter()
+            .filter(|variable| matches!(variable.kind, Kind::Value(t) if t == ty))
+            .map(|variable| variable.name.clone())
+            .collect();
+        if candidates.is_empty() {
+            return None;
+        }
+        let name = self.rng.pick(&candidates).clone();
+        self.variable_constant(&name);
+        Some(name)
+    }
+
+    /// A call of a function in scope returning a number, if calls are left.
+    fn call(&mut self) -> Option<String> {
+        if self.calls == Some(0) {
+            return None;
+        }
+        let candidates: Vec<(String, Kind)> = self.variables.iter()
+            .filter(|variable| !matches!(variable.kind, Kind::Value(_)))
+            .map(|variable| (variable.name.clone(), variable.kind.clone()))
+            .collect();
+        if candidates.is_empty() {
+            return None;
+        }
+        if let Some(calls) = &mut self.calls {
+            *calls -= 1;
+        }
+        let (name, kind) = self.rng.pick(&candidates).clone();
+        self.variable_constant(&name);
+        let mut arity = match kind {
+            Kind::Function(arity)   => arity,
+            _                       => 1,
+        };
+        if self.rng.one_in(100) {
+            arity += 1;
+        }
+        let arguments: Vec<String> = (0..arity).map(|_| self.expression(Type::Number, 0)).collect();
+        let call = format!("{name}({})", arguments.join(", "));
+        Some(match kind {
+            Kind::Maker => {
+                let argument = self.expression(Type::Number, 0);
+                format!("{call}({argument})")
+            }
+            _ => call,
+        })
+    }
+}
+
+/// Runs `path` with `rslox --differential`, returning its report if the
+/// engines disagree.
+fn differential(path: &Path, strict: bool) -> Result<(), String> {
+    let mut command = common::rslox();
+    command.arg("--differential");
+    if strict {
+        command.arg("--strict");
+    }
+    let run = command.arg(path).output().unwrap();
+    if run.status.code() == Some(1) {
+        return Err(String::from_utf8_lossy(&run.stderr).into_owned());
+    }
+    Ok(())
+}
+
+#[test]
+fn conformance_scripts() {
+    let mut paths = Vec::new();
+    common::scripts(&Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/lox"), &mut paths);
+    let mut failed = 0;
+    for path in &paths {
+        for strict in [false, true] {
+            if let Err(report) = differential(path, strict) {
+                failed += 1;
+                println!("DIVERGE {}{}\n{report}", path.display(), if strict { " --strict" } else { "" });
+            }
+        }
+    }
+    assert!(failed == 0, "the engines diverge on {failed} runs");
+}
+
+#[test]
+fn options_of_the_vm_alone_are_rejected() {
+    let options = [
+        &["--profile"][..],
+        &["--profile-stacks", "stacks.txt"],
+        &["--profile-interval", "10"],
+        &["--coverage", "lcov.info"],
+        &["--print-code"],
+        &["--trace"],
+        &["--trace-json", "trace.jsonl"],
+        &["--max-instructions", "1000"],
+        &["--timeout", "1000"],
+        &["--max-stack", "100"],
+        &["--max-heap", "1000"],
+    ];
+    for args in options {
+        let output = common::run_with(&[&["--differential"], args].concat(), "print 1;");
+        assert_eq!(output.status.code(), Some(64), "{args:?}");
+        assert!(output.stdout.is_empty());
+        assert!(String::from_utf8_lossy(&output.stderr).starts_with("--differential cannot be combined with"), "{args:?}");
+    }
+}
+
+#[test]
+fn natives_check_their_arity_in_both_engines() {
+    for (source, message) in [("print sqrt();", "Expected 1 arguments but got 0.\n"), ("print clock(1);", "Expected 0 arguments but got 1.\n")] {
+        let output = common::run_with(&["--differential"], source);
+        assert_eq!(output.status.code(), Some(70), "{source}");
+        let stderr = String::from_utf8(output.stderr).unwrap();
+        assert!(stderr.starts_with(message), "{source}: {stderr}");
+    }
+}
+
+#[test]
+fn fuzz() {
+    let iterations: u64 = std::env::var("RSLOX_FUZZ_ITERATIONS").ok().and_then(|n| n.parse().ok()).unwrap_or(100);
+    let seed: u64 = std::env::var("RSLOX_FUZZ_SEED").ok().and_then(|n| n.parse().ok()).unwrap_or(0x5eed);
+    let mut failed = 0;
+    for iteration in 0..iterations {
+        let program_seed = seed.wrapping_add(iteration);
+        let program = Generator::new(program_seed).program();
+        let path = std::env::temp_dir().join(format!("rslox-fuzz-{program_seed}.lox"));
+        std::fs::write(&path, &program).unwrap();
+        let strict = iteration % 2 == 1;
+        match differential(&path, strict) {
+            Ok(())      => std::fs::remove_file(&path).unwrap(),
+            Err(report) => {
+                failed += 1;
+                println!("DIVERGE {}{} (seed {program_seed})\n{report}", path.display(), if strict { " --strict" } else { "" });
+            }
+        }
+    }
+    assert!(failed == 0, "the engines diverge on {failed} of {iterations} programs");
+}
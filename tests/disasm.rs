@@ -0,0 +1,98 @@
+use rslox::chunk::OpCode;
+use rslox::compiler::{Compiler, Options};
+use rslox::debug::{self, Disassembler, Operand};
+use rslox::json::Json;
+use rslox::value::{Function, Value};
+
+use std::rc::Rc;
+
+mod common;
+
+
+fn compile(source: &str) -> Rc<Function> {
+    Compiler::with_options("script", source, Options::default()).compile().expect("the script compiles")
+}
+
+#[test]
+fn instructions_are_decoded() {
+    let script = compile("var a = 1;\nwhile (a < 3) a = a + 1;\n");
+    let instructions: Vec<_> = Disassembler::new(&script.chunk).instructions()
+        .map(|instruction| (instruction.offset, instruction.line, instruction.opcode, instruction.operands))
+        .collect();
+    assert_eq!(instructions[..3], [
+        (0, 1, OpCode::Constant, vec![Operand::Constant(1)]),
+        (2, 1, OpCode::DefineGlobal, vec![Operand::Constant(0)]),
+        (4, 2, OpCode::GetGlobal, vec![Operand::Constant(2)]),
+    ]);
+    let jumps: Vec<_> = instructions.iter()
+        .filter(|(_, _, opcode, _)| matches!(opcode, OpCode::JumpIfFalse | OpCode::Loop))
+        .map(|(offset, _, opcode, operands)| (*offset, *opcode, operands.clone()))
+        .collect();
+    let (exit, back) = (jumps[0].0, jumps[1].0);
+    assert_eq!(jumps[1].2, [Operand::Jump(4)]);
+    assert_eq!(jumps[0].2, [Operand::Jump(back + 3)]);
+    assert!(exit < back);
+}
+
+#[test]
+fn listing_is_written_to_a_string() {
+    let script = compile("fun f() { var x = 1; fun g() { return x; } fun h() {} h(); return g; }\n");
+    let Some(Value::Function(f)) = script.chunk.constants.iter().find(|constant| matches!(constant, Value::Function(_))) else {
+        panic!("no function among the constants");
+    };
+    let mut listing = String::new();
+    Disassembler::new(&f.chunk).disassemble_chunk("f", &mut listing).unwrap();
+    assert_eq!(listing, "\
+== f ==
+0000    1 CONSTANT            0 '1'
+0002    | CLOSURE             1 '<fn g>'
+0004    |                       local 1
+0006    | CLOSURE             2 '<fn h>'
+0008    | GET LOCAL           3
+0010    | CALL                0
+0012    | POP
+0013    | GET LOCAL           2
+0015    | RETURN
+0016    | NIL
+0017    | RETURN
+");
+    let mut line = String::new();
+    assert_eq!(Disassembler::new(&f.chunk).disassemble_instruction(2, &mut line), Ok(6));
+    assert_eq!(line.lines().count(), 2);
+}
+
+#[test]
+fn nested_functions_are_walked() {
+    let script = compile("fun a() { fun b() {} }\nfun c() {}\n");
+    let names: Vec<_> = debug::functions(&script).iter().map(|function| debug::function_name(function).to_string()).collect();
+    assert_eq!(names, ["<script>", "a", "b", "c"]);
+}
+
+#[test]
+fn disasm_command() {
+    let script = common::TempFile::new("lox", "fun f(x) { return x; }\nprint f(1);\n");
+    let rslox = |args: &[&str]| common::rslox().arg("disasm").args(args).arg(script.path()).output().unwrap();
+
+    let text = rslox(&[]);
+    assert!(text.status.success());
+    let text = String::from_utf8(text.stdout).unwrap();
+    assert!(text.starts_with("== <script> ==\n"), "{text}");
+    assert!(text.contains("== f ==\n0000    1 GET LOCAL           1\n"), "{text}");
+
+    let json = rslox(&["--json"]);
+    assert!(json.status.success());
+    let json = Json::parse(&String::from_utf8(json.stdout).unwrap()).unwrap();
+    assert_eq!(json.get("name").and_then(Json::as_str), Some("<script>"));
+    let f = &json.get("functions").and_then(Json::as_array).unwrap()[0];
+    assert_eq!(f.get("name").and_then(Json::as_str), Some("f"));
+    assert_eq!(f.get("arity").and_then(Json::as_f64), Some(1.0));
+    assert_eq!(
+        f.get("code").and_then(Json::as_array).unwrap()[0].to_string(),
+        r#"{"offset":0,"line":1,"opcode":"GetLocal","operands":[{"byte":1}]}"#,
+    );
+
+    script.write("print 1 +;\n");
+    let error = rslox(&[]);
+    assert_eq!(error.status.code(), Some(65));
+    assert!(error.stdout.is_empty());
+}
@@ -0,0 +1,57 @@
+mod common;
+
+
+/// A script with an error in each statement, two of them inside the lists
+/// of parameters and arguments the parser recovers from.
+const SCRIPT: &str = "\
+print 1 +;
+fun f(a, 1) {}
+f(1, +);
+var = 2;
+print \"ok\";
+";
+
+/// The exit code, the error headers and the last line of stderr of running
+/// `SCRIPT` with `args`.
+fn compile_errors(args: &[&str]) -> (Option<i32>, Vec<String>, String) {
+    let output = common::run_with(args, SCRIPT);
+    assert!(output.stdout.is_empty());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    let errors = stderr.lines().filter(|line| line.starts_with("[line ") && line.contains("] Error")).map(str::to_string).collect();
+    (output.status.code(), errors, stderr.lines().last().unwrap_or_default().to_string())
+}
+
+#[test]
+fn every_error_is_reported_after_recovering() {
+    let (code, errors, summary) = compile_errors(&[]);
+    assert_eq!(code, Some(65));
+    assert_eq!(errors, [
+        "[line 1] Error at ';': Expect expression.",
+        "[line 2] Error at '1': Expect parameter name.",
+        "[line 3] Error at '+': Expect expression.",
+        "[line 4] Error at '=': Expect variable name.",
+    ]);
+    assert_eq!(summary, "Compilation failed with 4 errors.");
+}
+
+#[test]
+fn max_errors_caps_the_errors_shown_but_not_counted() {
+    let (code, errors, summary) = compile_errors(&["--max-errors", "2"]);
+    assert_eq!(code, Some(65));
+    assert_eq!(errors, [
+        "[line 1] Error at ';': Expect expression.",
+        "[line 2] Error at '1': Expect parameter name.",
+    ]);
+    assert_eq!(summary, "Compilation failed with 4 errors (showing 2).");
+
+    let (_, errors, summary) = compile_errors(&["--max-errors", "10"]);
+    assert_eq!(errors.len(), 4);
+    assert_eq!(summary, "Compilation failed with 4 errors.");
+}
+
+#[test]
+fn a_single_error_is_not_plural() {
+    let output = common::run_with(&[], "print 1 +;\n");
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.ends_with("\nCompilation failed with 1 error.\n"), "{stderr}");
+}
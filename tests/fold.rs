@@ -0,0 +1,98 @@
+use rslox::chunk::OpCode;
+use rslox::compiler::{Compiler, Options};
+use rslox::debug::Disassembler;
+
+mod common;
+
+
+/// The opcodes of the script compiled from `source`.
+fn opcodes(source: &str, strict: bool) -> Vec<OpCode> {
+    let options = Options { strict, ..Options::default() };
+    let script = Compiler::with_options("script", source, options).compile().expect("the script compiles");
+    Disassembler::new(&script.chunk).instructions().map(|instruction| instruction.opcode).collect()
+}
+
+/// The exit code, stdout and first line of stderr of running `source` with `args`.
+fn run(args: &[&str], source: &str) -> (Option<i32>, String, String) {
+    let output = common::run_with(args, source);
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    let first = stderr.lines().next().unwrap_or_default().to_string();
+    (output.status.code(), String::from_utf8(output.stdout).unwrap(), first)
+}
+
+/// What running `source` printed. Comparing literals is warned about.
+fn output(source: &str) -> String {
+    common::stdout(common::run_with(&["-Wnone"], source))
+}
+
+/// Checks that `print <expression>;` compiles to `folded` followed by a
+/// `Print`, and prints `expected`.
+fn folds_to(expression: &str, folded: &[OpCode], expected: &str) {
+    let code = opcodes(&format!("print {expression};"), false);
+    assert_eq!(code[..code.len() - 3], *folded, "{expression}");
+    assert_eq!(code[code.len() - 3..], [OpCode::Print, OpCode::Nil, OpCode::Return], "{expression}");
+    assert_eq!(output(&format!("print {expression};")), expected, "{expression}");
+}
+
+#[test]
+fn nan_comparisons() {
+    let cases = [
+        ("(0/0) >= 1", "true\n"),
+        ("(0/0) <= 1", "true\n"),
+        ("1 >= (0/0)", "true\n"),
+        ("1 <= (0/0)", "true\n"),
+        ("(0/0) > 1", "false\n"),
+        ("(0/0) < 1", "false\n"),
+        ("(0/0) == (0/0)", "false\n"),
+    ];
+    for (expression, expected) in cases {
+        let folded = opcodes(&format!("print {expression};"), false);
+        let literal = if expected == "true\n" { OpCode::True } else { OpCode::False };
+        assert_eq!(folded, [literal, OpCode::Print, OpCode::Nil, OpCode::Return], "{expression}");
+        assert_eq!(output(&format!("print {expression};")), expected, "{expression}");
+        let unfolded = format!("var nan = 0/0; var one = 1;\nprint {};", expression.replace("(0/0)", "nan").replace('1', "one"));
+        assert_eq!(output(&unfolded), expected, "{unfolded}");
+    }
+}
+
+#[test]
+fn division_by_zero() {
+    folds_to("1 / 0", &[OpCode::Constant], "inf\n");
+    folds_to("-1 / 0", &[OpCode::Constant], "-inf\n");
+    folds_to("0 / 0", &[OpCode::Constant], "NaN\n");
+    for (expression, expected) in [("1 / 0", "inf\n"), ("-1 / 0", "-inf\n"), ("0 / 0", "NaN\n")] {
+        let unfolded = format!("var one = 1; var zero = 0;\nprint {};", expression.replace('1', "one").replace('0', "zero"));
+        assert_eq!(output(&unfolded), expected, "{unfolded}");
+    }
+}
+
+#[test]
+fn string_concatenation() {
+    folds_to("\"a\" + \"b\"", &[OpCode::Constant], "ab\n");
+    folds_to("\"a\" + \"b\" + \"c\"", &[OpCode::Constant], "abc\n");
+    assert_eq!(common::run("var a = \"a\"; var b = \"b\";\nprint a + b;"), "ab\n");
+
+    // Outside of strict mode a string takes any right operand.
+    folds_to("\"a\" + 1", &[OpCode::Constant], "a1\n");
+    assert_eq!(common::run("var a = \"a\"; var one = 1;\nprint a + one;"), "a1\n");
+    assert_eq!(opcodes("print \"a\" + 1;", true), [OpCode::Constant, OpCode::Constant, OpCode::Add, OpCode::Print, OpCode::Nil, OpCode::Return]);
+    let (code, stdout, stderr) = run(&["--strict"], "print \"a\" + 1;");
+    assert_eq!((code, stdout.as_str(), stderr.as_str()), (Some(70), "", "Operands must be two numbers or two strings."));
+}
+
+#[test]
+fn mixed_types_are_left_for_the_runtime_error() {
+    let cases = [
+        ("1 + \"a\"", OpCode::Add, "Operands must be numbers."),
+        ("nil * 2", OpCode::Multiply, "Operands must be numbers."),
+        ("true - 1", OpCode::Subtract, "Operands must be numbers."),
+        ("\"a\" < 1", OpCode::Less, "Operands must be numbers."),
+        ("-\"a\"", OpCode::Negate, "Operand must be a number."),
+    ];
+    for (expression, operator, message) in cases {
+        let code = opcodes(&format!("print {expression};"), false);
+        assert!(code.contains(&operator), "{expression}: {code:?}");
+        let (status, stdout, stderr) = run(&[], &format!("print {expression};"));
+        assert_eq!((status, stdout.as_str(), stderr.as_str()), (Some(70), "", message), "{expression}");
+    }
+}
@@ -0,0 +1,156 @@
+use rslox::ast::{dump, Builder};
+use rslox::format::format;
+
+use std::path::{Path, PathBuf};
+
+mod common;
+
+
+fn formatted(source: &str) -> String {
+    format(source).unwrap_or_else(|diagnostics| panic!("{diagnostics:?}"))
+}
+
+/// The tree of `source` as an S-expression and its comments, which
+/// formatting must keep.
+fn contents(source: &str) -> (String, Vec<String>) {
+    let mut builder = Builder::new(source);
+    builder.keep_comments();
+    let script = builder.build();
+    let comments = builder.comments().iter()
+        .map(|comment| source[comment.offset..comment.offset + comment.len].trim_end().to_string())
+        .collect();
+    (dump::sexpr(&script), comments)
+}
+
+fn lox_files(dir: &Path, files: &mut Vec<PathBuf>) {
+    for entry in std::fs::read_dir(dir).unwrap() {
+        let path = entry.unwrap().path();
+        if path.is_dir() {
+            lox_files(&path, files);
+        } else if path.extension().is_some_and(|extension| extension == "lox") {
+            files.push(path);
+        }
+    }
+}
+
+#[test]
+fn canonical_layout() {
+    let source = "\
+var   a=1 ;
+fun add(x,y){return x+y;}
+fun empty( ) { }
+{ print add( a , -2 ) ; }
+if (a>1) print \"big\"; else if (a < 0) { print \"neg\"; } else print !a;
+for(var i=0;i<2;i=i+1) { print i; }
+for(;;) return;
+while (a and (a or false)) a = a - 1;
+";
+    assert_eq!(formatted(source), "\
+var a = 1;
+fun add(x, y) {
+  return x + y;
+}
+fun empty() {}
+{
+  print add(a, -2);
+}
+if (a > 1) print \"big\";
+else if (a < 0) {
+  print \"neg\";
+} else print !a;
+for (var i = 0; i < 2; i = i + 1) {
+  print i;
+}
+for (;;) return;
+while (a and (a or false)) a = a - 1;
+");
+}
+
+#[test]
+fn dangling_else_is_indented_under_its_if() {
+    assert_eq!(formatted("if (a) if (b) x; else y;"), "if (a) if (b) x;\n  else y;\n");
+    assert_eq!(formatted("if (a) { if (b) x; } else y;"), "if (a) {\n  if (b) x;\n} else y;\n");
+    assert_eq!(formatted("if (a) x; else if (b) y; else z;"), "if (a) x;\nelse if (b) y;\nelse z;\n");
+}
+
+#[test]
+fn keeps_one_blank_line() {
+    let source = "print 1;\n\n\n\nprint 2;\nprint 3;\n{\n\n  print 4;\n\n}\n";
+    assert_eq!(formatted(source), "print 1;\n\nprint 2;\nprint 3;\n{\n  print 4;\n}\n");
+}
+
+#[test]
+fn keeps_comments() {
+    let source = "\
+// Header.
+
+var a = 1;   // Trailing.
+{ // After the brace.
+  print a +
+    // Inside an expression.
+    2;
+  // Before the brace.
+}
+// The end.
+";
+    assert_eq!(formatted(source), "\
+// Header.
+
+var a = 1; // Trailing.
+{ // After the brace.
+  // Inside an expression.
+  print a + 2;
+  // Before the brace.
+}
+// The end.
+");
+}
+
+#[test]
+fn keeps_numbers_and_strings_as_written() {
+    assert_eq!(formatted("print 1.50 + 007;\nprint \"a  // b\";\n"), "print 1.50 + 007;\nprint \"a  // b\";\n");
+}
+
+#[test]
+fn syntax_errors_are_returned() {
+    let diagnostics = format("print 1 +;").unwrap_err();
+    assert_eq!(diagnostics.len(), 1);
+    assert_eq!(diagnostics[0].message, "Expect expression.");
+}
+
+/// Every script of the repository formats to the same tree and comments,
+/// and formatting it again changes nothing.
+#[test]
+fn formatting_keeps_the_tree_and_is_stable() {
+    let mut files = Vec::new();
+    let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+    lox_files(&root.join("tests/lox"), &mut files);
+    lox_files(&root.join("benches/lox"), &mut files);
+    let mut checked = 0;
+    for path in files {
+        let source = std::fs::read_to_string(&path).unwrap();
+        let Ok(once) = format(&source) else {
+            continue;
+        };
+        assert_eq!(contents(&once), contents(&source), "{}", path.display());
+        assert_eq!(formatted(&once), once, "{}", path.display());
+        checked += 1;
+    }
+    assert!(checked > 50, "only {checked} scripts formatted");
+}
+
+#[test]
+fn check_and_write() {
+    let script = common::TempFile::new("lox", "print  1;");
+    let rslox = |args: &[&str]| common::rslox().arg("fmt").args(args).arg(script.path()).output().unwrap();
+
+    let check = rslox(&["--check"]);
+    assert_eq!(check.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&check.stderr).contains("is not formatted."));
+    assert_eq!(script.read(), "print  1;");
+
+    assert!(rslox(&["--write"]).status.success());
+    assert_eq!(script.read(), "print 1;\n");
+    let check = rslox(&["--check"]);
+    assert!(check.status.success() && check.stderr.is_empty());
+}
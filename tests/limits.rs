@@ -0,0 +1,100 @@
+use rslox::vm::{InterpretResult, VM};
+
+use std::sync::atomic::Ordering;
+use std::time::Duration;
+
+mod common;
+
+
+const FOREVER: &str = "var i = 0;\nwhile (true) i = i + 1;\n";
+const LOOP: &str = "var i = 0;\nwhile (i < 5000) i = i + 1;\n";
+
+#[test]
+fn instruction_limit() {
+    let (code, stderr) = common::failure(&["--max-instructions", "100"], FOREVER);
+    assert_eq!(code, Some(70));
+    assert!(stderr.starts_with("Instruction limit exceeded.\n"), "{stderr}");
+    assert!(stderr.ends_with("[line 2] in script\n"), "{stderr}");
+    assert_eq!(common::run_with(&["--max-instructions", "100000"], LOOP).status.code(), Some(0));
+}
+
+#[test]
+fn timeout() {
+    let (code, stderr) = common::failure(&["--timeout", "50"], FOREVER);
+    assert_eq!(code, Some(70));
+    assert!(stderr.starts_with("Deadline exceeded.\n"), "{stderr}");
+}
+
+#[test]
+fn interrupt_stops_the_running_script_only() {
+    let mut vm = VM::new();
+    let interrupt = vm.interrupt_handle();
+
+    interrupt.store(true, Ordering::Relaxed);
+    assert_eq!(vm.interpret(LOOP), InterpretResult::Ok);
+
+    let setter = std::thread::spawn(move || {
+        std::thread::sleep(Duration::from_millis(20));
+        interrupt.store(true, Ordering::Relaxed);
+    });
+    assert_eq!(vm.interpret(FOREVER), InterpretResult::Interrupted);
+    setter.join().unwrap();
+    assert!(!vm.interrupt_handle().load(Ordering::Relaxed));
+    assert_eq!(vm.interpret(LOOP), InterpretResult::Ok);
+}
+
+const RECURSION: &str = "\
+fun f(n) {
+  return 1 + f(n + 1);
+}
+f(0);
+";
+
+#[test]
+fn frame_limit() {
+    let (code, stderr) = common::failure(&["--max-frames", "20"], RECURSION);
+    assert_eq!(code, Some(70));
+    assert!(stderr.starts_with("Stack overflow.\n"), "{stderr}");
+    assert!(stderr.ends_with("\n[line 2] in f (x 19)\n[line 4] in script\n"), "{stderr}");
+}
+
+#[test]
+fn stack_limit() {
+    let (code, stderr) = common::failure(&["--max-stack", "30"], RECURSION);
+    assert_eq!(code, Some(70));
+    assert!(stderr.starts_with("Stack overflow.\n"), "{stderr}");
+    assert!(stderr.ends_with("\n[line 2] in f (x 10)\n[line 4] in script\n"), "{stderr}");
+}
+
+#[test]
+fn only_frames_at_the_same_line_are_folded() {
+    let (code, stderr) = common::failure(&[], "\
+fun f(n) {
+  if (n == 0) return nil + 1;
+  return 1 + f(n - 1);
+}
+fun g() { print f(3); }
+g();
+");
+    assert_eq!(code, Some(70));
+    assert!(stderr.ends_with("\n[line 2] in f\n[line 3] in f (x 3)\n[line 5] in g\n[line 6] in script\n"), "{stderr}");
+}
+
+#[test]
+fn heap_limit() {
+    let source = "var s = \"x\";\nwhile (true) s = s + s;\n";
+    let (code, stderr) = common::failure(&["--max-heap", "100000"], source);
+    assert_eq!(code, Some(70));
+    assert!(stderr.starts_with("Out of memory.\n"), "{stderr}");
+    assert!(stderr.ends_with("\n[line 2] in script\n"), "{stderr}");
+
+    let source = "var s = \"\";\nfor (var i = 0; i < 100; i = i + 1) s = s + \"x\";\nprint s == s;\n";
+    assert_eq!(common::stdout(common::run_with(&["--max-heap", "100000", "-Wnone"], source)), "true\n");
+}
+
+#[test]
+fn frame_limit_too_deep_for_the_tree_walker() {
+    let (code, stderr) = common::failure(&["--tree-walk", "--max-frames", "100000000000"], "print 1;");
+    assert_eq!(code, Some(64));
+    assert!(stderr.starts_with("Could not reserve the stack of the tree-walking interpreter for --max-frames 100000000000: "), "{stderr}");
+}
@@ -0,0 +1,155 @@
+use rslox::compiler::{Compiler, Options, Warnings};
+use rslox::json::Json;
+use rslox::lint::{lint, Config, Rule};
+
+mod common;
+
+
+/// The rule, line and message of every lint `config` finds in `source`.
+fn lints_with(source: &str, config: &Config) -> Vec<(Rule, usize, String)> {
+    let options = Options { warnings: Warnings::none(), ..Default::default() };
+    let mut compiler = Compiler::with_options("script", source, options);
+    let script = compiler.analyze().expect("the script compiles");
+    lint(&script, config).into_iter().map(|lint| (lint.rule, lint.token.line, lint.message)).collect()
+}
+
+fn lints(source: &str) -> Vec<(Rule, usize, String)> {
+    lints_with(source, &Config::default())
+}
+
+#[test]
+fn arity_of_known_functions() {
+    let found = lints("
+        fun add(a, b) { return a + b; }
+        print add(1);
+        print add(1, 2);
+        print clock(1);
+        var f = add;
+        f = clock;
+        print f(1);
+        fun twice(x) {}
+        fun twice(x, y) {}
+        twice(1);
+    ");
+    assert_eq!(found, [
+        (Rule::ArityMismatch, 3, "'add' takes 2 arguments but is called with 1.".to_string()),
+        (Rule::ArityMismatch, 5, "'clock' takes 0 arguments but is called with 1.".to_string()),
+    ]);
+}
+
+#[test]
+fn reassigned_functions_have_no_known_arity() {
+    assert_eq!(lints("fun f() {}\nf = clock;\nf(1);\n"), []);
+    assert_eq!(lints("{\n  fun g(a) {}\n  g = nil;\n  g();\n}\n"), []);
+}
+
+#[test]
+fn globals_used_before_their_definition() {
+    let found = lints("
+        print a;
+        var a = 1;
+        fun later() { return b + missing; }
+        var b = 2;
+        { print c; }
+        var c = 3;
+    ");
+    assert_eq!(found, [
+        (Rule::UseBeforeDefinition, 2, "Global 'a' is used before it is defined.".to_string()),
+        (Rule::UseBeforeDefinition, 4, "Global 'missing' is never defined.".to_string()),
+        (Rule::UseBeforeDefinition, 6, "Global 'c' is used before it is defined.".to_string()),
+    ]);
+}
+
+#[test]
+fn constant_nil_comparisons_in_conditions() {
+    let found = lints("
+        fun f() {}
+        var x;
+        if (f != nil) print 1;
+        while (!(\"s\" == nil)) {}
+        for (; x == nil and 1 + 2 != nil;) {}
+        print f == nil;
+        if (x == nil) print 2;
+    ");
+    assert_eq!(found, [
+        (Rule::ConstantNilComparison, 4, "This comparison is always true, the operand compared with nil is never nil.".to_string()),
+        (Rule::ConstantNilComparison, 5, "This comparison is always false, the operand compared with nil is never nil.".to_string()),
+        (Rule::ConstantNilComparison, 6, "This comparison is always true, the operand compared with nil is never nil.".to_string()),
+    ]);
+}
+
+#[test]
+fn deep_nesting_is_reported_once() {
+    let source = "
+        fun f() {
+          if (true) {
+            while (false) {
+              for (;;) {
+                if (true) {
+                  if (false) {}
+                }
+              }
+            }
+          } else if (false) { print 1; } else if (true) { print 2; }
+        }
+    ";
+    assert_eq!(lints(source), [(Rule::DeepNesting, 6, "Nesting deeper than 4 levels.".to_string())]);
+    let config = Config::parse("max-depth = 5").unwrap();
+    assert_eq!(lints_with(source, &config), [(Rule::DeepNesting, 7, "Nesting deeper than 5 levels.".to_string())]);
+}
+
+#[test]
+fn duplicate_branches() {
+    let found = lints("
+        var x = 1;
+        if (x > 1) { print 1; } else { print 1; }
+        if (x > 1) print 1;
+        else if (x < 1) print 2;
+        else if (x == 0) print 1;
+        else print 3;
+        if (x > 1) print 1; else print 2;
+    ");
+    assert_eq!(found, [
+        (Rule::DuplicateBranches, 3, "This branch is the same as the one on line 3.".to_string()),
+        (Rule::DuplicateBranches, 6, "This branch is the same as the one on line 4.".to_string()),
+    ]);
+}
+
+#[test]
+fn config_turns_rules_off() {
+    let config = Config::parse("# No arity checks.\narity-mismatch = off\n\nduplicate-branches = on # Stays on.\n").unwrap();
+    assert!(!config.is_enabled(Rule::ArityMismatch));
+    assert!(config.is_enabled(Rule::DuplicateBranches));
+    assert_eq!(lints_with("fun f() {}\nf(1);\n", &config), []);
+}
+
+#[test]
+fn config_errors() {
+    assert_eq!(
+        Config::parse("arity-mismatch = off\nno-such-rule = on").unwrap_err(),
+        "line 2: Unknown rule 'no-such-rule', expected one of: arity-mismatch, use-before-definition, \
+         constant-nil-comparison, deep-nesting, duplicate-branches.",
+    );
+    assert_eq!(Config::parse("deep-nesting").unwrap_err(), "line 1: Expect 'key = value', found 'deep-nesting'.");
+    assert_eq!(Config::parse("max-depth = deep").unwrap_err(), "line 1: Expect a number for 'max-depth', found 'deep'.");
+    assert_eq!(Config::parse("deep-nesting = yes").unwrap_err(), "line 1: Expect 'on' or 'off' for 'deep-nesting', found 'yes'.");
+}
+
+#[test]
+fn json_output_and_config_file() {
+    let script = common::TempFile::new("lox", "fun f(a) {}\nf();\nprint g;\n");
+    let config = common::TempFile::new("conf", "use-before-definition = off\n");
+    let output = common::rslox()
+        .args(["lint", "--json", "--config"])
+        .arg(config.path())
+        .arg(script.path())
+        .output()
+        .unwrap();
+
+    assert_eq!(output.status.code(), Some(1));
+    let found = Json::parse(&String::from_utf8(output.stdout).unwrap()).unwrap();
+    assert_eq!(found.to_string(), format!(
+        r#"[{{"file":"{}","line":2,"column":1,"rule":"arity-mismatch","message":"'f' takes 1 argument but is called with 0."}}]"#,
+        script.path().display(),
+    ));
+}
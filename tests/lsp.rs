@@ -0,0 +1,229 @@
+use rslox::json::Json;
+use rslox::lsp::Server;
+
+use std::io::{Read, Write};
+use std::process::Stdio;
+
+mod common;
+
+
+const URI: &str = "file:///test.lox";
+
+const SOURCE: &str = "\
+var total = 0;
+fun add(a, b) {
+  var sum = a + b;
+  fun inner() { return sum; }
+  return inner();
+}
+total = add(1, 2) + sqrt(4);
+";
+
+fn message(text: &str) -> Json {
+    Json::parse(text).unwrap()
+}
+
+/// A server with `source` open, and the diagnostics it published for it.
+fn open(source: &str) -> (Server, Json) {
+    let mut server = Server::new();
+    server.handle(&message(r#"{"jsonrpc":"2.0","id":0,"method":"initialize","params":{}}"#));
+    let open = Json::object([
+        ("jsonrpc", Json::from("2.0")),
+        ("method", Json::from("textDocument/didOpen")),
+        ("params", Json::object([("textDocument", Json::object([
+            ("uri", Json::from(URI)),
+            ("languageId", Json::from("lox")),
+            ("version", Json::from(1)),
+            ("text", Json::from(source)),
+        ]))])),
+    ]);
+    let mut replies = server.handle(&open);
+    assert_eq!(replies.len(), 1);
+    (server, replies.remove(0))
+}
+
+/// The result of the request `method` at `line` and `character`.
+fn request(server: &mut Server, method: &str, line: usize, character: usize) -> Json {
+    let text = format!(
+        r#"{{"jsonrpc":"2.0","id":7,"method":"{method}","params":{{"textDocument":{{"uri":"{URI}"}},"position":{{"line":{line},"character":{character}}}}}}}"#
+    );
+    let mut replies = server.handle(&message(&text));
+    assert_eq!(replies.len(), 1);
+    let reply = replies.remove(0);
+    assert_eq!(reply.get("id"), Some(&Json::from(7)), "{reply}");
+    reply.get("result").cloned().unwrap_or_else(|| panic!("{reply}"))
+}
+
+fn range(start: (usize, usize), end: (usize, usize)) -> String {
+    format!(
+        r#"{{"start":{{"line":{},"character":{}}},"end":{{"line":{},"character":{}}}}}"#,
+        start.0, start.1, end.0, end.1,
+    )
+}
+
+#[test]
+fn initialize_lists_the_capabilities() {
+    let mut server = Server::new();
+    let replies = server.handle(&message(r#"{"jsonrpc":"2.0","id":1,"method":"initialize","params":{}}"#));
+    let capabilities = replies[0].get("result").and_then(|result| result.get("capabilities")).unwrap();
+    for capability in ["definitionProvider", "hoverProvider", "documentSymbolProvider"] {
+        assert_eq!(capabilities.get(capability), Some(&Json::Bool(true)), "{capability}");
+    }
+    assert_eq!(capabilities.get("textDocumentSync"), Some(&Json::from(1)));
+    assert!(capabilities.get("completionProvider").is_some());
+}
+
+#[test]
+fn publishes_errors_and_warnings() {
+    let (_, published) = open("fun f() {\n  var unused = 1;\n}\nprint 1 +;\n");
+    assert_eq!(published.get("method").and_then(Json::as_str), Some("textDocument/publishDiagnostics"));
+    let diagnostics = published.get("params").and_then(|params| params.get("diagnostics")).unwrap();
+    assert_eq!(diagnostics.to_string(), format!(
+        r#"[{{"range":{},"severity":2,"source":"rslox","message":"Unused variable 'unused'."}},{{"range":{},"severity":1,"source":"rslox","message":"Expect expression."}}]"#,
+        range((1, 6), (1, 12)),
+        range((3, 9), (3, 10)),
+    ));
+}
+
+#[test]
+fn changes_replace_the_document() {
+    let (mut server, _) = open("print 1 +;");
+    let change = message(&format!(
+        r#"{{"jsonrpc":"2.0","method":"textDocument/didChange","params":{{"textDocument":{{"uri":"{URI}","version":2}},"contentChanges":[{{"text":"print 1;"}}]}}}}"#
+    ));
+    let replies = server.handle(&change);
+    let diagnostics = replies[0].get("params").and_then(|params| params.get("diagnostics")).unwrap();
+    assert_eq!(diagnostics, &Json::Array(Vec::new()));
+}
+
+#[test]
+fn definition_of_locals_upvalues_and_globals() {
+    let (mut server, _) = open(SOURCE);
+    let location = |range: String| format!(r#"{{"uri":"{URI}","range":{range}}}"#);
+    // `a` in `a + b` is the parameter.
+    let result = request(&mut server, "textDocument/definition", 2, 12);
+    assert_eq!(result.to_string(), location(range((1, 8), (1, 9))));
+    // `sum` in `inner` is the local of `add`.
+    let result = request(&mut server, "textDocument/definition", 3, 24);
+    assert_eq!(result.to_string(), location(range((2, 6), (2, 9))));
+    // `total` and `add` are globals.
+    let result = request(&mut server, "textDocument/definition", 6, 1);
+    assert_eq!(result.to_string(), location(range((0, 4), (0, 9))));
+    let result = request(&mut server, "textDocument/definition", 6, 9);
+    assert_eq!(result.to_string(), location(range((1, 4), (1, 7))));
+    // Natives have no definition in the source.
+    let result = request(&mut server, "textDocument/definition", 6, 21);
+    assert_eq!(result, Json::Null);
+}
+
+#[test]
+fn definition_of_a_shadowing_local() {
+    let (mut server, _) = open("var x = 1;\n{\n  var x = x;\n  print x;\n}\n");
+    // The initializer still refers to the global.
+    let result = request(&mut server, "textDocument/definition", 2, 10);
+    assert_eq!(result.get("range").unwrap().to_string(), range((0, 4), (0, 5)));
+    let result = request(&mut server, "textDocument/definition", 3, 8);
+    assert_eq!(result.get("range").unwrap().to_string(), range((2, 6), (2, 7)));
+}
+
+#[test]
+fn hover_shows_the_arity_of_functions() {
+    let (mut server, _) = open(SOURCE);
+    let hover = |result: Json| result.get("contents").and_then(|contents| contents.get("value")).unwrap().clone();
+    let result = request(&mut server, "textDocument/hover", 6, 10);
+    assert_eq!(hover(result), Json::from("```lox\nfun add(a, b)\n```\nTakes 2 arguments."));
+    let result = request(&mut server, "textDocument/hover", 6, 22);
+    assert_eq!(hover(result), Json::from("```lox\nfun sqrt\n```\nNative function. Takes 1 argument."));
+    let result = request(&mut server, "textDocument/hover", 2, 7);
+    assert_eq!(hover(result), Json::from("```lox\nvar sum\n```\nLocal variable."));
+    let result = request(&mut server, "textDocument/hover", 6, 15);
+    assert_eq!(result, Json::Null);
+}
+
+#[test]
+fn document_symbols_are_the_functions() {
+    let (mut server, _) = open(SOURCE);
+    let result = request(&mut server, "textDocument/documentSymbol", 0, 0);
+    let [add] = result.as_array().unwrap() else {
+        panic!("{result}");
+    };
+    assert_eq!(add.get("name"), Some(&Json::from("add")));
+    assert_eq!(add.get("detail"), Some(&Json::from("fun add(a, b)")));
+    assert_eq!(add.get("range").unwrap().to_string(), range((1, 0), (5, 1)));
+    assert_eq!(add.get("selectionRange").unwrap().to_string(), range((1, 4), (1, 7)));
+    let children = add.get("children").and_then(Json::as_array).unwrap();
+    assert_eq!(children.len(), 1);
+    assert_eq!(children[0].get("name"), Some(&Json::from("inner")));
+}
+
+#[test]
+fn completion_lists_the_names_in_scope() {
+    let (mut server, _) = open(SOURCE);
+    let labels = |result: Json| -> Vec<String> {
+        result.as_array().unwrap().iter()
+            .map(|item| item.get("label").and_then(Json::as_str).unwrap().to_string())
+            .collect()
+    };
+    // Inside `inner`, after `sum` is declared.
+    let result = request(&mut server, "textDocument/completion", 3, 16);
+    assert_eq!(labels(result), ["clock", "sqrt", "total", "add", "a", "b", "sum", "inner"]);
+    // At the top level the locals of `add` are out of scope.
+    let result = request(&mut server, "textDocument/completion", 6, 0);
+    assert_eq!(labels(result), ["clock", "sqrt", "total", "add"]);
+}
+
+#[test]
+fn positions_count_utf16_code_units() {
+    let (mut server, _) = open("var s = \"😀\"; var t = s;\n");
+    let result = request(&mut server, "textDocument/definition", 0, 23);
+    assert_eq!(result.get("range").unwrap().to_string(), range((0, 4), (0, 5)));
+    let result = request(&mut server, "textDocument/hover", 0, 18);
+    assert!(result.to_string().contains("var t"), "{result}");
+}
+
+#[test]
+fn unknown_requests_are_errors() {
+    let mut server = Server::new();
+    let replies = server.handle(&message(r#"{"jsonrpc":"2.0","id":3,"method":"textDocument/rename","params":{}}"#));
+    let code = replies[0].get("error").and_then(|error| error.get("code")).unwrap();
+    assert_eq!(code, &Json::Number(-32601.0));
+}
+
+fn frame(body: &str) -> String {
+    format!("Content-Length: {}\r\n\r\n{body}", body.len())
+}
+
+#[test]
+fn serves_over_stdio() {
+    let mut child = common::rslox()
+        .arg("lsp")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let input = [
+        r#"{"jsonrpc":"2.0","id":1,"method":"initialize","params":{}}"#,
+        r#"{"jsonrpc":"2.0","method":"initialized","params":{}}"#,
+        r#"{"jsonrpc":"2.0","method":"textDocument/didOpen","params":{"textDocument":{"uri":"file:///a.lox","languageId":"lox","version":1,"text":"print 1 +;"}}}"#,
+        r#"{"jsonrpc":"2.0","id":2,"method":"shutdown"}"#,
+        r#"{"jsonrpc":"2.0","method":"exit"}"#,
+    ].map(frame).concat();
+    child.stdin.take().unwrap().write_all(input.as_bytes()).unwrap();
+    let mut output = String::new();
+    child.stdout.take().unwrap().read_to_string(&mut output).unwrap();
+    assert!(child.wait().unwrap().success());
+
+    let mut bodies = Vec::new();
+    let mut rest = output.as_str();
+    while let Some(header_end) = rest.find("\r\n\r\n") {
+        let length: usize = rest[..header_end].trim_start_matches("Content-Length: ").parse().unwrap();
+        let body = &rest[header_end + 4..header_end + 4 + length];
+        bodies.push(message(body));
+        rest = &rest[header_end + 4 + length..];
+    }
+    assert_eq!(bodies.len(), 3, "{output}");
+    assert_eq!(bodies[0].get("id"), Some(&Json::from(1)));
+    let diagnostics = bodies[1].get("params").and_then(|params| params.get("diagnostics")).unwrap();
+    assert_eq!(diagnostics.as_array().map(<[Json]>::len), Some(1), "{diagnostics}");
+    assert_eq!(bodies[2].to_string(), r#"{"jsonrpc":"2.0","id":2,"result":null}"#);
+}
@@ -0,0 +1,165 @@
+//! Checks `-O`: every script behaves the same with and without it, and each
+//! rewrite of the peephole optimizer shows up in `rslox disasm -O`.
+
+use rslox::chunk::{Chunk, OpCode};
+use rslox::compiler::peephole;
+use rslox::diagnostic::Span;
+use rslox::json::Json;
+
+use std::path::{Path, PathBuf};
+
+mod common;
+
+
+fn lox_files(dir: &Path, files: &mut Vec<PathBuf>) {
+    for entry in std::fs::read_dir(dir).unwrap() {
+        let path = entry.unwrap().path();
+        if path.is_dir() {
+            lox_files(&path, files);
+        } else if path.extension().is_some_and(|extension| extension == "lox") {
+            files.push(path);
+        }
+    }
+}
+
+/// `function` and the functions nested in it, in the order `rslox disasm` lists them.
+fn flatten(function: &Json, found: &mut Vec<Json>) {
+    found.push(function.clone());
+    for nested in function.get("functions").and_then(Json::as_array).unwrap() {
+        flatten(nested, found);
+    }
+}
+
+/// The functions `rslox disasm --json` lists for `source`, the script first.
+fn functions(args: &[&str], source: &str) -> Vec<Json> {
+    let output = common::stdout(common::run_with(&[&["disasm", "--json"], args].concat(), source));
+    let mut found = Vec::new();
+    flatten(&Json::parse(&output).unwrap(), &mut found);
+    found
+}
+
+/// The offset, opcode and jump target of every instruction of `function`.
+fn code(function: &Json) -> Vec<(usize, String, Option<usize>)> {
+    let number = |json: Option<&Json>| json.and_then(Json::as_f64).map(|number| number as usize);
+    function.get("code").and_then(Json::as_array).unwrap().iter()
+        .map(|instruction| {
+            let operands = instruction.get("operands").and_then(Json::as_array).unwrap();
+            (
+                number(instruction.get("offset")).unwrap(),
+                instruction.get("opcode").and_then(Json::as_str).unwrap().to_string(),
+                operands.first().and_then(|operand| number(operand.get("jump"))),
+            )
+        })
+        .collect()
+}
+
+fn opcodes(function: &Json) -> Vec<String> {
+    code(function).into_iter().map(|(_, opcode, _)| opcode).collect()
+}
+
+#[test]
+fn optimized_scripts_behave_the_same() {
+    let mut files = Vec::new();
+    lox_files(&Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/lox"), &mut files);
+    files.sort();
+    assert!(files.len() > 50, "only {} scripts", files.len());
+    for path in &files {
+        for mode in [&["-Wnone"][..], &["-Wnone", "--strict"]] {
+            let run = |optimize: &[&str]| common::rslox().args(mode).args(optimize).arg(path).output().unwrap();
+            let (plain, optimized) = (run(&[]), run(&["-O"]));
+            assert_eq!(plain.status.code(), optimized.status.code(), "{} {mode:?}", path.display());
+            assert_eq!(String::from_utf8_lossy(&plain.stdout), String::from_utf8_lossy(&optimized.stdout), "{} {mode:?}", path.display());
+            assert_eq!(String::from_utf8_lossy(&plain.stderr), String::from_utf8_lossy(&optimized.stderr), "{} {mode:?}", path.display());
+        }
+    }
+}
+
+/// Every jump of the optimized code lands on an instruction.
+#[test]
+fn optimized_jumps_land_on_instructions() {
+    let mut files = Vec::new();
+    lox_files(&Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/lox"), &mut files);
+    for path in &files {
+        let output = common::rslox().args(["disasm", "--json", "-O"]).arg(path).output().unwrap();
+        if !output.status.success() {
+            continue;
+        }
+        let mut found = Vec::new();
+        flatten(&Json::parse(&String::from_utf8(output.stdout).unwrap()).unwrap(), &mut found);
+        for function in &found {
+            let code = code(function);
+            let end = code.last().map_or(0, |(offset, ..)| offset + 1);
+            for (offset, opcode, target) in &code {
+                if let Some(target) = target {
+                    assert!(
+                        *target == end || code.iter().any(|(offset, ..)| offset == target),
+                        "{}: {opcode} at {offset} jumps to {target}", path.display(),
+                    );
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn literals_popped_right_away_are_removed() {
+    let source = "1;\nnil;\ntrue;\n\"s\";\n";
+    assert!(opcodes(&functions(&[], source)[0]).contains(&"Pop".to_string()));
+    assert_eq!(opcodes(&functions(&["-O"], source)[0]), ["Nil", "Return"]);
+}
+
+#[test]
+fn not_and_jump_if_false_become_jump_if_true() {
+    let source = "var x = 1;\nif (!x) print 1; else print 2;\nwhile (!x) x = true;\n";
+    let plain = opcodes(&functions(&[], source)[0]);
+    assert_eq!(plain.iter().filter(|opcode| *opcode == "Not").count(), 2);
+
+    let code = code(&functions(&["-O"], source)[0]);
+    assert!(code.iter().all(|(_, opcode, _)| opcode != "Not" && opcode != "JumpIfFalse"), "{code:?}");
+    let fused: Vec<_> = code.iter().filter(|(_, opcode, _)| opcode == "JumpIfTrue").collect();
+    assert_eq!(fused.len(), 2);
+    for (offset, _, target) in fused {
+        let landing = code.iter().find(|(at, ..)| Some(*at) == *target).unwrap();
+        assert_eq!(landing.1, "Pop", "the jump at {offset} lands on {landing:?}");
+    }
+    assert_eq!(common::run(source), "2\n");
+    assert_eq!(common::stdout(common::run_with(&["-O"], source)), "2\n");
+}
+
+/// `!x and y` leaves the condition on the stack for the `and`, so `Not` stays.
+#[test]
+fn not_stays_when_the_condition_is_kept() {
+    let source = "var x = false;\nprint !x and 2;\n";
+    assert!(opcodes(&functions(&["-O"], source)[0]).contains(&"Not".to_string()));
+    assert_eq!(common::stdout(common::run_with(&["-O"], source)), "2\n");
+}
+
+#[test]
+fn local_plus_constant_becomes_add_local_constant() {
+    let source = "fun f(a) { return a + 1; }\nprint f(2);\n";
+    assert_eq!(opcodes(&functions(&[], source)[1]), ["GetLocal", "Constant", "Add", "Return", "Nil", "Return"]);
+    assert_eq!(opcodes(&functions(&["-O"], source)[1]), ["AddLocalConstant", "Return", "Nil", "Return"]);
+    assert_eq!(common::stdout(common::run_with(&["-O"], source)), "3\n");
+}
+
+/// No script compiles to a jump to the next instruction, so this builds one.
+#[test]
+fn jumps_to_the_next_instruction_are_removed() {
+    let mut chunk = Chunk::new();
+    let span = Span::default();
+    chunk.write(OpCode::True, 1, span);
+    chunk.write(OpCode::JumpIfFalse, 1, span);
+    chunk.write(0, 1, span);
+    chunk.write(4, 1, span);
+    chunk.write(OpCode::Jump, 1, span);
+    chunk.write(0, 1, span);
+    chunk.write(0, 1, span);
+    chunk.write(OpCode::Nil, 2, span);
+    chunk.write(OpCode::Return, 2, span);
+    peephole::optimize(&mut chunk);
+    assert_eq!(
+        chunk.code,
+        [OpCode::True.into(), OpCode::JumpIfFalse.into(), 0, 1, OpCode::Nil.into(), OpCode::Return.into()],
+    );
+    assert_eq!(chunk.lines, [1, 1, 1, 1, 2, 2]);
+}
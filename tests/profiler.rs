@@ -0,0 +1,63 @@
+mod common;
+
+
+const SCRIPT: &str = "\
+fun inner(n) { return n + 1; }
+fun outer(n) { return inner(n) * 2; }
+var i = 0;
+while (i < 3) { outer(i); i = i + 1; }
+";
+
+/// The report on stderr and the collapsed stacks of profiling `SCRIPT` with `args`.
+fn profile(args: &[&str]) -> (String, String) {
+    let stacks = common::TempFile::new("txt", "");
+    let script = common::TempFile::new("lox", SCRIPT);
+    let output = common::rslox()
+        .args(args)
+        .arg("--profile-stacks")
+        .arg(stacks.path())
+        .arg(script.path())
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert!(output.stdout.is_empty());
+    (String::from_utf8(output.stderr).unwrap(), stacks.read())
+}
+
+/// The first columns of the rows in the section of `report` headed by `title`.
+fn section<'a>(report: &'a str, title: &str) -> Vec<Vec<&'a str>> {
+    report.split("\n\n")
+        .find(|section| section.starts_with(title))
+        .unwrap_or_else(|| panic!("no {title} section in {report}"))
+        .lines()
+        .skip(1)
+        .map(|row| row.split_whitespace().take(2).collect())
+        .collect()
+}
+
+#[test]
+fn report_counts_instructions_calls_and_lines() {
+    let (report, _) = profile(&[]);
+    assert!(report.starts_with("== profile ==\n88 instructions in "), "{report}");
+
+    let opcodes = section(&report, "opcode");
+    assert_eq!(opcodes[0], ["GetGlobal", "16"]);
+    assert!(opcodes.contains(&vec!["Call", "6"]), "{report}");
+    let count: u64 = opcodes.iter().map(|row| row[1].parse::<u64>().unwrap()).sum();
+    assert_eq!(count, 88);
+
+    let mut functions = section(&report, "function");
+    functions.sort();
+    assert_eq!(functions, [["inner", "3"], ["outer", "3"], ["script", "1"]]);
+
+    assert_eq!(section(&report, "line")[..2], [["4", "50"], ["2", "20"]]);
+}
+
+#[test]
+fn collapsed_stacks_are_sampled_at_the_interval() {
+    let (_, stacks) = profile(&[]);
+    assert_eq!(stacks, "script 58\nscript;outer 18\nscript;outer;inner 12\n");
+
+    let (_, stacks) = profile(&["--profile-interval", "5"]);
+    assert_eq!(stacks, "script 11\nscript;outer 3\nscript;outer;inner 3\n");
+}
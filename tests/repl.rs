@@ -0,0 +1,102 @@
+use rslox::scanner::{ScanError, Scanner, TokenType};
+
+use std::io::Write;
+use std::process::Stdio;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+mod common;
+
+
+/// The stdout and stderr of the REPL reading `input`, with its history kept
+/// in a directory of its own.
+fn repl(input: &str) -> (String, String) {
+    static HOMES: AtomicUsize = AtomicUsize::new(0);
+    let home = std::env::temp_dir().join(format!("rslox-test-{}-home-{}", std::process::id(), HOMES.fetch_add(1, Ordering::Relaxed)));
+    std::fs::create_dir_all(&home).unwrap();
+    let mut child = common::rslox()
+        .env("HOME", &home)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child.stdin.take().unwrap().write_all(input.as_bytes()).unwrap();
+    let output = child.wait_with_output().unwrap();
+    let _ = std::fs::remove_dir_all(&home);
+    assert!(output.status.success());
+    (String::from_utf8(output.stdout).unwrap(), String::from_utf8(output.stderr).unwrap())
+}
+
+#[test]
+fn error_tokens_tell_their_kind() {
+    let kinds = |source| {
+        let token = Scanner::new(source).scan_token();
+        (token.token_type, token.value)
+    };
+    assert_eq!(kinds("\"open"), (TokenType::Error(ScanError::UnterminatedString), "Unterminated string."));
+    assert_eq!(kinds("@"), (TokenType::Error(ScanError::UnexpectedCharacter), "Unexpected character."));
+}
+
+#[test]
+fn unterminated_strings_and_blocks_wait_for_more_lines() {
+    let (stdout, stderr) = repl("print \"a\nb\";\n{\nprint 1;\n}\n");
+    assert_eq!(stdout, "a\nb\n1\n");
+    assert!(stderr.is_empty(), "{stderr}");
+}
+
+#[test]
+fn other_errors_are_reported_right_away() {
+    let (stdout, stderr) = repl("print 1; @\nprint 2;\n");
+    assert_eq!(stdout, "2\n");
+    assert!(stderr.starts_with("[line 1] Error: Unexpected character.\n"), "{stderr}");
+}
+
+#[test]
+fn expression_statements_are_echoed() {
+    let (stdout, stderr) = repl("1 + 2;\nvar a = \"x\";\na + \"y\"\nprint a;\nfun f() { a; }\nf();\n");
+    assert_eq!(stdout, "3\nxy\nx\nnil\n");
+    assert!(stderr.is_empty(), "{stderr}");
+}
+
+#[test]
+fn only_the_last_expression_may_leave_out_its_semicolon() {
+    let (stdout, stderr) = repl("1 + 2\n{ 3 }\n");
+    assert_eq!(stdout, "3\n");
+    assert!(stderr.starts_with("[line 1] Error at '}': Expect ';' after expression.\n"), "{stderr}");
+}
+
+#[test]
+fn dis_lists_the_code_of_a_global_function() {
+    let (stdout, _) = repl("fun g(a) { return a; }\n:dis g\n:dis clock\n:dis nope\nvar n = 1;\n:dis n\n");
+    assert_eq!(stdout, "\
+== g ==
+0000    1 GET LOCAL           1
+0002    | RETURN
+0003    | NIL
+0004    | RETURN
+'clock' is a native function.
+Undefined variable 'nope'.
+'n' is not a function.
+");
+}
+
+#[test]
+fn globals_load_and_reset() {
+    let file = common::TempFile::new("lox", "var x = 1;\nfun f() { return 2; }\nx;\n");
+    let input = format!(":globals\n:load {}\nf() + x\n:globals\n:reset\n:globals\n", file.path().display());
+    let (stdout, stderr) = repl(&input);
+    let natives = "clock = <native fn>\nsqrt = <native fn>\n";
+    assert_eq!(stdout, format!("{natives}3\nclock = <native fn>\nf = <fn f>\nsqrt = <native fn>\nx = 1\n{natives}"));
+    assert!(stderr.is_empty(), "{stderr}");
+}
+
+#[test]
+fn time_help_and_unknown_commands() {
+    let (stdout, _) = repl(":time 1 + 1\n:help\n:bogus\n:load\n");
+    let mut lines = stdout.lines();
+    assert_eq!(lines.next(), Some("2"));
+    let time = lines.next().unwrap();
+    assert!(time.starts_with('(') && time.ends_with(" ms)"), "{time}");
+    assert_eq!(lines.next(), Some(":dis <fn>       disassemble the global function <fn>"));
+    assert!(stdout.ends_with("Unknown command ':bogus', try :help.\nUnknown command ':load', try :help.\n"), "{stdout}");
+}
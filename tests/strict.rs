@@ -0,0 +1,33 @@
+mod common;
+
+
+/// Without `--strict` zero and the empty string are falsey, and `+` takes a
+/// string on its left with anything on its right, in both engines and with
+/// the operands folded or not.
+#[test]
+fn non_strict_truthiness_and_addition() {
+    let source = "\
+var zero = 0;
+var empty = \"\";
+if (zero) print \"zero\"; else print \"not zero\";
+if (-zero) print \"-0\"; else print \"not -0\";
+if (empty) print \"empty\"; else print \"not empty\";
+print !zero;
+print !\"\";
+print zero or \"or\";
+print empty and \"and\";
+print \"a\" + zero;
+print \"a\" + 1;
+print \"a\" + nil;
+print \"a\" + true;
+print zero + \"a\";
+";
+    let expected = "not zero\nnot -0\nnot empty\ntrue\ntrue\nor\n\na0\na1\nanil\natrue\n";
+    for args in [&[][..], &["-O"], &["--tree-walk"]] {
+        let output = common::run_with(args, source);
+        assert_eq!(output.status.code(), Some(70), "{args:?}");
+        assert_eq!(String::from_utf8(output.stdout).unwrap(), expected, "{args:?}");
+        let stderr = String::from_utf8(output.stderr).unwrap();
+        assert!(stderr.starts_with("Operands must be numbers.\n"), "{args:?}: {stderr}");
+    }
+}
@@ -0,0 +1,60 @@
+use rslox::chunk::OpCode;
+use rslox::compiler::{Compiler, Options};
+use rslox::debug::{self, Disassembler, Operand};
+
+mod common;
+
+
+const COUNT: &str = "\
+fun count(n) {
+  if (n == 0) return \"done\";
+  return count(n - 1);
+}
+print count(100000);
+";
+
+#[test]
+fn deep_recursion_stays_under_the_frame_limit() {
+    assert_eq!(common::run(COUNT), "done\n");
+    assert_eq!(common::stdout(common::run_with(&["--max-frames", "8"], COUNT)), "done\n");
+
+    let (code, stderr) = common::failure(&["--max-frames", "8"], &COUNT.replace("return count(n - 1);", "return 0 + count(n - 1);"));
+    assert_eq!(code, Some(70));
+    assert!(stderr.starts_with("Stack overflow.\n"), "{stderr}");
+}
+
+#[test]
+fn jumps_past_a_tail_call_land_on_the_return() {
+    let source = "fun f(a) { return a or g(); }\nfun g() { return 2; }\nprint f(1);\nprint f(false);\n";
+    let script = Compiler::with_options("script", source, Options::default()).compile().expect("the script compiles");
+    let f = debug::functions(&script).into_iter().find(|function| function.name == "f").unwrap();
+    let instructions: Vec<_> = Disassembler::new(&f.chunk).instructions().collect();
+
+    let call = instructions.iter().position(|instruction| instruction.opcode == OpCode::TailCall).expect("a tail call");
+    let ret = &instructions[call + 1];
+    assert_eq!(ret.opcode, OpCode::Return);
+    let jump = instructions.iter().find(|instruction| instruction.opcode == OpCode::Jump).unwrap();
+    assert_eq!(jump.operands, [Operand::Jump(ret.offset)]);
+
+    assert_eq!(common::run(source), "1\n2\n");
+}
+
+#[test]
+fn elided_frames_are_counted_in_traces() {
+    let (code, stderr) = common::failure(&[], "\
+fun count(n) {
+  if (n == 0) return nil + 1;
+  return count(n - 1);
+}
+print count(5);
+");
+    assert_eq!(code, Some(70));
+    assert!(stderr.ends_with("\n[line 2] in count (5 tail calls elided)\n[line 5] in script\n"), "{stderr}");
+
+    let (_, stderr) = common::failure(&[], "\
+fun fail() { return nil + 1; }
+fun f() { return fail(); }
+print f();
+");
+    assert!(stderr.ends_with("\n[line 1] in fail (1 tail call elided)\n[line 3] in script\n"), "{stderr}");
+}
@@ -0,0 +1,101 @@
+use rslox::json::Json;
+use rslox::trace::{Filter, Trace};
+use rslox::vm::{InterpretResult, VM};
+
+use std::cell::RefCell;
+use std::io::{self, Write};
+use std::rc::Rc;
+
+mod common;
+
+
+/// A writer whose bytes stay readable after it is handed to a trace.
+#[derive(Clone, Default)]
+struct Shared(Rc<RefCell<Vec<u8>>>);
+
+impl Write for Shared {
+    fn write(&mut self, bytes: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().write(bytes)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Shared {
+    fn text(&self) -> String {
+        String::from_utf8(self.0.borrow().clone()).unwrap()
+    }
+}
+
+const SCRIPT: &str = "\
+fun add(a, b) {
+  return a + b;
+}
+var x = add(1, 2);
+print x;
+";
+
+#[test]
+fn line_ranges() {
+    assert_eq!(Filter::parse_lines("3-7"), Ok(3..=7));
+    assert_eq!(Filter::parse_lines("4"), Ok(4..=4));
+    assert_eq!(Filter::parse_lines("7-3"), Err("The line range 7-3 is empty.".to_string()));
+    assert_eq!(Filter::parse_lines("a-3"), Err("Expect a line number, found 'a'.".to_string()));
+}
+
+#[test]
+fn traces_the_filtered_function() {
+    let (code, text) = (Shared::default(), Shared::default());
+    let mut trace = Trace::new(Filter { functions: vec!["add".to_string()], lines: None });
+    trace.print_code(Box::new(code.clone()));
+    trace.trace_text(Box::new(text.clone()));
+    let mut vm = VM::new();
+    vm.enable_trace(trace);
+    assert_eq!(vm.interpret(SCRIPT), InterpretResult::Ok);
+    assert!(vm.trace_mut().unwrap().finish().is_ok());
+
+    assert_eq!(code.text(), "\
+== add ==
+0000    2 GET LOCAL           1
+0002    | GET LOCAL           2
+0004    | ADD
+0005    | RETURN
+0006    3 NIL
+0007    | RETURN
+");
+    assert_eq!(text.text(), "          [ <script> ][ <fn add> ][ 1 ][ 2 ]
+0000    2 GET LOCAL           1
+          [ <script> ][ <fn add> ][ 1 ][ 2 ][ 1 ]
+0002    | GET LOCAL           2
+          [ <script> ][ <fn add> ][ 1 ][ 2 ][ 1 ][ 2 ]
+0004    | ADD
+          [ <script> ][ <fn add> ][ 1 ][ 2 ][ 3 ]
+0005    | RETURN
+");
+}
+
+#[test]
+fn json_lines_trace_of_a_line_range() {
+    let trace = common::TempFile::new("jsonl", "");
+    let script = common::TempFile::new("lox", SCRIPT);
+    let output = common::rslox()
+        .args(["--trace-lines", "2-2", "--trace-json"])
+        .arg(trace.path())
+        .arg(script.path())
+        .output()
+        .unwrap();
+    let trace = trace.read();
+
+    assert!(output.status.success());
+    assert_eq!(output.stdout, b"3\n");
+    assert!(output.stderr.is_empty());
+    let instructions: Vec<Json> = trace.lines().map(|line| Json::parse(line).unwrap()).collect();
+    let opcodes: Vec<_> = instructions.iter().map(|instruction| instruction.get("opcode").and_then(Json::as_str).unwrap()).collect();
+    assert_eq!(opcodes, ["GetLocal", "GetLocal", "Add", "Return"]);
+    assert_eq!(
+        instructions[2].to_string(),
+        r#"{"frame":1,"function":"add","ip":4,"line":2,"opcode":"Add","stack":["<script>","<fn add>","1","2","1","2"]}"#,
+    );
+}
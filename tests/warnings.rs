@@ -0,0 +1,85 @@
+mod common;
+
+
+/// A script with one of each warning, which runs without errors.
+const SCRIPT: &str = "\
+fun f(_a) {
+  var b = 1;
+  { var b = 2; print b; }
+  return 1;
+  print 2;
+}
+fun g() { undeclared = 1; }
+var x = 1;
+print x == x;
+";
+
+/// The headers of the warnings reported for `source` with `args`.
+fn warnings(args: &[&str], source: &str) -> Vec<String> {
+    let output = common::run_with(args, source);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    String::from_utf8(output.stderr).unwrap()
+        .lines()
+        .filter(|line| line.contains("] Warning at "))
+        .map(str::to_string)
+        .collect()
+}
+
+const SHADOWING: &str = "[line 3] Warning at 'b': Variable 'b' shadows a variable in an enclosing scope.";
+const UNREACHABLE_CODE: &str = "[line 5] Warning at 'print': Unreachable code.";
+const UNUSED_VARIABLE: &str = "[line 2] Warning at 'b': Unused variable 'b'.";
+const CONSTANT_COMPARISON: &str = "[line 9] Warning at '==': Comparison is always true.";
+const UNDECLARED_GLOBAL: &str = "[line 7] Warning at 'undeclared': Assignment to undeclared global 'undeclared'.";
+
+#[test]
+fn warnings_are_on_by_default() {
+    assert_eq!(warnings(&[], SCRIPT), warnings(&["-Wall"], SCRIPT));
+}
+
+#[test]
+fn each_warning_is_turned_on_by_name() {
+    let expected = [
+        ("unused-variable", UNUSED_VARIABLE),
+        ("unreachable-code", UNREACHABLE_CODE),
+        ("shadowing", SHADOWING),
+        ("undeclared-global", UNDECLARED_GLOBAL),
+        ("constant-comparison", CONSTANT_COMPARISON),
+    ];
+    for (name, warning) in expected {
+        assert_eq!(warnings(&["-Wnone", &format!("-W{name}")], SCRIPT), [warning], "-W{name}");
+    }
+}
+
+#[test]
+fn all_and_none() {
+    assert_eq!(warnings(&["-Wall"], SCRIPT), [
+        SHADOWING,
+        UNREACHABLE_CODE,
+        UNUSED_VARIABLE,
+        UNDECLARED_GLOBAL,
+        CONSTANT_COMPARISON,
+    ]);
+    assert!(warnings(&["-Wnone"], SCRIPT).is_empty());
+    assert!(warnings(&["-Wall", "-Wnone"], SCRIPT).is_empty());
+    assert_eq!(warnings(&["-Wnone", "-Wshadowing"], SCRIPT), [SHADOWING]);
+}
+
+#[test]
+fn warnings_are_turned_off_by_name() {
+    assert_eq!(warnings(&["-Wno-shadowing", "-Wno-unused-variable"], SCRIPT), [
+        UNREACHABLE_CODE,
+        UNDECLARED_GLOBAL,
+        CONSTANT_COMPARISON,
+    ]);
+    assert!(warnings(&["-Wnone", "-Wshadowing", "-Wno-shadowing"], SCRIPT).is_empty());
+}
+
+#[test]
+fn unknown_warnings_are_rejected() {
+    for arg in ["-Wshadow", "-Wno-shadow"] {
+        let output = common::run_with(&[arg], "print 1;");
+        assert_eq!(output.status.code(), Some(64));
+        let stderr = String::from_utf8(output.stderr).unwrap();
+        assert!(stderr.starts_with("Unknown warning 'shadow', expected one of: unused-variable, "), "{stderr}");
+    }
+}